@@ -13,11 +13,13 @@ use gelatin::image::imageops::{
 
 use crate::image_cache::image_loader::{
     complex_load_image, errors::Error, LoadResult, Orientation,
+    NON_EXISTENT_REQUEST_ID,
 };
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum ClipboardState {
-    Pending(PathBuf),
+    /// The file and the page of it to copy
+    Pending(PathBuf, usize),
     Succeeded,
     Failed,
 }
@@ -55,13 +57,13 @@ impl ClipboardHandler {
         }
     }
 
-    pub fn request_copy(&mut self, target: PathBuf) -> bool {
+    pub fn request_copy(&mut self, target: PathBuf, page: usize) -> bool {
         {
             let mut state = self.request_handle.state.lock().unwrap();
             if let ClipboardState::Pending(..) = &*state {
                 return false;
             } else {
-                *state = ClipboardState::Pending(target);
+                *state = ClipboardState::Pending(target, page);
             }
         }
         // Notify the condvar after releasing the mutex
@@ -93,12 +95,16 @@ impl ClipboardHandler {
             eprintln!("The clipboard could not be created, error was: {}", e);
         }
         while request_handle.run_thread.load(Ordering::Acquire) {
-            let request_path;
+            let path;
+            let page;
             {
                 let mut state_guard = request_handle.state.lock().unwrap();
                 'wait_for_request: loop {
-                    if let ClipboardState::Pending(path) = state_guard.clone() {
-                        request_path = path;
+                    if let ClipboardState::Pending(request_path, request_page) =
+                        state_guard.clone()
+                    {
+                        path = request_path;
+                        page = request_page;
                         break 'wait_for_request;
                     } else {
                         if !request_handle.run_thread.load(Ordering::Acquire) {
@@ -118,61 +124,78 @@ impl ClipboardHandler {
                     }
                 }
             }
-            let result = complex_load_image(&request_path, false, 0, |frame| {
-                if let LoadResult::Frame {
-                    mut image,
-                    orientation,
-                    ..
-                } = frame
-                {
-                    if let Ok(clipboard) = &mut clipboard {
-                        // Note: the imageops functions use clockwise rotation whereas the
-                        // `Orientation` type describes counter-clockwise rotation.
-                        image = match orientation {
-                            Orientation::Deg0 => image,
-                            Orientation::Deg0HorFlip => {
-                                flip_horizontal_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg90 => rotate270(&image),
-                            Orientation::Deg90VerFlip => {
-                                let mut result = rotate270(&image);
-                                flip_vertical_in_place(&mut result);
-                                result
-                            }
-                            Orientation::Deg180 => {
-                                rotate180_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg180HorFlip => {
-                                // This is identical to just a vertical flip with no rotation.
-                                flip_vertical_in_place(&mut image);
-                                image
-                            }
-                            Orientation::Deg270 => rotate90(&image),
-                            Orientation::Deg270VerFlip => {
-                                let mut result = rotate90(&image);
-                                flip_vertical_in_place(&mut result);
-                                result
-                            }
-                        };
-                        let (w, h) = image.dimensions();
-                        let cb_image = arboard::ImageData {
-                            width: w as usize,
-                            height: h as usize,
-                            bytes: image.into_raw().into(),
-                        };
-                        if let Err(e) = clipboard.set_image(cb_image) {
-                            eprintln!("Could not set the clipboard image, error was: {e}");
-                        } else {
-                            return Ok(());
+            // No request of the image cache has this id, so it's never
+            // cancelled
+            let id = NON_EXISTENT_REQUEST_ID;
+            let result = complex_load_image(&path, false, id, page, |frame| {
+                let (image, orientation) = match frame {
+                    LoadResult::Frame {
+                        image, orientation, ..
+                    } => (image, orientation),
+                    LoadResult::Tiles { .. } => {
+                        return Err(Error::Msg(
+                            "The image is too large to be copied".to_string(),
+                        ));
+                    }
+                    // Only the pixels are copied, not the page count, the
+                    // loop count or the preview
+                    _ => return Ok(()),
+                };
+                if let Ok(clipboard) = &mut clipboard {
+                    let mut image = image.into_rgba8();
+                    // Note: the imageops functions use clockwise rotation whereas the
+                    // `Orientation` type describes counter-clockwise rotation.
+                    image = match orientation {
+                        Orientation::Deg0 => image,
+                        Orientation::Deg0HorFlip => {
+                            flip_horizontal_in_place(&mut image);
+                            image
                         }
+                        Orientation::Deg90 => rotate270(&image),
+                        Orientation::Deg90VerFlip => {
+                            let mut result = rotate270(&image);
+                            flip_vertical_in_place(&mut result);
+                            result
+                        }
+                        Orientation::Deg180 => {
+                            rotate180_in_place(&mut image);
+                            image
+                        }
+                        Orientation::Deg180HorFlip => {
+                            // This is identical to just a vertical flip with no rotation.
+                            flip_vertical_in_place(&mut image);
+                            image
+                        }
+                        Orientation::Deg270 => rotate90(&image),
+                        Orientation::Deg270VerFlip => {
+                            let mut result = rotate90(&image);
+                            flip_vertical_in_place(&mut result);
+                            result
+                        }
+                    };
+                    let (w, h) = image.dimensions();
+                    let cb_image = arboard::ImageData {
+                        width: w as usize,
+                        height: h as usize,
+                        bytes: image.into_raw().into(),
+                    };
+                    if let Err(e) = clipboard.set_image(cb_image) {
+                        eprintln!(
+                            "Could not set the clipboard image, error was: {e}"
+                        );
+                    } else {
+                        return Ok(());
                     }
                 }
                 Err(Error::Msg(
                     "Could not set the clipboard image.".to_string(),
                 ))
             });
+            if let Err(Error::Msg(message)) = &result {
+                eprintln!(
+                    "Could not copy {path:?} to the clipboard: {message}"
+                );
+            }
             let mut state = request_handle.state.lock().unwrap();
             *state = if result.is_ok() {
                 ClipboardState::Succeeded
@@ -1,26 +1,216 @@
+use std::{ffi::OsString, path::PathBuf, str::FromStr, time::Duration};
+
 use pico_args::Arguments;
 
-use crate::version::Version;
+use crate::{
+    configuration::Theme, image_cache::sort::SortOrder, playlist,
+    version::Version,
+};
+
+/// The window area given with `--geometry`. Either part may be left out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub size: Option<(u32, u32)>,
+    pub position: Option<(i32, i32)>,
+}
 
+impl FromStr for Geometry {
+    type Err = String;
+
+    /// Parses `[WxH][{+-}X{+-}Y]`, for example `800x600+10+20`, `800x600` or
+    /// `+10-20`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid geometry `{s}`, expected WxH+X+Y");
+        let is_sign = |c: char| c == '+' || c == '-';
+        let (size_str, pos_str) =
+            s.split_at(s.find(is_sign).unwrap_or(s.len()));
+        let size = if size_str.is_empty() {
+            None
+        } else {
+            let (w, h) = size_str.split_once('x').ok_or_else(invalid)?;
+            let w: u32 = w.parse().map_err(|_| invalid())?;
+            let h: u32 = h.parse().map_err(|_| invalid())?;
+            if w == 0 || h == 0 {
+                return Err(invalid());
+            }
+            Some((w, h))
+        };
+        let position = if pos_str.is_empty() {
+            None
+        } else {
+            let y_start = pos_str[1..].find(is_sign).ok_or_else(invalid)? + 1;
+            let (x, y) = pos_str.split_at(y_start);
+            let x = x.parse().map_err(|_| invalid())?;
+            let y = y.parse().map_err(|_| invalid())?;
+            Some((x, y))
+        };
+        if size.is_none() && position.is_none() {
+            return Err(invalid());
+        }
+        Ok(Geometry { size, position })
+    }
+}
+
+/// The initial scaling of the image given with `--zoom`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    Fit,
+    Stretch,
+    Orig,
+    Percent(f32),
+}
+
+impl FromStr for Zoom {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fit" => Ok(Zoom::Fit),
+            "stretch" => Ok(Zoom::Stretch),
+            "orig" => Ok(Zoom::Orig),
+            _ => match s.trim_end_matches('%').parse::<f32>() {
+                Ok(percent) if percent > 0.0 && percent.is_finite() => {
+                    Ok(Zoom::Percent(percent))
+                }
+                _ => Err(format!(
+                    "invalid zoom `{s}`, expected `fit`, `stretch`, `orig` or a percentage"
+                )),
+            },
+        }
+    }
+}
+
+/// The options given on the command line. Every option overrides the
+/// corresponding value of the config and the cache for this session only.
 pub struct Args {
-    pub file_path: Option<String>,
+    /// The paths given as positional arguments
+    pub file_paths: Vec<String>,
+    /// A command to send to the running instance instead of opening a window
+    pub remote: Option<String>,
+    pub fullscreen: bool,
+    pub maximized: bool,
+    pub geometry: Option<Geometry>,
+    pub slideshow: bool,
+    /// The time each image is shown for during the slideshow
+    pub slideshow_interval: Option<Duration>,
+    pub random: bool,
+    pub theme: Option<Theme>,
+    /// Use this config file instead of the default one
+    pub config: Option<PathBuf>,
+    pub sort: Option<SortOrder>,
+    pub recursive: bool,
+    /// Show every image that's added to the folder
+    pub follow: bool,
+    pub zoom: Option<Zoom>,
+}
+
+impl Args {
+    /// The first path given on the command line
+    pub fn file_path(&self) -> Option<&str> {
+        self.file_paths.first().map(|p| p.as_str())
+    }
+
+    /// True if any option overrides the window area
+    pub fn overrides_window_area(&self) -> bool {
+        self.fullscreen || self.maximized || self.geometry.is_some()
+    }
 }
 
 const HELP: &str = "\
 Alloy
 USAGE:
-  alloy [OPTIONS] [PATH]
+  alloy [OPTIONS] [PATH]...
 FLAGS:
   -h, --help            Prints help information
   -v, --version         Prints version
+  --fullscreen          Starts in fullscreen mode
+  --maximized           Starts with a maximized window
+  --slideshow[=SECS]    Starts the slideshow, showing every image for SECS
+                        seconds (6 by default)
+  --random              Shows the images of the slideshow in random order.
+                        Starts the slideshow even without `--slideshow`
+  --recursive           Includes the images in subfolders
+  --follow              Shows every image that's added to the folder while
+                        it's open
 OPTIONS:
+  --geometry <GEOMETRY> Sets the size and position of the window, in the
+                        format `WxH+X+Y`. Either part may be left out
+  --theme <THEME>       Uses the `light` or `dark` theme
+  --config <FILE>       Reads the configuration from FILE instead of the
+                        default location
+  --sort <ORDER>        Sets the order in which the images are shown to
+                        `name`, `modified`, `size`, `exif_date`, `dimensions`,
+                        `extension` or `shuffle`, optionally followed by
+                        `_asc` or `_desc`, e.g. `modified_desc`
+  --zoom <ZOOM>         Sets the initial scaling of the image to `fit`,
+                        `stretch`, `orig` or a percentage like `150`
+  --remote <CMD>        Sends CMD to the running instance and prints the
+                        response. CMD is an action name like `img_next`, one of
+                        `jump_to_path <PATH>`, `jump_to_index <INDEX>`,
+                        `zoom <PERCENT>`, `state` and `quit`, or a JSON request.
+                        Requires `enabled = true` in the `[ipc]` config section
 ARGS:
-  <PATH>                The file path of the image
+  <PATH>                The file path of the image. Several paths, a list file
+                        (`.m3u`, `.m3u8` or `.txt`) or `-` to read the paths
+                        from stdin open a playlist
+
+The options only apply to this session, they are never written to the cache.
 ";
 
-/// Parses the command-line arguments and returns the file path
+fn exit_with_usage_error(msg: &str) -> ! {
+    println!("Invalid usage: {msg}\n");
+    print!("{}", HELP);
+    std::process::exit(1);
+}
+
+/// Removes `--slideshow` or `--slideshow=SECS` from `args`. This is done by
+/// hand because the value is optional.
+fn take_slideshow_arg(
+    args: &mut Vec<OsString>,
+) -> Result<(bool, Option<Duration>), String> {
+    let mut slideshow = false;
+    let mut interval = None;
+    let mut error = None;
+    args.retain(|arg| match arg.to_str() {
+        Some("--slideshow") => {
+            slideshow = true;
+            false
+        }
+        Some(arg) if arg.starts_with("--slideshow=") => {
+            slideshow = true;
+            let secs = &arg["--slideshow=".len()..];
+            match secs.parse::<f32>() {
+                Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                    interval = Some(Duration::from_secs_f32(secs));
+                }
+                _ => {
+                    error = Some(format!("invalid slideshow interval `{secs}`"))
+                }
+            }
+            false
+        }
+        _ => true,
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok((slideshow, interval)),
+    }
+}
+
+fn parse_theme(s: &str) -> Result<Theme, String> {
+    match s {
+        "light" => Ok(Theme::Light),
+        "dark" => Ok(Theme::Dark),
+        _ => Err(format!("invalid theme `{s}`, expected `light` or `dark`")),
+    }
+}
+
+/// Parses the command-line arguments
 pub fn parse_args() -> Args {
-    let mut pargs = Arguments::from_env();
+    let mut raw_args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    let (slideshow, slideshow_interval) = take_slideshow_arg(&mut raw_args)
+        .unwrap_or_else(|e| exit_with_usage_error(&e));
+    let mut pargs = Arguments::from_vec(raw_args);
 
     // Help and version flags take precedence
     if pargs.contains(["-h", "--help"]) {
@@ -31,18 +221,57 @@ pub fn parse_args() -> Args {
         std::process::exit(0);
     }
 
-    // TODO: Options
+    let fullscreen = pargs.contains("--fullscreen");
+    let maximized = pargs.contains("--maximized");
+    let random = pargs.contains("--random");
+    let recursive = pargs.contains("--recursive");
+    let follow = pargs.contains("--follow");
 
-    // Get filename
-    match pargs.free_from_str::<String>() {
-        Ok(file_path) if !file_path.starts_with('-') => Args {
-            file_path: Some(file_path),
-        },
-        Ok(_) => {
-            println!("Invalid usage\n");
-            print!("{}", HELP);
-            std::process::exit(1);
+    let options = (|| {
+        Ok::<_, pico_args::Error>((
+            pargs.opt_value_from_str::<_, String>("--remote")?,
+            pargs.opt_value_from_str::<_, Geometry>("--geometry")?,
+            pargs.opt_value_from_fn("--theme", parse_theme)?,
+            pargs.opt_value_from_str::<_, PathBuf>("--config")?,
+            pargs.opt_value_from_str::<_, SortOrder>("--sort")?,
+            pargs.opt_value_from_str::<_, Zoom>("--zoom")?,
+        ))
+    })();
+    let (remote, geometry, theme, config, sort, zoom) =
+        options.unwrap_or_else(|e| exit_with_usage_error(&e.to_string()));
+
+    // Get the file names
+    let mut file_paths = Vec::new();
+    for arg in pargs.finish() {
+        match arg.into_string() {
+            Ok(file_path)
+                if file_path == playlist::STDIN_PATH
+                    || !file_path.starts_with('-') =>
+            {
+                file_paths.push(file_path)
+            }
+            Ok(option) => {
+                exit_with_usage_error(&format!("unknown option `{option}`"))
+            }
+            Err(arg) => exit_with_usage_error(&format!(
+                "the path {arg:?} is not valid unicode"
+            )),
         }
-        Err(_) => Args { file_path: None },
+    }
+    Args {
+        file_paths,
+        remote,
+        fullscreen,
+        maximized,
+        geometry,
+        slideshow,
+        slideshow_interval,
+        random,
+        theme,
+        config,
+        sort,
+        recursive,
+        follow,
+        zoom,
     }
 }
@@ -0,0 +1,130 @@
+use std::{
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+/// The outcome of a user command that was started by the `CommandRunner`
+pub struct CommandResult {
+    pub program: String,
+    /// `Err` if the program couldn't be started or waited on
+    pub status: std::io::Result<ExitStatus>,
+    pub stderr: String,
+    pub reload_after: bool,
+}
+
+impl CommandResult {
+    /// Prints the error output of the command and the reason of the failure if
+    /// the command failed.
+    pub fn report(&self) {
+        let stderr = self.stderr.trim_end();
+        if !stderr.is_empty() {
+            eprintln!("[{}] {}", self.program, stderr);
+        }
+        match &self.status {
+            Ok(status) if status.success() => (),
+            Ok(status) => {
+                eprintln!("The user command '{}' {}", self.program, status)
+            }
+            Err(e) => eprintln!(
+                "Error while executing the user command '{}': {}",
+                self.program, e
+            ),
+        }
+    }
+}
+
+/// Runs user commands without blocking the thread that started them.
+///
+/// Every command is waited on by a thread of its own, which sends the result
+/// back once the command exited. The results can be collected with
+/// `try_get_results`.
+pub struct CommandRunner {
+    sender: Sender<CommandResult>,
+    receiver: Receiver<CommandResult>,
+    running: usize,
+}
+
+impl CommandRunner {
+    pub fn new() -> CommandRunner {
+        let (sender, receiver) = channel();
+        CommandRunner {
+            sender,
+            receiver,
+            running: 0,
+        }
+    }
+
+    /// Starts `cmd`. If `wait` is true, this function only returns after the
+    /// command exited, but the result is still delivered through `try_get_results`
+    pub fn run(&mut self, mut cmd: Command, wait: bool, reload_after: bool) {
+        let program = cmd.get_program().to_string_lossy().into_owned();
+        // `output` would capture the standard output as well unless it's
+        // explicitly inherited. Only the error output is needed for the report.
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped());
+        self.running += 1;
+        if wait {
+            let result = Self::make_result(program, cmd.output(), reload_after);
+            self.sender.send(result).unwrap();
+            return;
+        }
+        match cmd.spawn() {
+            Ok(child) => {
+                let sender = self.sender.clone();
+                std::thread::spawn(move || {
+                    let output = child.wait_with_output();
+                    let result =
+                        Self::make_result(program, output, reload_after);
+                    // The receiver is only gone if the runner was dropped,
+                    // in which case nobody is interested in the result.
+                    let _ = sender.send(result);
+                });
+            }
+            Err(e) => {
+                let result = Self::make_result(program, Err(e), reload_after);
+                self.sender.send(result).unwrap();
+            }
+        }
+    }
+
+    /// Returns true if there's a command that has not finished yet or whose
+    /// result has not been collected yet.
+    pub fn is_busy(&self) -> bool {
+        self.running > 0
+    }
+
+    /// Returns the results of all commands that finished since the last call.
+    pub fn try_get_results(&mut self) -> Vec<CommandResult> {
+        let results = self.receiver.try_iter().collect::<Vec<_>>();
+        self.running -= results.len();
+        results
+    }
+
+    fn make_result(
+        program: String,
+        output: std::io::Result<Output>,
+        reload_after: bool,
+    ) -> CommandResult {
+        match output {
+            Ok(output) => CommandResult {
+                program,
+                status: Ok(output.status),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                reload_after,
+            },
+            Err(e) => CommandResult {
+                program,
+                status: Err(e),
+                stderr: String::new(),
+                reload_after,
+            },
+        }
+    }
+}
+
+impl Default for CommandRunner {
+    fn default() -> Self {
+        CommandRunner::new()
+    }
+}
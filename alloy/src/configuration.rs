@@ -38,15 +38,54 @@ pub enum Antialias {
     Never,
 }
 
+/// How the brightness of HDR images is mapped to the range of the display
+#[derive(
+    Copy, Clone, Eq, PartialEq, Debug, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMap {
+    /// Everything brighter than the display is clipped
+    Clamp,
+    Reinhard,
+    /// The filmic curve of the Academy Color Encoding System
+    #[default]
+    Aces,
+}
+
+impl ToneMap {
+    pub fn next(self) -> ToneMap {
+        match self {
+            ToneMap::Clamp => ToneMap::Reinhard,
+            ToneMap::Reinhard => ToneMap::Aces,
+            ToneMap::Aces => ToneMap::Clamp,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "Clamp",
+            ToneMap::Reinhard => "Reinhard",
+            ToneMap::Aces => "ACES",
+        }
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CacheImageSection {
     pub fit_stretches: bool,
     pub antialiasing: Antialias,
+    #[serde(default)]
+    pub tone_map: ToneMap,
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
 pub struct ConfigImageSection {
     pub antialiasing: Option<String>,
+    /// The ICC profile of the display, images are converted to sRGB otherwise
+    pub display_profile: Option<String>,
+    /// Keep the tiles of very large images in the cache folder, so that they
+    /// open faster the next time
+    pub keep_tiles: Option<bool>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -75,12 +114,36 @@ pub struct ConfigWindowSection {
     pub start_fullscreen: Option<bool>,
     pub start_maximized: Option<bool>,
     pub show_bottom_bar: Option<bool>,
+    /// Show the thumbnails of the neighbouring images above the bottom bar
+    pub show_filmstrip: Option<bool>,
     pub theme: Option<Theme>,
     pub use_last_window_area: Option<bool>,
     pub win_w: Option<u32>,
     pub win_h: Option<u32>,
     pub win_x: Option<i32>,
     pub win_y: Option<i32>,
+    /// Open files in the already running instance instead of starting a new
+    /// one. Only supported on Unix-like systems.
+    pub single_instance: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ConfigFolderSection {
+    /// Browse the images in the subfolders as well
+    pub recursive: Option<bool>,
+    /// The order of the images, for example `modified_desc`. See `--help`
+    /// for the possible values.
+    pub sort: Option<String>,
+    /// Update the images when the folder changes. Enabled by default.
+    pub watch: Option<bool>,
+    /// Show every image that's added to the folder
+    pub follow: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct IpcSection {
+    /// Listen for remote control commands on a Unix domain socket
+    pub enabled: Option<bool>,
 }
 
 #[derive(Deserialize)]
@@ -144,12 +207,38 @@ pub struct EnvVar {
     pub value: String,
 }
 
+/// A user command bound to a set of keys.
+///
+/// The `program`, the `args` and the values of the `envs` may contain the
+/// following variables, which are replaced by their value when the command is
+/// executed:
+///
+/// - `${img}`: the absolute path of the current image
+/// - `${folder}`: the folder containing the current image
+/// - `${filename}`: the file name of the current image, e.g. `cat.jpg`
+/// - `${stem}`: the file name without the extension, e.g. `cat`
+/// - `${ext}`: the extension without the dot, e.g. `jpg`
+/// - `${index}`: the position of the image within the folder, starting at 1
+/// - `${count}`: the number of images in the folder
+/// - `${width}`, `${height}`: the size of the image in pixels, as displayed
+///   (i.e. after applying the orientation)
+/// - `${frame}`: the index of the shown animation frame, starting at 0
+/// - `${zoom}`: the zoom level in percent
+/// - `${view_x}`, `${view_y}`, `${view_w}`, `${view_h}`: the rectangle of the
+///   image that is visible on screen, in image pixels
+///
+/// A variable whose value is not known (for example the `${index}` before the
+/// folder has been read) is left as is. Write `$${` to get a literal `${`.
 #[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
 pub struct Command {
     pub input: Vec<String>,
     pub program: String,
     pub args: Option<Vec<String>>,
     pub envs: Option<Vec<EnvVar>>,
+    /// Block the viewer until the command exits
+    pub wait: Option<bool>,
+    /// Refresh the current folder once the command exits
+    pub reload_after: Option<bool>,
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Clone, Deserialize)]
@@ -159,9 +248,27 @@ pub struct TitleSection {
 }
 
 impl TitleSection {
-    pub fn format_file_path<'a>(&self, file_path: &'a Path) -> Cow<'a, str> {
+    /// Formats the path for the title. When `root` is given, a file in one of
+    /// its subfolders is shown with its path relative to `root`, unless more
+    /// folders are requested by `displayed_folders`.
+    pub fn format_file_path<'a>(
+        &self,
+        file_path: &'a Path,
+        root: Option<&Path>,
+    ) -> Cow<'a, str> {
+        let rel_path = root.and_then(|root| file_path.strip_prefix(root).ok());
         match self.displayed_folders {
-            Some(0) | None => file_path.file_name().unwrap().to_string_lossy(),
+            Some(0) | None => match rel_path {
+                Some(rel_path) => rel_path.to_string_lossy(),
+                None => file_path.file_name().unwrap().to_string_lossy(),
+            },
+            Some(n)
+                if rel_path.is_some_and(|p| {
+                    p.components().count() > 1 + n as usize
+                }) =>
+            {
+                rel_path.unwrap().to_string_lossy()
+            }
             Some(n) => {
                 let mut component_count = 0;
                 // On Windows the root can be the second component, when a
@@ -200,6 +307,8 @@ pub struct Configuration {
     pub title: Option<TitleSection>,
     pub image: Option<ConfigImageSection>,
     pub window: Option<ConfigWindowSection>,
+    pub folder: Option<ConfigFolderSection>,
+    pub ipc: Option<IpcSection>,
 }
 
 impl Configuration {
@@ -0,0 +1,136 @@
+//! Decoding of AVIF images and image sequences through libavif.
+//!
+//! `libavif_image` only decodes the first image of a file, so the sequences
+//! are decoded with the bindings of libavif directly.
+
+use std::{ffi::CStr, fs, path::Path};
+
+use gelatin::image;
+use libavif_sys::{
+    avifDecoder, avifDecoderCreate, avifDecoderDestroy, avifDecoderNextImage,
+    avifDecoderParse, avifDecoderSetIOMemory, avifImageYUVToRGB, avifRGBImage,
+    avifRGBImageAllocatePixels, avifRGBImageFreePixels,
+    avifRGBImageSetDefaults, avifResult, avifResultToString, AVIF_RESULT_OK,
+    AVIF_RGB_FORMAT_RGBA,
+};
+
+use super::image_loader::{errors::*, LoadResult, Orientation};
+
+/// Returns an iterator over the images of an AVIF file. A still image has a
+/// single frame.
+///
+/// The version of libavif in use doesn't report how many times a sequence is
+/// repeated, so the sequences are played forever.
+pub fn load_avif(
+    path: &Path,
+    req_id: u32,
+    orientation: Orientation,
+) -> Result<impl Iterator<Item = Result<LoadResult>>> {
+    let decoder = Decoder::new(fs::read(path)?)?;
+    let image_count = decoder.image_count();
+    Ok((0..image_count).map(move |_| {
+        let (image, duration) = decoder.next_image()?;
+        // Like GIFs, sequences without a duration are played at 10 fps
+        let delay_nano = if image_count > 1 && duration <= 0.0 {
+            100_000_000
+        } else {
+            (duration * 1e9) as u64
+        };
+        Ok(LoadResult::Frame {
+            req_id,
+            image: image.into(),
+            delay_nano,
+            orientation,
+        })
+    }))
+}
+
+struct Decoder {
+    decoder: *mut avifDecoder,
+    /// libavif reads from this while decoding, so it must outlive `decoder`
+    data: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(data: Vec<u8>) -> Result<Decoder> {
+        // SAFETY: The decoder is destroyed by `drop` and `data` is kept alive
+        // along with it.
+        unsafe {
+            let decoder = avifDecoderCreate();
+            if decoder.is_null() {
+                return Err(Error::Msg(
+                    "Could not create an AVIF decoder".into(),
+                ));
+            }
+            let decoder = Decoder { decoder, data };
+            check(avifDecoderSetIOMemory(
+                decoder.decoder,
+                decoder.data.as_ptr(),
+                decoder.data.len(),
+            ))?;
+            check(avifDecoderParse(decoder.decoder))?;
+            Ok(decoder)
+        }
+    }
+
+    fn image_count(&self) -> usize {
+        // SAFETY: `decoder` is valid until `drop`
+        unsafe { (*self.decoder).imageCount.max(0) as usize }
+    }
+
+    /// Decodes the next image, returning it along with its duration in
+    /// seconds
+    fn next_image(&self) -> Result<(image::RgbaImage, f64)> {
+        // SAFETY: `decoder` is valid until `drop`, `decoder.image` is valid
+        // after a successful call to `avifDecoderNextImage`. The pixels of
+        // `rgb` are freed after they are copied.
+        unsafe {
+            check(avifDecoderNextImage(self.decoder))?;
+            let avif_image = (*self.decoder).image;
+            let duration = (*self.decoder).imageTiming.duration;
+
+            let mut rgb: avifRGBImage = std::mem::zeroed();
+            avifRGBImageSetDefaults(&mut rgb, avif_image);
+            rgb.format = AVIF_RGB_FORMAT_RGBA;
+            rgb.depth = 8;
+            avifRGBImageAllocatePixels(&mut rgb);
+            let result = check(avifImageYUVToRGB(avif_image, &mut rgb));
+            let row_len = rgb.width as usize * 4;
+            let mut pixels = Vec::with_capacity(row_len * rgb.height as usize);
+            if result.is_ok() {
+                for y in 0..rgb.height as usize {
+                    let row = rgb.pixels.add(y * rgb.rowBytes as usize);
+                    pixels.extend_from_slice(std::slice::from_raw_parts(
+                        row, row_len,
+                    ));
+                }
+            }
+            let (width, height) = (rgb.width, rgb.height);
+            avifRGBImageFreePixels(&mut rgb);
+            result?;
+
+            let image = image::RgbaImage::from_raw(width, height, pixels)
+                .ok_or_else(|| Error::Msg("Invalid AVIF image size".into()))?;
+            Ok((image, duration))
+        }
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        // SAFETY: `decoder` was created by `avifDecoderCreate`
+        unsafe { avifDecoderDestroy(self.decoder) }
+    }
+}
+
+fn check(result: avifResult) -> Result<()> {
+    if result == AVIF_RESULT_OK {
+        return Ok(());
+    }
+    // SAFETY: libavif returns a static string for every result
+    let message = unsafe { CStr::from_ptr(avifResultToString(result)) };
+    Err(Error::Msg(format!(
+        "AVIF decoding failed: {}",
+        message.to_string_lossy()
+    )))
+}
@@ -1,12 +1,19 @@
 use std::{
-    ffi::{OsStr, OsString},
-    fmt, fs,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fmt, fs, io,
     path::{Path, PathBuf},
 };
 
 use log::debug;
+use rand::{thread_rng, Rng};
 
-use super::image_loader::is_file_supported;
+use super::{
+    image_loader::is_file_supported,
+    sort::{self, SortKey, SortOrder, SortValue},
+    watcher::{FolderWatcher, WakeFn, WatchEvent},
+};
 use crate::parallel_action::ParallelAction;
 
 #[derive(Debug)]
@@ -54,14 +61,40 @@ pub struct DirItem {
     pub request_id: u32,
 }
 
+/// Where the images of a `Directory` come from
+#[derive(Clone, PartialEq, Eq)]
+enum Source {
+    /// All the files of a single folder
+    Folder(PathBuf),
+    /// An explicit list of files which may be in any folder. The paths must
+    /// be absolute.
+    Playlist(Vec<PathBuf>),
+}
+
+/// The changes made by `Directory::process_watch_events`
+#[derive(Default)]
+pub struct FolderChanges {
+    /// The images that were added, in the order they were written
+    pub added: Vec<PathBuf>,
+    /// The files that were written to after they were listed
+    pub modified: Vec<DirItem>,
+    /// The files that were removed
+    pub removed: Vec<PathBuf>,
+    /// True if the current file is a different one than before, for example
+    /// because it was removed
+    pub current_changed: bool,
+}
+
 // enum FilterState {
 //     Idle,
 //     Processing(Arc<Vec<usize>>),
 //     JustFinished(Arc<Vec<usize>>),
 // }
 
+/// The list of files that can be browsed. This is either the contents of a
+/// folder or a playlist of arbitrary files.
 pub struct Directory {
-    path: PathBuf,
+    source: Source,
     files: Vec<DirItem>,
 
     /// Maps image indicies to indicies for the `files` vector.
@@ -87,6 +120,96 @@ pub struct Directory {
 
     //filter_state: Arc<Mutex<FilterState>>,
     filter_action: ParallelAction<Vec<DirItem>, Vec<usize>>,
+
+    /// Include the files in the subfolders of a folder
+    recursive: bool,
+
+    /// `None` keeps the natural order of the names for folders and the
+    /// original order for playlists
+    sort: Option<SortOrder>,
+    /// The values that the files are sorted by. Files without a value are
+    /// not in the map.
+    sort_values: HashMap<PathBuf, SortValue>,
+    /// The files that were added to the end while the files were being
+    /// collected. These are sorted in once the collecting is done.
+    unsorted: HashSet<PathBuf>,
+
+    /// True while the files are being collected in the background. The files
+    /// are not filtered until that's done.
+    collecting: bool,
+    /// Identifies the latest collect request, the results of the earlier
+    /// ones are ignored.
+    collect_generation: u32,
+    /// True if every file is being sorted, false if only the ones that are
+    /// not in the list yet
+    collect_resort: bool,
+    collect_action: ParallelAction<CollectInput, CollectOutput>,
+    /// The subfolders found by the last recursive collect
+    subfolders: Vec<PathBuf>,
+
+    /// Reports the changes in the folders of the files, if enabled
+    watcher: Option<FolderWatcher>,
+}
+
+/// Describes the files to be collected in the background. Walking subfolders
+/// and sorting by anything but the name can take a while.
+struct CollectInput {
+    generation: u32,
+    /// The folder to list, `None` for playlists
+    folder: Option<PathBuf>,
+    recursive: bool,
+    /// The files of the playlist
+    paths: Vec<PathBuf>,
+    sort: Option<SortOrder>,
+    /// The files that are already in order. If this is given, only the sort
+    /// values of the other files are read and the files are not sorted.
+    known: Option<HashSet<PathBuf>>,
+}
+
+struct CollectOutput {
+    generation: u32,
+    paths: Vec<PathBuf>,
+    /// The subfolders that were walked
+    subfolders: Vec<PathBuf>,
+    /// The values of the files that were sorted, or of the new files if the
+    /// files were not sorted
+    sort_values: HashMap<PathBuf, SortValue>,
+    /// True if `paths` is sorted
+    resorted: bool,
+}
+
+fn collect_files(input: CollectInput) -> CollectOutput {
+    let (mut paths, subfolders) = match &input.folder {
+        Some(folder) => walk_folder(folder, input.recursive),
+        None => (
+            input.paths.into_iter().filter(|p| p.is_file()).collect(),
+            Vec::new(),
+        ),
+    };
+    let mut sort_values = HashMap::new();
+    if let Some(order) = input.sort {
+        match &input.known {
+            Some(known) => {
+                let new_paths = paths.iter().filter(|p| !known.contains(*p));
+                for path in new_paths {
+                    if let Some(value) = sort::sort_value(path, order.key) {
+                        sort_values.insert(path.clone(), value);
+                    }
+                }
+            }
+            None => {
+                let root = input.folder.as_deref().filter(|_| input.recursive);
+                sort_values = sort::sort_paths(&mut paths, order, root);
+            }
+        }
+    }
+    CollectOutput {
+        generation: input.generation,
+        paths,
+        subfolders,
+        sort_values,
+        resorted: input.known.is_none(),
+    }
 }
 
 fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
@@ -105,10 +228,56 @@ fn get_action() -> impl FnMut(Vec<DirItem>) -> Vec<usize> {
     }
 }
 
+/// Returns the files in `root` and if `recursive` is true, in all of its
+/// subfolders except for hidden ones, in the natural order of their path
+/// relative to `root`. The subfolders are returned as well.
+fn walk_folder(root: &Path, recursive: bool) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    let mut files = Vec::new();
+    let mut subfolders = Vec::new();
+    let mut folders = vec![root.to_owned()];
+    while let Some(folder) = folders.pop() {
+        let entries = match fs::read_dir(&folder) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Could not read the folder {:?}: {}", folder, e);
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() && recursive => {
+                    let name = entry.file_name();
+                    if !name.to_string_lossy().starts_with('.') {
+                        folders.push(entry.path());
+                        subfolders.push(entry.path());
+                    }
+                }
+                Ok(file_type)
+                    if file_type.is_file() || file_type.is_symlink() =>
+                {
+                    let path = entry.path();
+                    let rel_path = path
+                        .strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned();
+                    files.push((rel_path, path));
+                }
+                _ => (),
+            }
+        }
+    }
+    files.sort_unstable_by(|(a, _), (b, _)| {
+        lexical_sort::natural_lexical_cmp(a, b)
+    });
+    let files = files.into_iter().map(|(_, path)| path).collect();
+    (files, subfolders)
+}
+
 impl Directory {
     pub fn new() -> Self {
         Directory {
-            path: PathBuf::new(),
+            source: Source::Folder(PathBuf::new()),
             files: Vec::new(),
             img_i_to_file_i: Vec::new(),
             file_i_to_img_i: Vec::new(),
@@ -116,27 +285,269 @@ impl Directory {
             curr_image_idx: 0,
             current_req_id: 0,
             filter_action: ParallelAction::new(get_action()),
+            recursive: false,
+            sort: None,
+            sort_values: HashMap::new(),
+            unsorted: HashSet::new(),
+            collecting: false,
+            collect_generation: 0,
+            collect_resort: false,
+            collect_action: ParallelAction::new(collect_files),
+            subfolders: Vec::new(),
+            watcher: None,
+        }
+    }
+
+    /// Starts watching the folders of the files for changes. `wake` is called
+    /// when there are changes to apply with `process_watch_events`.
+    pub fn watch_folders(&mut self, wake: WakeFn) -> io::Result<()> {
+        self.watcher = Some(FolderWatcher::new(wake)?);
+        self.update_watches();
+        Ok(())
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Watches the folders that the files are listed from
+    fn update_watches(&mut self) {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+        let folders = match &self.source {
+            Source::Folder(path) if path.as_os_str().is_empty() => Vec::new(),
+            Source::Folder(path) => {
+                let mut folders = vec![path.clone()];
+                if self.recursive {
+                    folders.extend(self.subfolders.iter().cloned());
+                }
+                folders
+            }
+            Source::Playlist(paths) => paths
+                .iter()
+                .filter_map(|path| path.parent())
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .map(|folder| folder.to_owned())
+                .collect(),
+        };
+        watcher.set_folders(&folders);
+    }
+
+    /// Applies the changes reported by the watcher since the last call. New
+    /// files are inserted and removed files are dropped without reading the
+    /// folder again.
+    pub fn process_watch_events(&mut self) -> FolderChanges {
+        let mut changes = FolderChanges::default();
+        let events = match &self.watcher {
+            Some(watcher) => watcher.try_get_events(),
+            None => return changes,
+        };
+        if events.is_empty() {
+            return changes;
+        }
+        let curr_path = self.curr_descriptor().map(|desc| desc.path.clone());
+        let mut rescan = false;
+        for event in events {
+            match event {
+                WatchEvent::Written(path) => {
+                    if let Some(item) =
+                        self.files.iter().find(|item| item.path == path)
+                    {
+                        changes.modified.push(item.clone());
+                    } else if let Source::Playlist(paths) = &self.source {
+                        // A file of the playlist was written again after it
+                        // had been removed
+                        rescan |= paths.contains(&path);
+                    } else if self.is_listed_folder(path.parent())
+                        && self.insert_file(path.clone())
+                    {
+                        changes.added.push(path);
+                    }
+                }
+                WatchEvent::Removed(path) => {
+                    if let Some(index) =
+                        self.files.iter().position(|item| item.path == path)
+                    {
+                        self.remove_file(index);
+                        changes.removed.push(path);
+                    }
+                }
+                WatchEvent::Rescan => rescan = true,
+            }
+        }
+        if rescan {
+            if let Err(e) = self.update_directory() {
+                eprintln!("Could not read the folder again: {e}");
+            }
+        }
+        let new_path = self.curr_descriptor().map(|desc| desc.path.clone());
+        changes.current_changed = curr_path != new_path;
+        changes
+    }
+
+    /// Returns true if the files of `folder` are listed. Events may still
+    /// arrive from a folder that was shown before.
+    fn is_listed_folder(&self, folder: Option<&Path>) -> bool {
+        let folder = match folder {
+            Some(folder) => folder,
+            None => return false,
+        };
+        match &self.source {
+            Source::Folder(path) => {
+                path == folder
+                    || (self.recursive
+                        && self.subfolders.iter().any(|sub| sub == folder))
+            }
+            Source::Playlist(_) => false,
+        }
+    }
+
+    /// Inserts the file at `path` where it belongs and returns true if it's an
+    /// image.
+    fn insert_file(&mut self, path: PathBuf) -> bool {
+        self.current_req_id += 1;
+        let is_image = is_file_supported(&path);
+        let item = DirItem {
+            path,
+            request_id: self.current_req_id,
+        };
+        if self.collecting {
+            // The file can be browsed at the end until it's sorted in.
+            self.unsorted.insert(item.path.clone());
+            self.files.push(item);
+            self.img_i_to_file_i.clear();
+            self.file_i_to_img_i.clear();
+            self.start_collecting(self.collect_resort);
+            return is_image;
+        }
+        let value = self
+            .sort
+            .and_then(|order| sort::sort_value(&item.path, order.key));
+        if let Some(value) = value {
+            self.sort_values.insert(item.path.clone(), value);
+        }
+        let index = self.insertion_index(&item.path);
+        let filtered = self.check_filter_ready();
+        self.files.insert(index, item);
+        if self.files.len() > 1 && self.curr_file_idx >= index {
+            self.curr_file_idx += 1;
+        }
+        if filtered {
+            for file_i in self.img_i_to_file_i.iter_mut() {
+                if *file_i >= index {
+                    *file_i += 1;
+                }
+            }
+            if is_image {
+                let img_i =
+                    self.img_i_to_file_i.partition_point(|&i| i < index);
+                self.img_i_to_file_i.insert(img_i, index);
+            }
+            self.finished_filtering();
+        } else {
+            self.filter_action.give_input(self.files.clone());
+        }
+        is_image
+    }
+
+    /// Returns the index that the file at `path` belongs at among the files.
+    /// The sort value of the file has to be in `sort_values` already.
+    fn insertion_index(&self, path: &Path) -> usize {
+        let descending = match self.sort {
+            Some(order) if order.key == SortKey::Shuffle => {
+                return thread_rng().gen_range(0..=self.files.len());
+            }
+            Some(order) => order.descending,
+            None => false,
+        };
+        let root = self.recursive_root();
+        let name = sort::sort_name(path, root);
+        let value = self.sort_values.get(path);
+        self.files.partition_point(|file| {
+            let file_name = sort::sort_name(&file.path, root);
+            let file_value = self.sort_values.get(&file.path);
+            sort::compare((file_value, &file_name), (value, &name), descending)
+                == Ordering::Less
+        })
+    }
+
+    /// Removes the file at `index`. If it was the current file, the next
+    /// image becomes the current file.
+    fn remove_file(&mut self, index: usize) {
+        let filtered = self.check_filter_ready();
+        let curr_removed = self.curr_file_idx == index;
+        let removed = self.files.remove(index);
+        self.sort_values.remove(&removed.path);
+        self.unsorted.remove(&removed.path);
+        if self.curr_file_idx > index {
+            self.curr_file_idx -= 1;
+        }
+        if filtered {
+            self.img_i_to_file_i.retain(|&file_i| file_i != index);
+            for file_i in self.img_i_to_file_i.iter_mut() {
+                if *file_i > index {
+                    *file_i -= 1;
+                }
+            }
+        }
+        if curr_removed {
+            let next_image = if filtered {
+                self.img_i_to_file_i
+                    .iter()
+                    .find(|&&file_i| file_i >= index)
+                    .or_else(|| self.img_i_to_file_i.last())
+                    .copied()
+            } else {
+                self.files
+                    .iter()
+                    .skip(index)
+                    .position(|item| is_file_supported(&item.path))
+                    .map(|i| index + i)
+            };
+            self.curr_file_idx = next_image.unwrap_or_else(|| {
+                index.min(self.files.len().saturating_sub(1))
+            });
+        }
+        if filtered {
+            self.finished_filtering();
+        } else if self.collecting {
+            // The collected files may still contain the removed one
+            self.img_i_to_file_i.clear();
+            self.file_i_to_img_i.clear();
+            self.start_collecting(self.collect_resort);
+        } else {
+            self.filter_action.give_input(self.files.clone());
         }
     }
 
     pub fn change_directory(&mut self, path: &Path) -> Result<()> {
-        if self.path != path {
-            self.path = path.to_owned();
+        if !self.is_folder(path) {
+            self.source = Source::Folder(path.to_owned());
             self.collect_directory()
         } else {
             Ok(())
         }
     }
 
+    /// Makes the file at `path/filename` the current file.
+    ///
+    /// If a playlist is open and the file is part of it, the playlist stays
+    /// open. Otherwise this changes to the folder at `path`.
     pub fn change_directory_with_filename(
         &mut self,
         path: &Path,
         filename: &OsStr,
     ) -> Result<()> {
-        self.change_directory(path)?;
-        // Look up the index of the filename in the directory
+        let file_path = path.join(filename);
+        if !self.contains(&file_path) {
+            self.change_directory(path)?;
+        }
+        // Look up the index of the file in the directory
         for (index, desc) in self.files.iter().enumerate() {
-            if desc.path.file_name().unwrap() == filename {
+            if desc.path == file_path {
                 self.curr_file_idx = index;
                 self.set_image_index_from_file_index();
                 // If we already finished filtering somehow
@@ -151,18 +562,71 @@ impl Directory {
         )))
     }
 
-    pub fn curr_filename(&self) -> Option<OsString> {
-        self.files
-            .get(self.curr_file_idx)
-            .and_then(|x| x.path.file_name().map(|x| x.to_owned()))
+    /// Replaces the contents with the files in `paths`, which must be
+    /// absolute. The files are kept in the given order.
+    pub fn open_playlist(&mut self, paths: Vec<PathBuf>) -> Result<()> {
+        self.source = Source::Playlist(paths);
+        self.collect_directory()
     }
 
-    pub fn curr_descriptor(&self) -> Option<&DirItem> {
-        self.files.get(self.curr_file_idx)
+    /// Returns true if the contents are the files of the folder at `path`
+    pub fn is_folder(&self, path: &Path) -> bool {
+        matches!(&self.source, Source::Folder(p) if p == path)
     }
 
-    pub fn path(&self) -> &Path {
-        self.path.as_path()
+    /// Includes or excludes the files in the subfolders of the current folder.
+    /// The subfolders are walked in the background.
+    pub fn set_recursive(&mut self, recursive: bool) -> Result<()> {
+        if self.recursive == recursive {
+            return Ok(());
+        }
+        self.recursive = recursive;
+        match &self.source {
+            Source::Folder(path) if !path.as_os_str().is_empty() => {
+                self.refresh(true)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Sorts the files in the background, keeping the current file
+    pub fn set_sort_order(&mut self, order: SortOrder) -> Result<()> {
+        self.sort = Some(order);
+        if self.files.is_empty() {
+            return Ok(());
+        }
+        self.refresh(true)
+    }
+
+    pub fn sort_order(&self) -> Option<SortOrder> {
+        self.sort
+    }
+
+    /// Returns true if the files have to be collected in the background
+    fn collects_in_background(&self) -> bool {
+        self.recursive_root().is_some() || self.sort.is_some()
+    }
+
+    /// The folder that the paths are shown relative to, which is the current
+    /// folder in recursive mode.
+    pub fn recursive_root(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Folder(path) if self.recursive => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns true if `path` is one of the files of the directory
+    fn contains(&self, path: &Path) -> bool {
+        self.files.iter().any(|item| item.path == path)
+    }
+
+    pub fn curr_descriptor(&self) -> Option<&DirItem> {
+        self.files.get(self.curr_file_idx)
     }
 
     pub fn set_curr_img_index(&mut self, index: usize) -> Result<()> {
@@ -236,18 +700,34 @@ impl Directory {
         }
     }
 
+    /// Reads the files again, keeping the current file. Files that are new
+    /// are sorted in among the others without changing their order.
     pub fn update_directory(&mut self) -> Result<()> {
-        let curr_filename = self.curr_filename();
-        let curr_filename = curr_filename.as_deref();
+        self.refresh(false)
+    }
+
+    /// Reads the files again, keeping the current file. If `resort` is true
+    /// every file is sorted again.
+    fn refresh(&mut self, resort: bool) -> Result<()> {
+        let curr_path = self.curr_descriptor().map(|desc| desc.path.clone());
         let curr_index = self.curr_file_idx;
         debug!(
-			"Directory: `update_directory`. Current filename: {:?}, curr_index: {:?}",
-			curr_filename, curr_index
+			"Directory: `update_directory`. Current path: {:?}, curr_index: {:?}",
+			curr_path, curr_index
 		);
-        self.collect_directory()?;
-        if curr_filename.is_some() {
+        if self.collects_in_background() && !self.files.is_empty() {
+            // Until the files are collected again, only the files that were
+            // removed are dropped so that the order is kept.
+            self.files.retain(|item| item.path.exists());
+            self.img_i_to_file_i.clear();
+            self.file_i_to_img_i.clear();
+            self.start_collecting(resort);
+        } else {
+            self.collect_directory()?;
+        }
+        if let Some(curr_path) = curr_path {
             for (index, desc) in self.files.iter().enumerate() {
-                if desc.path.file_name() == curr_filename {
+                if desc.path == curr_path {
                     debug!("Found file the previously 'current' file in the directory.");
                     self.curr_file_idx = index;
                     self.set_image_index_from_file_index();
@@ -280,32 +760,53 @@ impl Directory {
     }
 
     pub fn collect_directory(&mut self) -> Result<()> {
-        let mut dir_files: Vec<_> = fs::read_dir(&self.path)?
-            .filter_map(|x| match x {
-                Ok(entry) => match entry.file_type() {
-                    Ok(file_type) => {
-                        if file_type.is_file() || file_type.is_symlink() {
-                            self.current_req_id += 1;
-                            Some(DirItem {
-                                path: entry.path(),
-                                request_id: self.current_req_id,
-                            })
-                        } else {
-                            None
-                        }
+        self.collecting = false;
+        self.subfolders.clear();
+        let dir_files = match &self.source {
+            Source::Folder(path) => {
+                let mut dir_files: Vec<_> = fs::read_dir(path)?
+                    .filter_map(|x| match x {
+                        Ok(entry) => match entry.file_type() {
+                            Ok(file_type) => {
+                                if file_type.is_file() || file_type.is_symlink()
+                                {
+                                    self.current_req_id += 1;
+                                    Some(DirItem {
+                                        path: entry.path(),
+                                        request_id: self.current_req_id,
+                                    })
+                                } else {
+                                    None
+                                }
+                            }
+                            Err(_) => None,
+                        },
+                        Err(_) => None,
+                    })
+                    .collect();
+
+                dir_files.sort_unstable_by(|a, b| {
+                    lexical_sort::natural_lexical_cmp(
+                        &a.path.file_name().unwrap().to_string_lossy(),
+                        &b.path.file_name().unwrap().to_string_lossy(),
+                    )
+                });
+                dir_files
+            }
+            Source::Playlist(paths) => paths
+                .iter()
+                // Files that were deleted since the playlist was opened
+                // are skipped.
+                .filter(|path| path.is_file())
+                .map(|path| {
+                    self.current_req_id += 1;
+                    DirItem {
+                        path: path.clone(),
+                        request_id: self.current_req_id,
                     }
-                    Err(_) => None,
-                },
-                Err(_) => None,
-            })
-            .collect();
-
-        dir_files.sort_unstable_by(|a, b| {
-            lexical_sort::natural_lexical_cmp(
-                &a.path.file_name().unwrap().to_string_lossy(),
-                &b.path.file_name().unwrap().to_string_lossy(),
-            )
-        });
+                })
+                .collect(),
+        };
 
         // Set the current file index to the first image
         for (i, item) in dir_files.iter().enumerate() {
@@ -314,13 +815,138 @@ impl Directory {
                 break;
             }
         }
-        self.filter_action.give_input(dir_files.clone());
         self.img_i_to_file_i.clear();
         self.file_i_to_img_i.clear();
         self.files = dir_files;
+        self.sort_values.clear();
+        self.unsorted.clear();
+        if self.collects_in_background() {
+            // The files can be browsed in their natural order until the
+            // collection is done.
+            self.start_collecting(true);
+        } else {
+            self.filter_action.give_input(self.files.clone());
+        }
+        self.update_watches();
         Ok(())
     }
 
+    /// Collects the files in the background. Unless `resort` is true, the
+    /// files that are already listed keep their order and only the new ones
+    /// are sorted in between them once the collecting is done.
+    fn start_collecting(&mut self, resort: bool) {
+        self.collect_generation += 1;
+        self.collect_resort = resort;
+        let known = if resort {
+            None
+        } else {
+            let known = self
+                .files
+                .iter()
+                .filter(|item| !self.unsorted.contains(&item.path))
+                .map(|item| item.path.clone());
+            Some(known.collect())
+        };
+        let input = match &self.source {
+            Source::Folder(path) => CollectInput {
+                generation: self.collect_generation,
+                folder: Some(path.clone()),
+                recursive: self.recursive,
+                paths: Vec::new(),
+                sort: self.sort,
+                known,
+            },
+            Source::Playlist(paths) => CollectInput {
+                generation: self.collect_generation,
+                folder: None,
+                recursive: false,
+                paths: paths.clone(),
+                sort: self.sort,
+                known,
+            },
+        };
+        self.collect_action.give_input(input);
+        self.collecting = true;
+    }
+
+    /// Replaces the files with the collected ones, keeping the current file
+    /// and the request ids of the files that were already known.
+    fn finished_collecting(&mut self, output: CollectOutput) {
+        self.collecting = false;
+        self.subfolders = output.subfolders;
+        let curr_path = self.curr_descriptor().map(|desc| desc.path.clone());
+        if output.resorted {
+            let known_ids = self
+                .files
+                .drain(..)
+                .map(|item| (item.path, item.request_id))
+                .collect::<HashMap<_, _>>();
+            for path in output.paths {
+                let request_id = match known_ids.get(&path) {
+                    Some(&request_id) => request_id,
+                    None => {
+                        self.current_req_id += 1;
+                        self.current_req_id
+                    }
+                };
+                self.files.push(DirItem { path, request_id });
+            }
+            self.sort_values = output.sort_values;
+        } else {
+            self.sort_in_collected(output.paths, output.sort_values);
+        }
+        self.unsorted.clear();
+        let curr_index = curr_path
+            .and_then(|curr| self.files.iter().position(|f| f.path == curr));
+        self.curr_file_idx = curr_index.unwrap_or_else(|| {
+            self.curr_file_idx.min(self.files.len().saturating_sub(1))
+        });
+        self.img_i_to_file_i.clear();
+        self.file_i_to_img_i.clear();
+        self.filter_action.give_input(self.files.clone());
+        self.update_watches();
+    }
+
+    /// Drops the files that are not among `paths` and inserts the ones that
+    /// are not listed yet where they belong. The files that were added to the
+    /// end while collecting are inserted again as well.
+    fn sort_in_collected(
+        &mut self,
+        paths: Vec<PathBuf>,
+        sort_values: HashMap<PathBuf, SortValue>,
+    ) {
+        let mut unsorted_ids = HashMap::new();
+        {
+            let present = paths.iter().collect::<HashSet<_>>();
+            let unsorted = &self.unsorted;
+            self.files.retain(|item| {
+                if unsorted.contains(&item.path) {
+                    unsorted_ids.insert(item.path.clone(), item.request_id);
+                    return false;
+                }
+                present.contains(&item.path)
+            });
+        }
+        let known = self
+            .files
+            .iter()
+            .map(|item| item.path.clone())
+            .collect::<HashSet<_>>();
+        self.sort_values.retain(|path, _| known.contains(path));
+        self.sort_values.extend(sort_values);
+        for path in paths.into_iter().filter(|path| !known.contains(path)) {
+            let request_id = match unsorted_ids.get(&path) {
+                Some(&request_id) => request_id,
+                None => {
+                    self.current_req_id += 1;
+                    self.current_req_id
+                }
+            };
+            let index = self.insertion_index(&path);
+            self.files.insert(index, DirItem { path, request_id });
+        }
+    }
+
     fn finished_filtering(&mut self) {
         self.file_i_to_img_i.clear();
         self.file_i_to_img_i.reserve(self.files.len());
@@ -338,12 +964,23 @@ impl Directory {
     }
 
     fn set_image_index_from_file_index(&mut self) {
-        if let Some(img_idx) = self.file_i_to_img_i.get(self.curr_file_idx) {
-            self.curr_image_idx = img_idx.unwrap() as usize;
+        if let Some(Some(img_idx)) =
+            self.file_i_to_img_i.get(self.curr_file_idx)
+        {
+            self.curr_image_idx = *img_idx as usize;
         }
     }
 
     fn check_filter_ready(&mut self) -> bool {
+        if let Some(output) = self.collect_action.try_get_output() {
+            // Ignore the results of outdated requests
+            if self.collecting && output.generation == self.collect_generation {
+                self.finished_collecting(output);
+            }
+        }
+        if self.collecting {
+            return false;
+        }
         if let Some(out) = self.filter_action.try_get_output() {
             self.img_i_to_file_i = out;
             self.finished_filtering();
@@ -0,0 +1,331 @@
+//! Decoding of HEIF images, like the HEIC photos taken by phones.
+//!
+//! The rotation and the mirroring of a HEIF image are stored in the `irot`
+//! and `imir` properties of the image within the container. These take
+//! precedence over the EXIF orientation, so libheif is asked to leave the
+//! pixels as they are and the properties are translated to an `Orientation`
+//! instead.
+
+use std::{
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use gelatin::image;
+use libheif_rs::{
+    ColorSpace, DecodingOptions, HeifContext, LibHeif, RgbChroma,
+};
+
+use super::image_loader::{errors::*, Orientation};
+
+/// The brands of the `ftyp` box that mark a HEIF file with HEVC coded images
+const HEIF_BRANDS: [&[u8; 4]; 8] = [
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"hevm", b"hevs",
+];
+
+/// Larger `meta` boxes are not read to find the orientation
+const MAX_META_SIZE: u64 = 16 * 1024 * 1024;
+
+pub fn is_heif(file_start_bytes: &[u8]) -> bool {
+    if file_start_bytes.len() < 16 || &file_start_bytes[4..8] != b"ftyp" {
+        return false;
+    }
+    let box_size = u32::from_be_bytes([
+        file_start_bytes[0],
+        file_start_bytes[1],
+        file_start_bytes[2],
+        file_start_bytes[3],
+    ]) as usize;
+    let end = box_size.min(file_start_bytes.len());
+    // The major brand, followed by the minor version and the compatible
+    // brands
+    let major_brand = &file_start_bytes[8..12];
+    let compatible_brands = file_start_bytes[16..end.max(16)].chunks_exact(4);
+    std::iter::once(major_brand)
+        .chain(compatible_brands)
+        .any(|brand| HEIF_BRANDS.iter().any(|heif| &heif[..] == brand))
+}
+
+/// Decodes the primary image of the file. The pixels are returned as they are
+/// stored, the returned orientation tells how to show them.
+pub fn load_heif(path: &Path) -> Result<(image::RgbaImage, Orientation)> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| Error::Msg(format!("Invalid path: {path:?}")))?;
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_file(path_str)?;
+    let handle = context.primary_image_handle()?;
+    let mut options = DecodingOptions::new().ok_or_else(|| {
+        Error::Msg("Could not allocate the HEIF decoding options".into())
+    })?;
+    options.set_ignore_transformations(true);
+    let heif_image = lib_heif.decode(
+        &handle,
+        ColorSpace::Rgb(RgbChroma::Rgba),
+        Some(options),
+    )?;
+    let plane = heif_image
+        .planes()
+        .interleaved
+        .ok_or_else(|| Error::Msg("The HEIF image has no pixels".into()))?;
+    let row_len = plane.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    let image = image::RgbaImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or_else(|| Error::Msg("Invalid HEIF image size".into()))?;
+    let orientation = read_orientation(path).unwrap_or_else(|e| {
+        eprintln!("Could not read the orientation of {path:?}: {e}");
+        Orientation::Deg0
+    });
+    Ok((image, orientation))
+}
+
+/// Finds the `irot` and `imir` properties of the primary image
+fn read_orientation(path: &Path) -> io::Result<Orientation> {
+    let mut file = fs::File::open(path)?;
+    let meta = loop {
+        let (box_type, size) = match read_box_header(&mut file)? {
+            Some(header) => header,
+            None => return Ok(Orientation::Deg0),
+        };
+        if &box_type == b"meta" {
+            if size > MAX_META_SIZE {
+                return Err(invalid_data("The meta box is too large"));
+            }
+            let mut meta = Vec::with_capacity(size as usize);
+            file.by_ref().take(size).read_to_end(&mut meta)?;
+            break meta;
+        }
+        file.seek(SeekFrom::Current(size as i64))?;
+    };
+    // `meta` is a full box, its content starts after the version and flags
+    let meta = meta
+        .get(4..)
+        .ok_or_else(|| invalid_data("Short meta box"))?;
+
+    let mut primary_item = None;
+    let mut properties = Vec::new();
+    let mut associations = None;
+    for (box_type, content) in boxes(meta) {
+        match &box_type {
+            b"pitm" => primary_item = parse_pitm(content),
+            b"iprp" => {
+                for (box_type, content) in boxes(content) {
+                    match &box_type {
+                        b"ipco" => properties = boxes(content).collect(),
+                        b"ipma" => associations = Some(content),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    let (primary_item, associations) = match (primary_item, associations) {
+        (Some(item), Some(associations)) => (item, associations),
+        _ => return Ok(Orientation::Deg0),
+    };
+
+    // The transformations are applied in the order of the associations
+    let mut transform = Transform::default();
+    for index in parse_ipma(associations, primary_item)? {
+        // The property indices start from 1, 0 means no property
+        let property = index.checked_sub(1).and_then(|i| properties.get(i));
+        let (box_type, content) = match property {
+            Some(property) => property,
+            None => continue,
+        };
+        match (box_type, content.first()) {
+            (b"irot", Some(&angle)) => transform.rotate(angle & 0b11),
+            (b"imir", Some(&axis)) => transform.mirror(axis & 1 == 1),
+            _ => (),
+        }
+    }
+    Ok(transform.orientation())
+}
+
+/// A counter-clockwise rotation by `quarter_turns` times 90 degrees,
+/// optionally followed by a horizontal flip
+#[derive(Default)]
+struct Transform {
+    quarter_turns: u8,
+    flip: bool,
+}
+
+impl Transform {
+    fn rotate(&mut self, quarter_turns: u8) {
+        // Rotating after a flip is the same as flipping after rotating in the
+        // opposite direction
+        self.quarter_turns = if self.flip {
+            (self.quarter_turns + 4 - quarter_turns) % 4
+        } else {
+            (self.quarter_turns + quarter_turns) % 4
+        };
+    }
+
+    /// `upside_down` mirrors about the horizontal axis, otherwise about the
+    /// vertical one
+    fn mirror(&mut self, upside_down: bool) {
+        // Mirroring upside down is the same as mirroring left to right and
+        // rotating by 180 degrees
+        if upside_down {
+            self.rotate(2);
+        }
+        self.flip = !self.flip;
+    }
+
+    fn orientation(&self) -> Orientation {
+        match (self.quarter_turns, self.flip) {
+            (0, false) => Orientation::Deg0,
+            (1, false) => Orientation::Deg90,
+            (2, false) => Orientation::Deg180,
+            (3, false) => Orientation::Deg270,
+            (0, true) => Orientation::Deg0HorFlip,
+            (1, true) => Orientation::Deg270VerFlip,
+            (2, true) => Orientation::Deg180HorFlip,
+            _ => Orientation::Deg90VerFlip,
+        }
+    }
+}
+
+/// Returns the type and the size of the content of the next box, or `None`
+/// at the end of the file
+fn read_box_header(file: &mut fs::File) -> io::Result<Option<([u8; 4], u64)>> {
+    let mut header = [0; 8];
+    match file.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let box_type = [header[4], header[5], header[6], header[7]];
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let content_size = match size {
+        // The box extends to the end of the file
+        0 => {
+            let position = file.stream_position()?;
+            file.metadata()?.len().saturating_sub(position)
+        }
+        1 => {
+            let mut large_size = [0; 8];
+            file.read_exact(&mut large_size)?;
+            u64::from_be_bytes(large_size)
+                .checked_sub(16)
+                .ok_or_else(|| invalid_data("Invalid box size"))?
+        }
+        size => (size as u64)
+            .checked_sub(8)
+            .ok_or_else(|| invalid_data("Invalid box size"))?,
+    };
+    Ok(Some((box_type, content_size)))
+}
+
+/// Iterates over the type and the content of the boxes in `data`. Stops at the
+/// first box that doesn't fit.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let header = data.get(..8)?;
+        let size =
+            u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let box_type = [header[4], header[5], header[6], header[7]];
+        let (header_len, size) = match size {
+            0 => (8, data.len()),
+            1 => {
+                let large_size = data.get(8..16)?;
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(large_size);
+                (16, usize::try_from(u64::from_be_bytes(bytes)).ok()?)
+            }
+            size => (8, size as usize),
+        };
+        let content = data.get(header_len..size)?;
+        data = &data[size..];
+        Some((box_type, content))
+    })
+}
+
+fn parse_pitm(content: &[u8]) -> Option<u32> {
+    let mut reader = ByteReader::new(content);
+    let version = reader.u8()?;
+    reader.skip(3)?;
+    if version == 0 {
+        reader.u16().map(u32::from)
+    } else {
+        reader.u32()
+    }
+}
+
+/// Returns the indices of the properties associated with `item`
+fn parse_ipma(content: &[u8], item: u32) -> io::Result<Vec<usize>> {
+    let parse = || {
+        let mut reader = ByteReader::new(content);
+        let version = reader.u8()?;
+        reader.skip(2)?;
+        let flags = reader.u8()?;
+        let entry_count = reader.u32()?;
+        for _ in 0..entry_count {
+            let item_id = if version < 1 {
+                reader.u16().map(u32::from)?
+            } else {
+                reader.u32()?
+            };
+            let association_count = reader.u8()?;
+            let mut indices = Vec::with_capacity(association_count as usize);
+            for _ in 0..association_count {
+                // The highest bit tells if the property is essential
+                let index = if flags & 1 == 1 {
+                    reader.u16()? as usize & 0x7fff
+                } else {
+                    reader.u8()? as usize & 0x7f
+                };
+                indices.push(index);
+            }
+            if item_id == item {
+                return Some(indices);
+            }
+        }
+        Some(Vec::new())
+    };
+    parse().ok_or_else(|| invalid_data("Invalid ipma box"))
+}
+
+struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        ByteReader { data }
+    }
+
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(..count)?;
+        self.data = &self.data[count..];
+        Some(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Option<()> {
+        self.take(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|bytes| {
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
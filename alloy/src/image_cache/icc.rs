@@ -0,0 +1,177 @@
+//! Color management of images with an embedded ICC profile.
+//!
+//! The pixels of such images are converted from their profile to the one of
+//! the display on the loader threads, before the frames are sent. The display
+//! is assumed to be sRGB unless a profile is configured for it. Thumbnails
+//! are stored in sRGB and only converted to the display when they're loaded.
+
+use std::{
+    fs,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+use gelatin::image::{
+    self,
+    codecs::{jpeg::JpegDecoder, png::PngDecoder},
+    ImageDecoder, ImageFormat,
+};
+use moxcms::{ColorProfile, Layout, Transform8BitExecutor};
+
+use super::{
+    image_loader::{errors::*, read_webp_chunk},
+    pages::tiff_page_offsets,
+};
+
+const TAG_ICC_PROFILE: u16 = 34675;
+/// The `UNDEFINED` type of TIFF fields, which holds bytes
+const TIFF_UNDEFINED: u16 = 7;
+/// Larger profiles are assumed to be broken
+const MAX_PROFILE_SIZE: u32 = 64 * 1024 * 1024;
+
+static DISPLAY_PROFILE: OnceLock<ColorProfile> = OnceLock::new();
+
+/// Reads the ICC profile of the display from `path`. Has to be called before
+/// the first image is loaded, the display is sRGB otherwise.
+pub fn set_display_profile(path: &Path) -> Result<()> {
+    let profile = parse_profile(&fs::read(path)?)?;
+    DISPLAY_PROFILE
+        .set(profile)
+        .map_err(|_| Error::Msg("The display profile is already in use".into()))
+}
+
+fn display_profile() -> &'static ColorProfile {
+    DISPLAY_PROFILE.get_or_init(ColorProfile::new_srgb)
+}
+
+/// Returns the embedded ICC profile of a JPEG, PNG, WebP or TIFF file. For
+/// TIFF files the profile of `page` is returned.
+pub fn read_icc_profile(
+    path: &Path,
+    format: ImageFormat,
+    page: usize,
+) -> Result<Option<Vec<u8>>> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let profile = match format {
+        ImageFormat::Jpeg => JpegDecoder::new(reader)?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(reader)?.icc_profile(),
+        ImageFormat::WebP => read_webp_chunk(path, b"ICCP"),
+        ImageFormat::Tiff => read_tiff_profile(path, page)?,
+        _ => None,
+    };
+    Ok(profile)
+}
+
+/// Creates a conversion of RGBA pixels from the colors described by `icc` to
+/// the colors of the display
+pub fn display_transform(icc: &[u8]) -> Result<Arc<Transform8BitExecutor>> {
+    transform_to(&parse_profile(icc)?, display_profile())
+}
+
+/// Creates a conversion of RGBA pixels from the colors described by `icc` to
+/// sRGB, the colors of the shared thumbnails
+pub fn srgb_transform(icc: &[u8]) -> Result<Arc<Transform8BitExecutor>> {
+    transform_to(&parse_profile(icc)?, &ColorProfile::new_srgb())
+}
+
+/// The conversion of sRGB pixels to the colors of the display, `None` if no
+/// profile is configured for the display
+pub fn srgb_to_display() -> Option<Arc<Transform8BitExecutor>> {
+    static TRANSFORM: OnceLock<Option<Arc<Transform8BitExecutor>>> =
+        OnceLock::new();
+    let transform = TRANSFORM.get_or_init(|| {
+        let display = DISPLAY_PROFILE.get()?;
+        transform_to(&ColorProfile::new_srgb(), display)
+            .map_err(|e| eprintln!("Could not convert sRGB colors: {e}"))
+            .ok()
+    });
+    transform.clone()
+}
+
+fn transform_to(
+    source: &ColorProfile,
+    target: &ColorProfile,
+) -> Result<Arc<Transform8BitExecutor>> {
+    source
+        .create_transform_8bit(
+            Layout::Rgba,
+            target,
+            Layout::Rgba,
+            Default::default(),
+        )
+        .map_err(|e| Error::Msg(format!("Invalid ICC profile: {e}")))
+}
+
+/// Converts the pixels in place, one row at a time
+pub fn to_display_colors(
+    image: &mut image::RgbaImage,
+    transform: &Transform8BitExecutor,
+) -> Result<()> {
+    let row_len = image.width() as usize * 4;
+    if row_len == 0 {
+        return Ok(());
+    }
+    let mut source = vec![0; row_len];
+    for row in image.chunks_exact_mut(row_len) {
+        source.copy_from_slice(row);
+        transform
+            .transform(&source, row)
+            .map_err(|e| Error::Msg(format!("Color conversion failed: {e}")))?;
+    }
+    Ok(())
+}
+
+fn parse_profile(icc: &[u8]) -> Result<ColorProfile> {
+    ColorProfile::new_from_slice(icc)
+        .map_err(|e| Error::Msg(format!("Invalid ICC profile: {e}")))
+}
+
+/// Looks for the profile in the IFD of `page`
+fn read_tiff_profile(path: &Path, page: usize) -> Result<Option<Vec<u8>>> {
+    let mut file = fs::File::open(path)?;
+    let (big_endian, offsets) = match tiff_page_offsets(&mut file)? {
+        Some(pages) => pages,
+        None => return Ok(None),
+    };
+    let u16_from = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1]];
+        if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    };
+    let u32_from = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let ifd_offset = offsets[page.min(offsets.len() - 1)];
+    file.seek(SeekFrom::Start(ifd_offset as u64))?;
+    let mut count = [0; 2];
+    file.read_exact(&mut count)?;
+    let mut entries = vec![0; u16_from(&count) as usize * 12];
+    file.read_exact(&mut entries)?;
+    let entry = entries
+        .chunks_exact(12)
+        .find(|entry| u16_from(&entry[0..2]) == TAG_ICC_PROFILE);
+    let entry = match entry {
+        Some(entry) if u16_from(&entry[2..4]) == TIFF_UNDEFINED => entry,
+        _ => return Ok(None),
+    };
+    let len = u32_from(&entry[4..8]);
+    if len > MAX_PROFILE_SIZE {
+        return Err(Error::Msg("The ICC profile is too large".into()));
+    }
+    if len <= 4 {
+        return Ok(Some(entry[8..8 + len as usize].to_vec()));
+    }
+    let mut profile = vec![0; len as usize];
+    file.seek(SeekFrom::Start(u32_from(&entry[8..12]) as u64))?;
+    file.read_exact(&mut profile)?;
+    Ok(Some(profile))
+}
@@ -1,6 +1,7 @@
 use std::{
+    collections::BTreeSet,
     fs,
-    io::{BufReader, Read},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
@@ -12,15 +13,38 @@ use std::{
 
 use gelatin::image::{
     self,
-    codecs::{gif::GifDecoder, png::PngDecoder},
-    AnimationDecoder, ImageFormat,
+    codecs::{
+        gif::GifDecoder, hdr::HdrDecoder, jpeg::JpegDecoder, png::PngDecoder,
+        webp::WebPDecoder,
+    },
+    imageops, AnimationDecoder, ImageDecoder, ImageFormat,
 };
+use moxcms::Transform8BitExecutor;
 use resvg::{
     tiny_skia::{Pixmap, Transform},
     usvg::{self, TreeParsing},
     Tree,
 };
 
+#[cfg(feature = "avif")]
+use super::avif::load_avif;
+#[cfg(feature = "heif")]
+use super::heif::{is_heif, load_heif};
+#[cfg(feature = "raw")]
+use super::raw::{is_raw_file, load_raw, load_raw_preview, RAW_EXTENSIONS};
+use super::{
+    icc::{
+        display_transform, read_icc_profile, srgb_to_display, srgb_transform,
+        to_display_colors,
+    },
+    pages::{has_pages, is_cur, load_page},
+    svg::{frame_zoom, is_svg},
+    thumbnail_cache::load_cached_thumbnail,
+    tiles::{
+        is_huge_image, load_downscaled, load_tiles, TilePyramid, TileRequest,
+    },
+};
+
 pub mod errors {
     use std::io;
 
@@ -41,8 +65,17 @@ pub mod errors {
         ExifError(#[from] exif::Error),
         #[error(transparent)]
         SvgError(#[from] usvg::Error),
+        #[error(transparent)]
+        PngEncodingError(#[from] png::EncodingError),
+        #[error(transparent)]
+        TiffError(#[from] tiff::TiffError),
         #[cfg(feature = "avif")]
         AvifError(#[from] libavif_image::Error),
+        #[cfg(feature = "heif")]
+        #[error(transparent)]
+        HeifError(#[from] libheif_rs::HeifError),
+        #[error("The request was cancelled")]
+        Cancelled,
         #[error("{0}")]
         Msg(String),
     }
@@ -57,11 +90,36 @@ use self::errors::*;
 pub static PRIORITY_REQUEST_ID: AtomicU32 = AtomicU32::new(0); // The first request usually
 pub const NON_EXISTENT_REQUEST_ID: u32 = std::u32::MAX;
 
+/// The requests that the loader threads stop working on as soon as they can,
+/// see `ImageLoader::cancel_request`
+static CANCELLED_REQUESTS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Images with an embedded ICC profile are converted to the colors of the
+/// display unless this is unset
+pub static MANAGE_COLORS: AtomicBool = AtomicBool::new(true);
+
+/// Camera RAW files are shown using their embedded preview unless this is set,
+/// in which case the sensor data is developed. Thumbnails always use the
+/// preview.
+#[cfg(feature = "raw")]
+pub static DEVELOP_RAW: AtomicBool = AtomicBool::new(false);
+
+/// JPEG files with more pixels than this get a preview, see `load_preview`
+const MIN_PREVIEW_PIXELS: u64 = 16 * 1024 * 1024;
+/// The longest edge of a preview has at least this many pixels
+const PREVIEW_SIZE: u32 = 1024;
+
 pub enum ImgFormat {
     Image(ImageFormat),
     Svg,
     #[cfg(feature = "avif")]
     Avif,
+    #[cfg(feature = "jxl")]
+    Jxl,
+    #[cfg(feature = "heif")]
+    Heif,
+    #[cfg(feature = "raw")]
+    Raw,
 }
 
 /// These values define the transformation for a pixel array which is to be displayed.
@@ -98,9 +156,40 @@ pub enum Orientation {
     Deg90,
 }
 
+impl Orientation {
+    /// Returns `None` if `value` is not a valid EXIF orientation
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        // According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
+        match value {
+            1 => Some(Orientation::Deg0),
+            2 => Some(Orientation::Deg0HorFlip),
+            3 => Some(Orientation::Deg180),
+            4 => Some(Orientation::Deg180HorFlip),
+            5 => Some(Orientation::Deg90VerFlip),
+            6 => Some(Orientation::Deg270),
+            7 => Some(Orientation::Deg270VerFlip),
+            8 => Some(Orientation::Deg90),
+            _ => None,
+        }
+    }
+}
+
+/// Checked by the loader threads between the stages of a load and between the
+/// frames of an animation
+pub fn is_cancelled(req_id: u32) -> bool {
+    CANCELLED_REQUESTS.lock().unwrap().contains(&req_id)
+}
+
 /// Detects the format of an image file. It looks at the first 512 bytes;
 /// if that fails, it uses the file ending.
 pub fn detect_format(path: &Path) -> Result<ImgFormat> {
+    // RAW files look like TIFF files, only the file ending tells them apart
+    #[cfg(feature = "raw")]
+    {
+        if is_raw_file(path) {
+            return Ok(ImgFormat::Raw);
+        }
+    }
     let mut file = fs::File::open(path)?;
     let mut file_start_bytes = [0; 512];
 
@@ -112,18 +201,52 @@ pub fn detect_format(path: &Path) -> Result<ImgFormat> {
                 return Ok(ImgFormat::Avif);
             }
         }
-        if path.extension() == Some(std::ffi::OsStr::new("svg")) {
+        #[cfg(feature = "jxl")]
+        {
+            if is_jxl(&file_start_bytes) {
+                return Ok(ImgFormat::Jxl);
+            }
+        }
+        #[cfg(feature = "heif")]
+        {
+            if is_heif(&file_start_bytes) {
+                return Ok(ImgFormat::Heif);
+            }
+        }
+        if is_svg(path) {
             return Ok(ImgFormat::Svg);
         }
+        if is_cur(path, &file_start_bytes) {
+            return Ok(ImgFormat::Image(ImageFormat::Ico));
+        }
         if let Ok(format) = image::guess_format(&file_start_bytes) {
             return Ok(ImgFormat::Image(format));
         }
     }
 
     // If that didn't work, try to detect the format from the file ending
+    #[cfg(feature = "jxl")]
+    {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if extension.map_or(false, |ext| ext.eq_ignore_ascii_case("jxl")) {
+            return Ok(ImgFormat::Jxl);
+        }
+    }
     Ok(ImgFormat::Image(ImageFormat::from_path(path)?))
 }
 
+/// A JPEG XL file is either a bare codestream or a codestream wrapped in an
+/// ISO BMFF container
+#[cfg(feature = "jxl")]
+fn is_jxl(file_start_bytes: &[u8]) -> bool {
+    const CODESTREAM_SIGNATURE: &[u8] = &[0xff, 0x0a];
+    const CONTAINER_SIGNATURE: &[u8] = &[
+        0x00, 0x00, 0x00, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+    ];
+    file_start_bytes.starts_with(CODESTREAM_SIGNATURE)
+        || file_start_bytes.starts_with(CONTAINER_SIGNATURE)
+}
+
 pub fn detect_orientation(path: &Path) -> Result<Orientation> {
     let file = std::fs::File::open(path)?;
     let mut bufreader = std::io::BufReader::new(&file);
@@ -134,17 +257,9 @@ pub fn detect_orientation(path: &Path) -> Result<Orientation> {
     {
         if let exif::Value::Short(ref shorts) = orientation.value {
             if let Some(&exif_orientation) = shorts.first() {
-                // According to page 30 of http://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf
-                match exif_orientation {
-                    1 => Ok(Orientation::Deg0),
-                    2 => Ok(Orientation::Deg0HorFlip),
-                    3 => Ok(Orientation::Deg180),
-                    4 => Ok(Orientation::Deg180HorFlip),
-                    5 => Ok(Orientation::Deg90VerFlip),
-                    6 => Ok(Orientation::Deg270),
-                    7 => Ok(Orientation::Deg270VerFlip),
-                    8 => Ok(Orientation::Deg90),
-                    _ => {
+                match Orientation::from_exif(exif_orientation) {
+                    Some(orientation) => Ok(orientation),
+                    None => {
                         eprintln!("Invalid Exif orientation. Using default orientation.");
                         Ok(Orientation::Deg0)
                     }
@@ -168,6 +283,33 @@ pub fn simple_load_image(
     Ok(image::load(reader, image_format)?.into_rgba8())
 }
 
+/// True for the formats whose pixels may be brighter than 1.0
+pub fn is_hdr(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Hdr | ImageFormat::OpenExr)
+}
+
+/// Decodes an HDR image without clipping its samples
+pub fn load_hdr(
+    path: &Path,
+    image_format: ImageFormat,
+) -> Result<image::Rgba32FImage> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    if image_format != ImageFormat::Hdr {
+        return Ok(image::load(reader, image_format)?.into_rgba32f());
+    }
+    // `image::load` converts Radiance files to 8 bits
+    let decoder = HdrDecoder::new(reader)?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let mut samples = Vec::with_capacity(pixels.len() * 4);
+    for pixel in pixels {
+        samples.extend_from_slice(&pixel.0);
+        samples.push(1.0);
+    }
+    image::Rgba32FImage::from_raw(metadata.width, metadata.height, samples)
+        .ok_or_else(|| Error::Msg("Invalid HDR image size".into()))
+}
+
 /// Returns an iterator over the animation frames of a GIF file
 pub fn load_gif(
     path: &Path,
@@ -178,6 +320,81 @@ pub fn load_gif(
     Ok(load_animation(req_id, decoder))
 }
 
+/// Returns an iterator over the frames of a JPEG XL file. A still image has a
+/// single frame.
+///
+/// The decoder applies the orientation stored in the codestream, which takes
+/// precedence over the EXIF orientation, so the frames are never rotated any
+/// further.
+#[cfg(feature = "jxl")]
+pub fn load_jxl(
+    path: &Path,
+    req_id: u32,
+) -> Result<impl Iterator<Item = Result<LoadResult>>> {
+    let image = jxl_oxide::JxlImage::builder()
+        .open(path)
+        .map_err(jxl_error)?;
+    let tick_nano = match &image.image_header().metadata.animation {
+        Some(animation) if animation.tps_numerator > 0 => {
+            animation.tps_denominator as u64 * 1_000_000_000
+                / animation.tps_numerator as u64
+        }
+        _ => 0,
+    };
+    let frame_count = image.num_loaded_keyframes();
+    Ok((0..frame_count).map(move |index| {
+        let render = image.render_frame(index).map_err(jxl_error)?;
+        let mut delay_nano = render.duration() as u64 * tick_nano;
+        if frame_count > 1 && delay_nano == 0 {
+            // The same as for animated gifs
+            delay_nano = 100_000_000;
+        }
+        let image = jxl_frame_to_rgba(&render.image_all_channels())?;
+        Ok(LoadResult::Frame {
+            req_id,
+            image: image.into(),
+            delay_nano,
+            orientation: Orientation::Deg0,
+        })
+    }))
+}
+
+/// The decoder's errors are boxed, so they are kept as messages
+#[cfg(feature = "jxl")]
+fn jxl_error(error: Box<dyn std::error::Error + Send + Sync>) -> Error {
+    Error::Msg(format!("JPEG XL decoding failed: {error}"))
+}
+
+/// Converts the floating point samples of the decoder, which are between 0
+/// and 1, to 8 bit RGBA
+#[cfg(feature = "jxl")]
+fn jxl_frame_to_rgba(
+    frame: &jxl_oxide::FrameBuffer,
+) -> Result<image::RgbaImage> {
+    let channels = frame.channels();
+    if channels == 0 {
+        return Err(Error::Msg("The JPEG XL frame has no channels".into()));
+    }
+    let to_u8 = |sample: f32| (sample.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut pixels = Vec::with_capacity(frame.width() * frame.height() * 4);
+    for pixel in frame.buf().chunks_exact(channels) {
+        let (rgb, alpha) = match channels {
+            1 => ([pixel[0]; 3], 1.0),
+            2 => ([pixel[0]; 3], pixel[1]),
+            3 => ([pixel[0], pixel[1], pixel[2]], 1.0),
+            _ => ([pixel[0], pixel[1], pixel[2]], pixel[3]),
+        };
+        pixels.extend(rgb.iter().map(|&sample| to_u8(sample)));
+        pixels.push(to_u8(alpha));
+    }
+    image::RgbaImage::from_raw(
+        frame.width() as u32,
+        frame.height() as u32,
+        pixels,
+    )
+    .ok_or_else(|| Error::Msg("Invalid JPEG XL frame size".into()))
+}
+
 /// Parse, render and gather an SVG into a ImageBuffer<Rgba>
 pub fn load_svg(path: &std::path::Path) -> Result<image::RgbaImage> {
     let opt = usvg::Options::default();
@@ -185,10 +402,10 @@ pub fn load_svg(path: &std::path::Path) -> Result<image::RgbaImage> {
     let tree = usvg::Tree::from_data(&data, &opt)?;
     let tree = Tree::from_usvg(&tree);
     let size = tree.size;
-    let (width, height) = (size.width(), size.height());
-    // Scale to fit 4096
-    let zoom = 4096.0 / width.max(height);
-    let (width, height) = ((width * zoom) as u32, (height * zoom) as u32);
+    // Zoomed in views are rendered again at the zoom level, see `svg`
+    let zoom = frame_zoom(size);
+    let width = ((size.width() * zoom) as u32).max(1);
+    let height = ((size.height() * zoom) as u32).max(1);
     // These unwrapped Options are fine as long as the dimensions are correct
     let mut pixmap = Pixmap::new(width, height).unwrap();
 
@@ -197,10 +414,44 @@ pub fn load_svg(path: &std::path::Path) -> Result<image::RgbaImage> {
     Ok(image::RgbaImage::from_raw(width, height, pixmap.take()).unwrap())
 }
 
+/// The colors that the pixels of images with an ICC profile are converted to
+#[derive(Clone, Copy)]
+enum TargetColors {
+    Display,
+    /// The colors of the thumbnails that are shared with other applications
+    Srgb,
+}
+
+/// `page` selects the page of the files that have several, see `pages`.
+/// Images that are too large to be decoded at once are sent as tiles, see
+/// `tiles`.
 pub fn complex_load_image<F>(
     path: &Path,
     allow_animation: bool,
     req_id: u32,
+    page: usize,
+    process_image: F,
+) -> Result<()>
+where
+    F: FnMut(LoadResult) -> Result<()>,
+{
+    load_in_colors(
+        path,
+        allow_animation,
+        req_id,
+        page,
+        TargetColors::Display,
+        process_image,
+    )
+}
+
+/// Like `complex_load_image`, with the frames in the colors of `target`
+fn load_in_colors<F>(
+    path: &Path,
+    allow_animation: bool,
+    req_id: u32,
+    page: usize,
+    target: TargetColors,
     mut process_image: F,
 ) -> Result<()>
 where
@@ -209,55 +460,155 @@ where
     let image_format = detect_format(path)?;
     let orientation = detect_orientation(path).unwrap_or(Orientation::Deg0);
 
+    // The colors are converted before the frames leave the loader thread
+    let transform = match image_format {
+        ImgFormat::Image(format) => color_transform(path, format, page, target),
+        _ => None,
+    };
+    let mut process_image = |mut load_result: LoadResult| {
+        if let (
+            Some(transform),
+            LoadResult::Frame {
+                image: FrameImage::Rgba8(image),
+                ..
+            },
+        ) = (&transform, &mut load_result)
+        {
+            to_display_colors(image, transform.as_ref())?;
+        }
+        process_image(load_result)
+    };
+
     match image_format {
-        ImgFormat::Image(ImageFormat::Gif) => {
-            let mut frames = load_gif(path, req_id)?;
-            if allow_animation {
-                for frame in frames {
-                    process_image(frame?)?;
-                }
-            } else if let Some(frame) = frames.next() {
-                process_image(frame?)?;
+        ImgFormat::Image(image_format) if is_huge_image(path, image_format) => {
+            let (pyramid, page, page_count) = load_tiles(
+                path,
+                image_format,
+                page,
+                transform.as_deref(),
+                req_id,
+            )?;
+            if page_count > 1 {
+                process_image(LoadResult::Pages {
+                    req_id,
+                    page,
+                    page_count,
+                })?;
             }
+            process_image(LoadResult::Tiles {
+                req_id,
+                pyramid,
+                orientation,
+            })?;
+        }
+        ImgFormat::Image(ImageFormat::Gif) => {
+            let frames = load_gif(path, req_id)?;
+            process_frames(frames, allow_animation, &mut process_image)?;
         }
         ImgFormat::Image(ImageFormat::Png) => {
             let file = fs::File::open(path)?;
             let decoder = PngDecoder::new(file)?;
             if decoder.is_apng() {
-                let mut animation = load_animation(req_id, decoder.apng());
-                if allow_animation {
-                    for frame in animation {
-                        process_image(frame?)?;
-                    }
-                } else if let Some(frame) = animation.next() {
-                    process_image(frame?)?;
-                }
+                let frames = load_animation(req_id, decoder.apng());
+                process_frames(frames, allow_animation, &mut process_image)?;
             } else {
                 let image = simple_load_image(path, ImageFormat::Png)?;
                 process_image(LoadResult::Frame {
                     req_id,
-                    image,
+                    image: image.into(),
                     delay_nano: 0,
                     orientation,
                 })?;
             }
         }
+        ImgFormat::Image(ImageFormat::WebP) => {
+            let file = fs::File::open(path)?;
+            let decoder = WebPDecoder::new(file)?;
+            if decoder.has_animation() {
+                let loop_count =
+                    allow_animation.then(|| webp_loop_count(path)).flatten();
+                if let Some(loop_count) = loop_count {
+                    process_image(LoadResult::LoopCount {
+                        req_id,
+                        loop_count,
+                    })?;
+                }
+                let frames = load_animation(req_id, decoder);
+                process_frames(frames, allow_animation, &mut process_image)?;
+            } else {
+                let image =
+                    image::DynamicImage::from_decoder(decoder)?.into_rgba8();
+                process_image(LoadResult::Frame {
+                    req_id,
+                    image: image.into(),
+                    delay_nano: 0,
+                    orientation,
+                })?;
+            }
+        }
+        ImgFormat::Image(image_format) if has_pages(image_format) => {
+            let (image, page, page_count) =
+                load_page(path, image_format, page)?;
+            if page_count > 1 {
+                process_image(LoadResult::Pages {
+                    req_id,
+                    page,
+                    page_count,
+                })?;
+            }
+            process_image(LoadResult::Frame {
+                req_id,
+                image: image.into(),
+                delay_nano: 0,
+                orientation,
+            })?;
+        }
+        ImgFormat::Image(image_format) if is_hdr(image_format) => {
+            let image = load_hdr(path, image_format)?;
+            process_image(LoadResult::Frame {
+                req_id,
+                image: image.into(),
+                delay_nano: 0,
+                orientation,
+            })?;
+        }
         ImgFormat::Image(image_format) => {
             let image = simple_load_image(path, image_format)?;
             process_image(LoadResult::Frame {
                 req_id,
-                image,
+                image: image.into(),
                 delay_nano: 0,
                 orientation,
             })?;
         }
         #[cfg(feature = "avif")]
         ImgFormat::Avif => {
-            let buf = fs::read(path)?;
-            let image = libavif_image::read(&buf)?.into_rgba8();
+            let frames = load_avif(path, req_id, orientation)?;
+            process_frames(frames, allow_animation, &mut process_image)?;
+        }
+        #[cfg(feature = "jxl")]
+        ImgFormat::Jxl => {
+            let frames = load_jxl(path, req_id)?;
+            process_frames(frames, allow_animation, &mut process_image)?;
+        }
+        #[cfg(feature = "heif")]
+        ImgFormat::Heif => {
+            // The EXIF orientation is ignored, the container has its own
+            let (image, orientation) = load_heif(path)?;
             process_image(LoadResult::Frame {
                 req_id,
-                image,
+                image: image.into(),
+                delay_nano: 0,
+                orientation,
+            })?;
+        }
+        #[cfg(feature = "raw")]
+        ImgFormat::Raw => {
+            // The orientation is read from the RAW file itself
+            let (image, orientation) = load_raw(path)?;
+            process_image(LoadResult::Frame {
+                req_id,
+                image: image.into(),
                 delay_nano: 0,
                 orientation,
             })?;
@@ -266,7 +617,7 @@ where
             let image = load_svg(path)?;
             process_image(LoadResult::Frame {
                 req_id,
-                image,
+                image: image.into(),
                 delay_nano: 0,
                 orientation,
             })?;
@@ -276,6 +627,205 @@ where
     Ok(())
 }
 
+/// The conversion to the colors of `target`, if the colors are managed and the
+/// image has an ICC profile
+fn color_transform(
+    path: &Path,
+    format: ImageFormat,
+    page: usize,
+    target: TargetColors,
+) -> Option<Arc<Transform8BitExecutor>> {
+    if !MANAGE_COLORS.load(Ordering::SeqCst) {
+        return None;
+    }
+    let create_transform = match target {
+        TargetColors::Display => display_transform,
+        TargetColors::Srgb => srgb_transform,
+    };
+    read_icc_profile(path, format, page)
+        .and_then(|icc| icc.map(|icc| create_transform(&icc)).transpose())
+        .unwrap_or_else(|e| {
+            eprintln!("Could not manage the colors of {path:?}: {e}");
+            None
+        })
+}
+
+/// Decodes a large JPEG at a fraction of its resolution, which takes a lot
+/// less time than decoding all of it. The preview is shown until the frame is
+/// loaded. Returns `None` for other images.
+pub fn load_preview(path: &Path, req_id: u32) -> Result<Option<LoadResult>> {
+    if !matches!(detect_format(path)?, ImgFormat::Image(ImageFormat::Jpeg)) {
+        return Ok(None);
+    }
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut decoder = JpegDecoder::new(reader)?;
+    let (w, h) = decoder.dimensions();
+    if (w as u64 * h as u64) < MIN_PREVIEW_PIXELS {
+        return Ok(None);
+    }
+    // The decoder uses the smallest of its scales that covers the requested
+    // size, and only does a fraction of the work at that scale
+    let scale = PREVIEW_SIZE as f32 / w.max(h) as f32;
+    let preview_w = (w as f32 * scale).ceil() as u16;
+    let preview_h = (h as f32 * scale).ceil() as u16;
+    decoder.scale(preview_w, preview_h)?;
+    let mut image = image::DynamicImage::from_decoder(decoder)?.into_rgba8();
+    if let Some(transform) =
+        color_transform(path, ImageFormat::Jpeg, 0, TargetColors::Display)
+    {
+        to_display_colors(&mut image, transform.as_ref())?;
+    }
+    Ok(Some(LoadResult::Preview {
+        req_id,
+        image,
+        full_size: (w, h),
+        orientation: detect_orientation(path).unwrap_or(Orientation::Deg0),
+    }))
+}
+
+/// Passes every frame to `process_image`, or only the first one if
+/// `allow_animation` is false
+fn process_frames<F>(
+    mut frames: impl Iterator<Item = Result<LoadResult>>,
+    allow_animation: bool,
+    process_image: &mut F,
+) -> Result<()>
+where
+    F: FnMut(LoadResult) -> Result<()>,
+{
+    if allow_animation {
+        for frame in frames {
+            process_image(frame?)?;
+        }
+    } else if let Some(frame) = frames.next() {
+        process_image(frame?)?;
+    }
+    Ok(())
+}
+
+/// Reads how many times an animated WebP is played from its `ANIM` chunk.
+/// Returns `None` if it's played forever.
+fn webp_loop_count(path: &Path) -> Option<u32> {
+    let anim = read_webp_chunk(path, b"ANIM")?;
+    // The background color is followed by the loop count
+    let loop_count = u16::from_le_bytes([*anim.get(4)?, *anim.get(5)?]);
+    (loop_count > 0).then_some(loop_count as u32)
+}
+
+/// Returns the content of the first chunk of the type `fourcc` in a WebP file
+pub fn read_webp_chunk(path: &Path, fourcc: &[u8; 4]) -> Option<Vec<u8>> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).ok()?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return None;
+    }
+    loop {
+        let mut chunk_header = [0; 8];
+        file.read_exact(&mut chunk_header).ok()?;
+        let size = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]);
+        if &chunk_header[..4] == fourcc {
+            let mut chunk = Vec::new();
+            file.take(size as u64).read_to_end(&mut chunk).ok()?;
+            return Some(chunk);
+        }
+        // Chunks are padded to an even size
+        let padded_size = size as i64 + (size & 1) as i64;
+        file.seek(SeekFrom::Current(padded_size)).ok()?;
+    }
+}
+
+/// Decodes the first frame of the image at `path` and scales it down to fit
+/// into a square with sides of `max_size` pixels. Unlike the frames of
+/// `complex_load_image`, the thumbnail is already rotated according to the
+/// EXIF orientation. Its colors are sRGB, like the ones of the thumbnails
+/// in the shared cache.
+pub fn load_thumbnail(path: &Path, max_size: u32) -> Result<image::RgbaImage> {
+    // Developing the sensor data would be wasted on a thumbnail
+    #[cfg(feature = "raw")]
+    {
+        if is_raw_file(path) {
+            let (image, orientation) = load_raw_preview(path)?;
+            return Ok(apply_orientation(
+                fit_to_size(image, max_size),
+                orientation,
+            ));
+        }
+    }
+    // Huge images are decoded at a lower resolution instead of being tiled
+    if let ImgFormat::Image(format) = detect_format(path)? {
+        if is_huge_image(path, format) {
+            let transform =
+                color_transform(path, format, 0, TargetColors::Srgb);
+            let image =
+                load_downscaled(path, format, max_size, transform.as_deref())?;
+            let orientation =
+                detect_orientation(path).unwrap_or(Orientation::Deg0);
+            return Ok(apply_orientation(
+                fit_to_size(image, max_size),
+                orientation,
+            ));
+        }
+    }
+    let mut first_frame = None;
+    // No request of the image cache has this id, so it's never cancelled
+    let req_id = NON_EXISTENT_REQUEST_ID;
+    let target = TargetColors::Srgb;
+    load_in_colors(path, false, req_id, 0, target, |load_result| {
+        if let LoadResult::Frame {
+            image, orientation, ..
+        } = load_result
+        {
+            first_frame = Some((image.into_rgba8(), orientation));
+        }
+        Ok(())
+    })?;
+    let (image, orientation) = first_frame
+        .ok_or_else(|| Error::Msg("The image has no frames".to_string()))?;
+    Ok(apply_orientation(fit_to_size(image, max_size), orientation))
+}
+
+/// Scales the image down to fit into a square with sides of `max_size`
+/// pixels. Smaller images are returned unchanged.
+pub fn fit_to_size(image: image::RgbaImage, max_size: u32) -> image::RgbaImage {
+    let (w, h) = image.dimensions();
+    if w <= max_size && h <= max_size {
+        return image;
+    }
+    let scale = max_size as f32 / w.max(h) as f32;
+    let thumb_w = ((w as f32 * scale).round() as u32).max(1);
+    let thumb_h = ((h as f32 * scale).round() as u32).max(1);
+    imageops::thumbnail(&image, thumb_w, thumb_h)
+}
+
+/// Rotates and flips the pixels so that the image is shown correctly without
+/// any further transformation
+pub fn apply_orientation(
+    image: image::RgbaImage,
+    orientation: Orientation,
+) -> image::RgbaImage {
+    // `imageops` rotates clockwise, the orientation is counter-clockwise
+    match orientation {
+        Orientation::Deg0 => image,
+        Orientation::Deg0HorFlip => imageops::flip_horizontal(&image),
+        Orientation::Deg180 => imageops::rotate180(&image),
+        Orientation::Deg180HorFlip => imageops::flip_vertical(&image),
+        Orientation::Deg90VerFlip => {
+            imageops::flip_vertical(&imageops::rotate270(&image))
+        }
+        Orientation::Deg270 => imageops::rotate90(&image),
+        Orientation::Deg270VerFlip => {
+            imageops::flip_vertical(&imageops::rotate90(&image))
+        }
+        Orientation::Deg90 => imageops::rotate270(&image),
+    }
+}
+
 fn load_animation(
     req_id: u32,
     decoder: impl AnimationDecoder<'static>,
@@ -296,7 +846,7 @@ fn load_animation(
             let image = frame.into_buffer();
             LoadResult::Frame {
                 req_id,
-                image,
+                image: image.into(),
                 delay_nano,
                 orientation: Orientation::Deg0,
             }
@@ -311,11 +861,17 @@ pub fn is_file_supported(filename: &Path) -> bool {
             match ext.as_str() {
                 "jpg" | "jpeg" | "png" | "apng" | "gif" | "webp" | "tif"
                 | "tiff" | "tga" | "bmp" | "ico" | "hdr" | "pbm" | "pam"
-                | "ppm" | "pgm" => {
+                | "ppm" | "pgm" | "cur" | "exr" => {
                     return true;
                 }
                 #[cfg(feature = "avif")]
                 "avif" => return true,
+                #[cfg(feature = "jxl")]
+                "jxl" => return true,
+                #[cfg(feature = "heif")]
+                "heic" | "heif" => return true,
+                #[cfg(feature = "raw")]
+                ext if RAW_EXTENSIONS.contains(&ext) => return true,
                 _ => (),
             }
         }
@@ -327,6 +883,68 @@ pub fn is_file_supported(filename: &Path) -> bool {
 pub struct LoadRequest {
     pub req_id: u32,
     pub path: PathBuf,
+    /// If set, only a thumbnail that fits into a square of this size is
+    /// loaded and sent as a single `LoadResult::Thumbnail`
+    pub thumbnail_size: Option<u32>,
+    /// The page to load from files that have several
+    pub page: usize,
+    /// If set, `path` is the file of a `TilePyramid` and only this tile is
+    /// read from it. It's sent to its texture instead of the results.
+    pub tile: Option<TileRequest>,
+}
+
+/// The pixels of a frame. HDR images keep their floating point samples, which
+/// are in linear light and may exceed 1.0, so they can be tone mapped when
+/// they are drawn.
+pub enum FrameImage {
+    Rgba8(image::RgbaImage),
+    Rgba32F(image::Rgba32FImage),
+}
+
+impl FrameImage {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            FrameImage::Rgba8(image) => image.dimensions(),
+            FrameImage::Rgba32F(image) => image.dimensions(),
+        }
+    }
+
+    /// HDR images are converted to sRGB, clipping everything above 1.0
+    pub fn into_rgba8(self) -> image::RgbaImage {
+        match self {
+            FrameImage::Rgba8(image) => image,
+            FrameImage::Rgba32F(image) => {
+                let to_u8 = |sample: f32| {
+                    (sample.clamp(0.0, 1.0) * 255.0).round() as u8
+                };
+                let encode = |linear: f32| {
+                    let linear = linear.clamp(0.0, 1.0);
+                    if linear <= 0.0031308 {
+                        to_u8(linear * 12.92)
+                    } else {
+                        to_u8(1.055 * linear.powf(1.0 / 2.4) - 0.055)
+                    }
+                };
+                let (w, h) = image.dimensions();
+                image::RgbaImage::from_fn(w, h, |x, y| {
+                    let [r, g, b, a] = image.get_pixel(x, y).0;
+                    image::Rgba([encode(r), encode(g), encode(b), to_u8(a)])
+                })
+            }
+        }
+    }
+}
+
+impl From<image::RgbaImage> for FrameImage {
+    fn from(image: image::RgbaImage) -> Self {
+        FrameImage::Rgba8(image)
+    }
+}
+
+impl From<image::Rgba32FImage> for FrameImage {
+    fn from(image: image::Rgba32FImage) -> Self {
+        FrameImage::Rgba32F(image)
+    }
 }
 
 pub enum LoadResult {
@@ -336,18 +954,58 @@ pub enum LoadResult {
     },
     Frame {
         req_id: u32,
-        image: image::RgbaImage,
+        image: FrameImage,
         delay_nano: u64,
 
         /// How much does the image need to be rotated counter-clockwise to be shown correctly
         orientation: Orientation,
     },
+    /// Sent before the frame of a large image that takes long to decode. The
+    /// preview `image` is shown scaled up to `full_size` until the frame is
+    /// loaded.
+    Preview {
+        req_id: u32,
+        image: image::RgbaImage,
+        full_size: (u32, u32),
+        orientation: Orientation,
+    },
+    /// Sent in place of the frame of an image that's too large to be decoded
+    /// at once
+    Tiles {
+        req_id: u32,
+        pyramid: TilePyramid,
+        orientation: Orientation,
+    },
+    /// Sent before the frames of an animation that is played `loop_count`
+    /// times instead of forever
+    LoopCount {
+        req_id: u32,
+        loop_count: u32,
+    },
+    /// Sent before the frames of a file with several pages, `page` is the
+    /// index of the page that's loaded
+    Pages {
+        req_id: u32,
+        page: usize,
+        page_count: usize,
+    },
     Done {
         req_id: u32,
     },
     Failed {
         req_id: u32,
     },
+    /// Sent in place of `Done` if the request was cancelled before it was
+    /// finished
+    Cancelled {
+        req_id: u32,
+    },
+    /// The result of a thumbnail request. `image` is `None` if the image
+    /// could not be loaded.
+    Thumbnail {
+        req_id: u32,
+        image: Option<image::RgbaImage>,
+    },
 }
 
 impl LoadResult {
@@ -355,8 +1013,14 @@ impl LoadResult {
         match self {
             LoadResult::Start { req_id, .. } => *req_id,
             LoadResult::Frame { req_id, .. } => *req_id,
+            LoadResult::Preview { req_id, .. } => *req_id,
+            LoadResult::Tiles { req_id, .. } => *req_id,
+            LoadResult::LoopCount { req_id, .. } => *req_id,
+            LoadResult::Pages { req_id, .. } => *req_id,
             LoadResult::Done { req_id, .. } => *req_id,
             LoadResult::Failed { req_id, .. } => *req_id,
+            LoadResult::Cancelled { req_id, .. } => *req_id,
+            LoadResult::Thumbnail { req_id, .. } => *req_id,
         }
     }
 }
@@ -445,11 +1109,67 @@ impl ImageLoader {
         self.path_tx.send(request).unwrap();
     }
 
+    /// For sending requests from elsewhere, like the tiles of a
+    /// `TiledTexture`
+    pub fn request_sender(&self) -> Sender<LoadRequest> {
+        self.path_tx.clone()
+    }
+
+    /// Makes the loader threads stop working on the request as soon as they
+    /// can. Its last result is `LoadResult::Cancelled` unless it was finished
+    /// already.
+    pub fn cancel_request(&mut self, req_id: u32) {
+        CANCELLED_REQUESTS.lock().unwrap().insert(req_id);
+    }
+
+    /// Has to be called once the last result of a request was received, so
+    /// that the next request with the same id isn't cancelled
+    pub fn finish_request(&mut self, req_id: u32) {
+        CANCELLED_REQUESTS.lock().unwrap().remove(&req_id);
+    }
+
     fn load_and_send(img_sender: &Sender<LoadResult>, request: LoadRequest) {
+        if let Some(tile) = request.tile {
+            tile.read(&request.path);
+            return;
+        }
+        if let Some(max_size) = request.thumbnail_size {
+            let image = load_cached_thumbnail(&request.path, max_size)
+                .and_then(|mut image| {
+                    // The thumbnails are stored in sRGB
+                    if MANAGE_COLORS.load(Ordering::SeqCst) {
+                        if let Some(transform) = srgb_to_display() {
+                            to_display_colors(&mut image, transform.as_ref())?;
+                        }
+                    }
+                    Ok(image)
+                });
+            let image = match image {
+                Ok(image) => Some(image),
+                Err(error) => {
+                    eprintln!(
+                        "Request #{}: Error occurred while loading the thumbnail of {:?}\n    {}",
+                        request.req_id, request.path, error,
+                    );
+                    None
+                }
+            };
+            img_sender
+                .send(LoadResult::Thumbnail {
+                    req_id: request.req_id,
+                    image,
+                })
+                .unwrap();
+            return;
+        }
         fn try_load_and_send(
             img_sender: &Sender<LoadResult>,
             request: &LoadRequest,
         ) -> Result<()> {
+            let req_id = request.req_id;
+            if is_cancelled(req_id) {
+                return Err(Error::Cancelled);
+            }
             let metadata = fs::metadata(&request.path)?;
             img_sender
                 .send(LoadResult::Start {
@@ -457,7 +1177,29 @@ impl ImageLoader {
                     metadata,
                 })
                 .unwrap();
-            complex_load_image(&request.path, true, request.req_id, |frame| {
+            if is_cancelled(req_id) {
+                return Err(Error::Cancelled);
+            }
+            // Only the image that's being waited for gets a preview, the
+            // neighbors that are prefetched have time to be decoded
+            if PRIORITY_REQUEST_ID.load(Ordering::SeqCst) == req_id {
+                match load_preview(&request.path, req_id) {
+                    Ok(Some(preview)) => img_sender.send(preview).unwrap(),
+                    Ok(None) => (),
+                    Err(e) => eprintln!(
+                        "Could not load a preview of {:?}: {e}",
+                        request.path
+                    ),
+                }
+            }
+            if is_cancelled(req_id) {
+                return Err(Error::Cancelled);
+            }
+            let page = request.page;
+            complex_load_image(&request.path, true, req_id, page, |frame| {
+                if is_cancelled(req_id) {
+                    return Err(Error::Cancelled);
+                }
                 img_sender.send(frame).unwrap();
                 Ok(())
             })?;
@@ -469,6 +1211,9 @@ impl ImageLoader {
                 Ok(()) => LoadResult::Done {
                     req_id: request.req_id,
                 },
+                Err(Error::Cancelled) => LoadResult::Cancelled {
+                    req_id: request.req_id,
+                },
                 Err(error) => {
                     eprintln!(
 						"Request #{}: Error occurred while loading file {:?}\n    {}",
@@ -492,6 +1237,9 @@ impl Drop for ImageLoader {
                     .send(LoadRequest {
                         req_id: 0,
                         path: PathBuf::from(""),
+                        thumbnail_size: None,
+                        page: 0,
+                        tile: None,
                     })
                     .unwrap();
             }
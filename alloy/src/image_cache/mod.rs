@@ -1,32 +1,61 @@
+#[cfg(feature = "avif")]
+mod avif;
 mod directory;
+#[cfg(feature = "heif")]
+mod heif;
+mod icc;
 pub mod image_loader;
+mod pages;
 mod pending_requests;
+#[cfg(feature = "raw")]
+mod raw;
+pub mod sort;
+mod svg;
+mod thumbnail_cache;
+mod thumbnails;
+mod tiles;
+pub mod watcher;
 
 use std::{
     borrow::Cow,
     collections::BTreeMap,
     ffi::{OsStr, OsString},
     fs, mem,
+    ops::Range,
     path::{Path, PathBuf},
     rc::Rc,
-    sync::atomic::Ordering,
+    slice,
+    sync::{atomic::Ordering, mpsc::Sender},
     time::SystemTime,
 };
 
-use gelatin::{
-    glium::{
-        self,
-        texture::{MipmapsOption, RawImage2d, SrgbTexture2d},
-        CapabilitiesSource,
+use gelatin::glium::{
+    self,
+    texture::{
+        MipmapsOption, RawImage2d, SrgbTexture2d, Texture2d, ToClientFormat,
+        UncompressedFloatFormat,
     },
-    image,
+    CapabilitiesSource,
 };
 use log::trace;
 
+#[cfg(feature = "raw")]
+use self::raw::is_raw_file;
+pub use self::{
+    directory::FolderChanges,
+    icc::set_display_profile,
+    svg::{is_svg, FrameRect},
+    thumbnails::Thumbnail,
+    tiles::{TiledTexture, KEEP_TILES},
+};
 use self::{
     directory::{DirItem, Directory},
     image_loader::*,
     pending_requests::PendingRequests,
+    svg::SvgRenderer,
+    thumbnails::{Thumbnails, THUMBNAIL_SIZE},
+    tiles::{TilePyramid, TILE_SIZE},
+    watcher::WakeFn,
 };
 
 pub mod errors {
@@ -64,18 +93,35 @@ pub mod errors {
 pub use self::errors::Result;
 use self::errors::*;
 
-pub fn get_image_size_estimate(width: u32, height: u32) -> isize {
+pub fn get_image_size_estimate(width: u32, height: u32, hdr: bool) -> isize {
     // In an RGBA image, each pixel is 4 bytes.
     // counting all the mipmaps would add an additionnal multiplier of around ~1.6
     // but only the gpu textures have mip maps so just multiply by 1.5
     // 4 x 1.5 gives the factor 6.
-    (width * height * 6) as isize
+    // HDR textures store each channel as a 16 bit float, doubling the size.
+    let bytes_per_pixel = if hdr { 12 } else { 6 };
+    // Computed in 64 bits, the product overflows a u32 for large images
+    let size = width as u64 * height as u64 * bytes_per_pixel;
+    size.min(isize::MAX as u64) as isize
 }
 
 pub fn get_anim_size_estimate(frames: &[AnimationFrameTexture]) -> isize {
     frames
         .iter()
-        .map(|frame| get_image_size_estimate(frame.w, frame.h))
+        .map(|frame| {
+            if let Some(tiles) = &frame.tiles {
+                return tiles.size_estimate();
+            }
+            if let Some(preview) = &frame.preview {
+                let (w, h) = preview.dimensions();
+                return get_image_size_estimate(w, h, false);
+            }
+            let detail_size = frame.detail.as_ref().map_or(0, |detail| {
+                let (w, h) = detail.tex.dimensions();
+                get_image_size_estimate(w, h, false)
+            });
+            get_image_size_estimate(frame.w, frame.h, frame.hdr) + detail_size
+        })
         .sum()
 }
 
@@ -97,12 +143,36 @@ impl<'a> RequestKind<'a> {
     }
 }
 
+/// The texture of a cell in the grid of an `AnimationFrameTexture`
+pub enum CellTexture {
+    Srgb(SrgbTexture2d),
+    /// Holds the linear floating point samples of an HDR image
+    Float(Texture2d),
+}
+
+impl CellTexture {
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            CellTexture::Srgb(tex) => tex.dimensions(),
+            CellTexture::Float(tex) => tex.dimensions(),
+        }
+    }
+}
+
 pub struct TextureGridItem {
-    pub tex: SrgbTexture2d,
+    pub tex: CellTexture,
     pub col: u32,
     pub row: u32,
 }
 
+/// A part of an SVG that was rendered again at the zoom level it's shown at,
+/// see `svg`
+pub struct DetailTexture {
+    pub tex: CellTexture,
+    /// The part of the frame that the texture covers
+    pub region: FrameRect,
+}
+
 #[derive(Clone)]
 pub struct AnimationFrameTexture {
     /// The maximum texture size supported by GPUs is limited. However it may be
@@ -120,6 +190,16 @@ pub struct AnimationFrameTexture {
 
     pub delay_nano: u64,
     pub orientation: Orientation,
+    /// True if the frame is in floating point and has to be tone mapped
+    pub hdr: bool,
+    /// Drawn over the grid where it's sharper than the frame
+    pub detail: Option<Rc<DetailTexture>>,
+    /// The tiles of an image that's too large for the grid. The grid is empty
+    /// if this is set, see `tiles`
+    pub tiles: Option<Rc<TiledTexture>>,
+    /// A low resolution version of the image that's shown until the image is
+    /// decoded. The grid is empty if this is set.
+    pub preview: Option<Rc<CellTexture>>,
 
     /// The total width of the image. This equals to the sum of the widths of the
     /// textures from a single row of the grid
@@ -132,12 +212,11 @@ pub struct AnimationFrameTexture {
 impl AnimationFrameTexture {
     pub fn from_image(
         display: &glium::Display,
-        image: image::RgbaImage,
+        image: FrameImage,
         delay_nano: u64,
         orientation: Orientation,
     ) -> Result<Self> {
         let (w, h) = image.dimensions();
-        let img_bytes = image.into_raw();
         let mut tex_grid = Vec::new();
 
         // The reasoning behind dividing by 2 and taking the min with 4*1024, is
@@ -161,8 +240,7 @@ impl AnimationFrameTexture {
                 let cell_w = (w - offset_x).min(max_size);
                 let cell_h = (h - offset_y).min(max_size);
                 let tex = texture_from_img_rect(
-                    display, w, h, &img_bytes, offset_x, offset_y, cell_w,
-                    cell_h,
+                    display, &image, offset_x, offset_y, cell_w, cell_h,
                 )?;
                 let item = TextureGridItem { tex, col, row };
                 tex_grid.push(item);
@@ -173,6 +251,10 @@ impl AnimationFrameTexture {
             tex_grid: Rc::new(tex_grid),
             delay_nano,
             orientation,
+            hdr: matches!(image, FrameImage::Rgba32F(_)),
+            detail: None,
+            tiles: None,
+            preview: None,
             w,
             h,
             cell_step_size: max_size,
@@ -181,6 +263,57 @@ impl AnimationFrameTexture {
         })
     }
 
+    pub fn from_tiles(
+        display: &glium::Display,
+        pyramid: TilePyramid,
+        orientation: Orientation,
+        req_id: u32,
+        request_tx: Sender<LoadRequest>,
+    ) -> Result<Self> {
+        let (w, h) = pyramid.dimensions();
+        let tiles = TiledTexture::new(display, pyramid, req_id, request_tx)?;
+        Ok(AnimationFrameTexture {
+            tex_grid: Rc::new(Vec::new()),
+            delay_nano: 0,
+            orientation,
+            hdr: false,
+            detail: None,
+            tiles: Some(Rc::new(tiles)),
+            preview: None,
+            w,
+            h,
+            cell_step_size: TILE_SIZE,
+            grid_rows: 0,
+            grid_cols: 0,
+        })
+    }
+
+    /// The frame has the size of the image, `image` is scaled up to it
+    pub fn from_preview(
+        display: &glium::Display,
+        image: FrameImage,
+        (w, h): (u32, u32),
+        orientation: Orientation,
+    ) -> Result<Self> {
+        let (preview_w, preview_h) = image.dimensions();
+        let tex =
+            texture_from_img_rect(display, &image, 0, 0, preview_w, preview_h)?;
+        Ok(AnimationFrameTexture {
+            tex_grid: Rc::new(Vec::new()),
+            delay_nano: 0,
+            orientation,
+            hdr: false,
+            detail: None,
+            tiles: None,
+            preview: Some(Rc::new(tex)),
+            w,
+            h,
+            cell_step_size: w.max(h),
+            grid_rows: 0,
+            grid_cols: 0,
+        })
+    }
+
     pub fn oriented_dimensions(&self) -> (u32, u32) {
         use Orientation::*;
         match self.orientation {
@@ -190,25 +323,66 @@ impl AnimationFrameTexture {
     }
 }
 
-/// img_bytes has to be an rgba8 buffer.
-#[allow(clippy::too_many_arguments)]
 fn texture_from_img_rect(
     display: &glium::Display,
+    image: &FrameImage,
+    offset_x: u32,
+    offset_y: u32,
+    cell_w: u32,
+    cell_h: u32,
+) -> Result<CellTexture> {
+    let (img_w, img_h) = image.dimensions();
+    let x_pow = 31 - img_w.leading_zeros();
+    let y_pow = 31 - img_h.leading_zeros();
+
+    let max_mipmap_levels = x_pow.min(y_pow).min(4);
+
+    let mipmaps = if max_mipmap_levels == 1 {
+        MipmapsOption::NoMipmap
+    } else {
+        MipmapsOption::AutoGeneratedMipmapsMax(max_mipmap_levels)
+        //MipmapsOption::AutoGeneratedMipmaps
+    };
+    match image {
+        FrameImage::Rgba8(image) => {
+            let samples = image.as_raw();
+            let raw_image =
+                img_rect(img_w, samples, offset_x, offset_y, cell_w, cell_h);
+            let tex = SrgbTexture2d::with_mipmaps(display, raw_image, mipmaps)?;
+            Ok(CellTexture::Srgb(tex))
+        }
+        FrameImage::Rgba32F(image) => {
+            let samples = image.as_raw();
+            let raw_image =
+                img_rect(img_w, samples, offset_x, offset_y, cell_w, cell_h);
+            // Half floats have enough precision and range for displaying
+            let tex = Texture2d::with_format(
+                display,
+                raw_image,
+                UncompressedFloatFormat::F16F16F16F16,
+                mipmaps,
+            )?;
+            Ok(CellTexture::Float(tex))
+        }
+    }
+}
+
+/// `img_samples` has to be an RGBA buffer
+fn img_rect<T: Clone + ToClientFormat>(
     img_w: u32,
-    img_h: u32,
-    img_bytes: &[u8],
+    img_samples: &[T],
     offset_x: u32,
     offset_y: u32,
     cell_w: u32,
     cell_h: u32,
-) -> Result<SrgbTexture2d> {
-    let raw_image = if img_w == cell_w {
+) -> RawImage2d<'_, T> {
+    if img_w == cell_w {
         assert!(offset_x == 0);
         let start = (offset_y as usize * img_w as usize) * 4;
         let end = start + (cell_h as usize * cell_w as usize * 4);
         RawImage2d {
-            data: Cow::Borrowed(&img_bytes[start..end]),
-            format: glium::texture::ClientFormat::U8U8U8U8,
+            data: Cow::Borrowed(&img_samples[start..end]),
+            format: T::rgba_format(),
             width: cell_w,
             height: cell_h,
         }
@@ -217,26 +391,13 @@ fn texture_from_img_rect(
         let mut cell_pixels = Vec::with_capacity(cell_size);
         for y in offset_y..(offset_y + cell_h) {
             // We multiply by four becase we need to convert from a pixel offset to
-            // a byte offset and each pixel is 4 bytes wide.
+            // a sample offset and each pixel has 4 samples.
             let start = (y as usize * img_w as usize + offset_x as usize) * 4;
             let end = start + (cell_w as usize * 4);
-            cell_pixels.extend_from_slice(&img_bytes[start..end]);
+            cell_pixels.extend_from_slice(&img_samples[start..end]);
         }
         RawImage2d::from_raw_rgba(cell_pixels, (cell_w, cell_h))
-    };
-
-    let x_pow = 31 - img_w.leading_zeros();
-    let y_pow = 31 - img_h.leading_zeros();
-
-    let max_mipmap_levels = x_pow.min(y_pow).min(4);
-
-    let mipmaps = if max_mipmap_levels == 1 {
-        MipmapsOption::NoMipmap
-    } else {
-        MipmapsOption::AutoGeneratedMipmapsMax(max_mipmap_levels)
-        //MipmapsOption::AutoGeneratedMipmaps
-    };
-    Ok(SrgbTexture2d::with_mipmaps(display, raw_image, mipmaps)?)
+    }
 }
 
 struct CachedTexture {
@@ -256,12 +417,33 @@ struct CachedTexture {
     /// - `true` if this failed to load,
     failed: bool,
 
+    /// How many times the animation is played, `None` if it's played forever
+    loop_count: Option<u32>,
+
+    /// The loaded page and the number of pages of the file
+    page: usize,
+    page_count: usize,
+
     /// If the target file is an image this vector will have a single texture once the
     /// image uploaded to the GPU. If the target file is an animated image like a gif,
     /// these the frames
     frames: Vec<AnimationFrameTexture>,
 }
 
+impl CachedTexture {
+    /// Removes the preview of the image, which is replaced by the frame.
+    /// Returns the size estimate of the preview.
+    fn remove_preview(&mut self) -> isize {
+        let preview = self.frames.first().is_some_and(|f| f.preview.is_some());
+        if !preview {
+            return 0;
+        }
+        let size_estimate = get_anim_size_estimate(&self.frames);
+        self.frames.clear();
+        size_estimate
+    }
+}
+
 /// The process of loading an image (or animation frame) consists of the following steps.
 /// Note that even still images are handled as 1 frame long animations as there is
 /// semantically no difference between those and this keeps the code relatively simple.
@@ -288,7 +470,11 @@ pub struct ImageCache {
 
     pending_requests: PendingRequests,
     texture_cache: BTreeMap<u32, CachedTexture>,
+    /// The request id of a file and the page that was selected in it
+    selected_page: Option<(u32, usize)>,
+    thumbnails: Thumbnails,
     loader: ImageLoader,
+    svg_renderer: SvgRenderer,
 }
 
 /// This is a store for the supported images loaded from a folder
@@ -311,17 +497,15 @@ impl ImageCache {
 
             pending_requests: PendingRequests::new(),
             texture_cache: BTreeMap::new(),
+            selected_page: None,
+            thumbnails: Thumbnails::new(),
             loader: ImageLoader::new(threads),
+            svg_renderer: SvgRenderer::new(),
         }
     }
 
-    pub fn current_filename(&self) -> Option<OsString> {
-        self.dir.curr_filename()
-    }
-
     pub fn current_file_path(&self) -> Option<PathBuf> {
-        self.current_filename()
-            .map(|name| self.dir.path().join(name))
+        self.dir.curr_descriptor().map(|desc| desc.path.clone())
     }
 
     /// Returns `None` when the directory hasn't finished filtering image files.
@@ -334,6 +518,17 @@ impl ImageCache {
         self.dir.image_count()
     }
 
+    /// Returns `None` when the directory hasn't finished filtering image files
+    /// or when there's no image at `index`.
+    pub fn image_path_at_index(&mut self, index: usize) -> Option<PathBuf> {
+        self.dir.image_by_index(index).map(|desc| desc.path.clone())
+    }
+
+    /// The index of the animation frame that was loaded last.
+    pub fn current_frame_index(&self) -> usize {
+        self.current_frame_idx
+    }
+
     fn curr_dir_item(&self) -> Result<DirItem> {
         if let Some(desc) = self.dir.curr_descriptor() {
             Ok(desc.clone())
@@ -356,6 +551,56 @@ impl ImageCache {
         false
     }
 
+    /// The index of the shown page of the current file and the number of its
+    /// pages. `None` if the file has a single page.
+    pub fn current_page(&self) -> Option<(usize, usize)> {
+        let desc = self.dir.curr_descriptor()?;
+        let img = self.texture_cache.get(&desc.request_id)?;
+        (img.page_count > 1).then_some((img.page, img.page_count))
+    }
+
+    /// Selects the page `amount` pages away from the selected page of the
+    /// current file, stopping at the first and the last page. Returns false
+    /// if the selected page didn't change.
+    pub fn jump_page(&mut self, amount: isize) -> bool {
+        let req_id = match self.dir.curr_descriptor() {
+            Some(desc) => desc.request_id,
+            None => return false,
+        };
+        let page_count = match self.current_page() {
+            Some((_, page_count)) => page_count,
+            None => return false,
+        };
+        let page = self.requested_page(req_id);
+        let target = (page as isize + amount).clamp(0, page_count as isize - 1);
+        if target as usize == page {
+            return false;
+        }
+        self.selected_page = Some((req_id, target as usize));
+        true
+    }
+
+    /// The page to load from the file of `req_id`. Only one file can have a
+    /// page other than the first selected at a time.
+    fn requested_page(&self, req_id: u32) -> usize {
+        match self.selected_page {
+            Some((selected_id, page)) if selected_id == req_id => page,
+            _ => 0,
+        }
+    }
+
+    /// The number of frames of the current animation and how many times it's
+    /// played. `None` if it's played forever or not fully loaded yet.
+    pub fn current_loop_limit(&self) -> Option<(usize, u32)> {
+        let desc = self.dir.curr_descriptor()?;
+        let img = self.texture_cache.get(&desc.request_id)?;
+        if !img.fully_loaded {
+            return None;
+        }
+        img.loop_count
+            .map(|loop_count| (img.frames.len(), loop_count))
+    }
+
     /// Fetches the contents of the folder and stores the list of image filenames to know which
     /// files will be the next and previous.
     ///
@@ -379,6 +624,203 @@ impl ImageCache {
         Ok(())
     }
 
+    /// Starts watching the folder of the images for changes. See
+    /// `Directory::watch_folders`
+    pub fn watch_folders(&mut self, wake: WakeFn) -> std::io::Result<()> {
+        self.dir.watch_folders(wake)
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.dir.is_watching()
+    }
+
+    /// Applies the changes in the folder since the last call. The textures
+    /// of the files that were modified since they were loaded are dropped, so
+    /// that they are loaded again.
+    pub fn process_folder_changes(&mut self) -> FolderChanges {
+        let mut changes = self.dir.process_watch_events();
+        let curr_req_id = self.dir.curr_descriptor().map(|d| d.request_id);
+        for DirItem { path, request_id } in &changes.modified {
+            self.thumbnails.remove(*request_id);
+            let texture = match self.texture_cache.get(request_id) {
+                Some(texture) => texture,
+                None => continue,
+            };
+            let mod_time = fs::metadata(path).and_then(|m| m.modified()).ok();
+            if mod_time.is_some() && mod_time == texture.mod_time {
+                continue;
+            }
+            self.remaining_capacity += get_anim_size_estimate(&texture.frames);
+            self.texture_cache.remove(request_id);
+            if curr_req_id == Some(*request_id) {
+                changes.current_changed = true;
+            }
+        }
+        changes
+    }
+
+    /// Includes the images in the subfolders of the current folder. See
+    /// `Directory::set_recursive`
+    pub fn set_recursive(&mut self, recursive: bool) -> Result<()> {
+        self.dir.set_recursive(recursive)?;
+        Ok(())
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.dir.recursive()
+    }
+
+    /// The folder whose subfolders are included, if any
+    pub fn recursive_root(&self) -> Option<&Path> {
+        self.dir.recursive_root()
+    }
+
+    /// Switches between the embedded previews of camera RAW files and
+    /// developing their sensor data. See `DEVELOP_RAW`
+    #[cfg(feature = "raw")]
+    pub fn set_develop_raw(&mut self, develop: bool) {
+        if DEVELOP_RAW.swap(develop, Ordering::SeqCst) == develop {
+            return;
+        }
+        // Only the RAW files were decoded differently, the other images can
+        // stay in the cache
+        let image_count = self.dir.image_count().unwrap_or(0);
+        let raw_ids: std::collections::HashSet<u32> = (0..image_count)
+            .filter_map(|index| {
+                let desc = self.dir.image_by_index(index)?;
+                is_raw_file(&desc.path).then_some(desc.request_id)
+            })
+            .collect();
+        for (req_id, request) in self.pending_requests.iter_mut() {
+            if raw_ids.contains(req_id) {
+                request.cancel();
+                self.loader.cancel_request(*req_id);
+            }
+        }
+        for req_id in raw_ids {
+            if let Some(tex) = self.texture_cache.remove(&req_id) {
+                self.remaining_capacity += get_anim_size_estimate(&tex.frames);
+            }
+        }
+    }
+
+    #[cfg(feature = "raw")]
+    pub fn develop_raw(&self) -> bool {
+        DEVELOP_RAW.load(Ordering::SeqCst)
+    }
+
+    /// Switches the conversion of images with an embedded ICC profile to the
+    /// colors of the display on or off. See `MANAGE_COLORS`
+    pub fn set_manage_colors(&mut self, manage: bool) {
+        if MANAGE_COLORS.swap(manage, Ordering::SeqCst) != manage {
+            self.clear_cache();
+        }
+    }
+
+    pub fn manage_colors(&self) -> bool {
+        MANAGE_COLORS.load(Ordering::SeqCst)
+    }
+
+    /// Renders the `region` of the current image again, with `scale` pixels
+    /// for each of its pixels. Does nothing if the current image isn't an SVG.
+    pub fn request_svg_render(&mut self, region: FrameRect, scale: f32) {
+        if let Some(path) = self.current_file_path() {
+            if is_svg(&path) {
+                self.svg_renderer.request(path, region, scale);
+            }
+        }
+    }
+
+    pub fn svg_render_pending(&self) -> bool {
+        self.svg_renderer.pending()
+    }
+
+    /// Uploads the latest SVG render and adds it to the frame of the current
+    /// image. Returns the frame along with the render if there's a new one.
+    pub fn receive_svg_render(
+        &mut self,
+        display: &glium::Display,
+    ) -> Result<Option<AnimationFrameTexture>> {
+        let render = match self.svg_renderer.receive() {
+            Some(render) => render,
+            None => return Ok(None),
+        };
+        let desc = match self.dir.curr_descriptor() {
+            Some(desc) if desc.path == render.path => desc,
+            // The image was changed while it was rendered
+            _ => return Ok(None),
+        };
+        let entry = match self.texture_cache.get_mut(&desc.request_id) {
+            Some(entry) if entry.fully_loaded && entry.frames.len() == 1 => {
+                entry
+            }
+            _ => return Ok(None),
+        };
+        let (w, h) = render.image.dimensions();
+        let image = FrameImage::from(render.image);
+        let tex = texture_from_img_rect(display, &image, 0, 0, w, h)?;
+        let detail = DetailTexture {
+            tex,
+            region: render.region,
+        };
+        let frame = AnimationFrameTexture {
+            detail: Some(Rc::new(detail)),
+            ..entry.frames[0].clone()
+        };
+        self.remaining_capacity += get_anim_size_estimate(&entry.frames);
+        entry.frames[0] = frame.clone();
+        self.remaining_capacity -= get_anim_size_estimate(&entry.frames);
+        Ok(Some(frame))
+    }
+
+    /// Sorts the images in the background. The current image stays current.
+    pub fn set_sort_order(&mut self, order: sort::SortOrder) -> Result<()> {
+        self.dir.set_sort_order(order)?;
+        Ok(())
+    }
+
+    /// `None` if the images are in their default order
+    pub fn sort_order(&self) -> Option<sort::SortOrder> {
+        self.dir.sort_order()
+    }
+
+    /// Returns the thumbnails of the images in `range`, along with their
+    /// indices. The thumbnails that are neither loaded nor loading are
+    /// requested from the loader, a few at a time.
+    pub fn thumbnails(
+        &mut self,
+        display: &glium::Display,
+        range: Range<usize>,
+    ) -> Vec<(usize, Thumbnail)> {
+        self.receive_prefetched();
+        self.thumbnails.upload(display);
+        let mut thumbnails = Vec::with_capacity(range.len());
+        for index in range {
+            let DirItem { path, request_id } =
+                match self.dir.image_by_index(index) {
+                    Some(desc) => desc.clone(),
+                    None => break,
+                };
+            let thumbnail = match self.thumbnails.get(request_id) {
+                Some(thumbnail) => thumbnail,
+                None if self.thumbnails.can_request() => {
+                    self.thumbnails.add_request(request_id);
+                    self.loader.send_load_request(LoadRequest {
+                        req_id: request_id,
+                        path,
+                        thumbnail_size: Some(THUMBNAIL_SIZE),
+                        page: 0,
+                        tile: None,
+                    });
+                    Thumbnail::Loading
+                }
+                None => Thumbnail::Loading,
+            };
+            thumbnails.push((index, thumbnail));
+        }
+        thumbnails
+    }
+
     pub fn load_at_index(
         &mut self,
         display: &glium::Display,
@@ -419,13 +861,13 @@ impl ImageCache {
         }
 
         let prev_img_index = self.dir.curr_img_index();
-        if let Some(target_file_name) = target_file_name {
-            self.change_directory_with_filename(&parent, &target_file_name)?;
+        if let Some(target_file_name) = &target_file_name {
+            self.change_directory_with_filename(&parent, target_file_name)?;
         } else {
             self.change_directory(&parent)?;
             self.current_frame_idx = 0;
         }
-        if self.dir.path() != parent {
+        if target_file_name.is_none() && !self.dir.is_folder(&parent) {
             let DirItem { path, request_id } = self.curr_dir_item()?;
             self.send_request_for_file(
                 path,
@@ -451,6 +893,7 @@ impl ImageCache {
                 retval
             }
         };
+        self.cancel_distant_requests();
         self.refresh_cache();
         self.try_getting_requested_image(display, requested_frame_id)
     }
@@ -555,6 +998,9 @@ impl ImageCache {
         use std::sync::mpsc::TryRecvError;
         loop {
             match self.loader.try_recv_prefetched() {
+                Ok(LoadResult::Thumbnail { req_id, image }) => {
+                    self.thumbnails.add_result(req_id, image);
+                }
                 Ok(load_result) => {
                     self.pending_requests.add_load_result(load_result);
                 }
@@ -620,6 +1066,15 @@ impl ImageCache {
             // And just let the next blok deal with locating the appropriate frame.
         }
 
+        // Drop the loaded page if another one was selected since
+        let page = self.requested_page(req_id);
+        if let Some(tex) = self.texture_cache.get(&req_id) {
+            if tex.fully_loaded && tex.page != page && page < tex.page_count {
+                self.remaining_capacity += get_anim_size_estimate(&tex.frames);
+                self.texture_cache.remove(&req_id);
+            }
+        }
+
         // Check if it is inside the texture cache first
         if let Some(tex) = self.texture_cache.get(&req_id) {
             if tex.failed {
@@ -695,6 +1150,9 @@ impl ImageCache {
                             fully_loaded: false,
                             mod_time: curr_mod_time,
                             failed: false,
+                            loop_count: None,
+                            page: 0,
+                            page_count: 1,
                             frames: Vec::new(),
                         });
                     }
@@ -714,6 +1172,9 @@ impl ImageCache {
                             self.remaining_capacity += old_size_estimate;
                             let mut_entry = entry.get_mut();
                             mut_entry.frames.clear();
+                            mut_entry.loop_count = None;
+                            mut_entry.page = 0;
+                            mut_entry.page_count = 1;
                             mut_entry.mod_time = curr_mod_time;
                         }
                     }
@@ -735,8 +1196,9 @@ impl ImageCache {
                 } else {
                     return Ok(None);
                 }
-                let size_estimate =
-                    get_image_size_estimate(image.width(), image.height());
+                let (w, h) = image.dimensions();
+                let hdr = matches!(image, FrameImage::Rgba32F(_));
+                let size_estimate = get_image_size_estimate(w, h, hdr);
                 if let Some(entry) = self.texture_cache.get_mut(&req_id) {
                     let anim_frame = AnimationFrameTexture::from_image(
                         display,
@@ -744,23 +1206,92 @@ impl ImageCache {
                         delay_nano,
                         orientation,
                     )?;
+                    self.remaining_capacity += entry.remove_preview();
                     entry.frames.push(anim_frame.clone());
                     self.remaining_capacity -= size_estimate;
                     return Ok(Some(anim_frame));
                 }
                 Ok(None)
             }
+            LoadResult::Preview {
+                req_id,
+                image,
+                full_size,
+                orientation,
+            } => {
+                if self.pending_requests.cancelled(&req_id) != Some(false) {
+                    return Ok(None);
+                }
+                match self.texture_cache.get_mut(&req_id) {
+                    // The frame is kept if the file was loaded before
+                    Some(entry) if entry.frames.is_empty() => {
+                        let anim_frame = AnimationFrameTexture::from_preview(
+                            display,
+                            image.into(),
+                            full_size,
+                            orientation,
+                        )?;
+                        self.remaining_capacity -= get_anim_size_estimate(
+                            slice::from_ref(&anim_frame),
+                        );
+                        entry.frames.push(anim_frame.clone());
+                        Ok(Some(anim_frame))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            LoadResult::Tiles {
+                req_id,
+                pyramid,
+                orientation,
+            } => {
+                if self.pending_requests.cancelled(&req_id) != Some(false) {
+                    return Ok(None);
+                }
+                if let Some(entry) = self.texture_cache.get_mut(&req_id) {
+                    let anim_frame = AnimationFrameTexture::from_tiles(
+                        display,
+                        pyramid,
+                        orientation,
+                        req_id,
+                        self.loader.request_sender(),
+                    )?;
+                    self.remaining_capacity += entry.remove_preview();
+                    self.remaining_capacity -=
+                        get_anim_size_estimate(slice::from_ref(&anim_frame));
+                    entry.frames.push(anim_frame.clone());
+                    return Ok(Some(anim_frame));
+                }
+                Ok(None)
+            }
+            LoadResult::LoopCount { req_id, loop_count } => {
+                if self.pending_requests.cancelled(&req_id) != Some(false) {
+                    return Ok(None);
+                }
+                if let Some(entry) = self.texture_cache.get_mut(&req_id) {
+                    entry.loop_count = Some(loop_count);
+                }
+                Ok(None)
+            }
+            LoadResult::Pages {
+                req_id,
+                page,
+                page_count,
+            } => {
+                if self.pending_requests.cancelled(&req_id) != Some(false) {
+                    return Ok(None);
+                }
+                if let Some(entry) = self.texture_cache.get_mut(&req_id) {
+                    entry.page = page;
+                    entry.page_count = page_count;
+                }
+                Ok(None)
+            }
             LoadResult::Done { req_id } => {
                 if let Some(tex) = self.texture_cache.get_mut(&req_id) {
                     tex.fully_loaded = true;
                 }
-                let _ = PRIORITY_REQUEST_ID.compare_exchange(
-                    req_id,
-                    NON_EXISTENT_REQUEST_ID,
-                    Ordering::SeqCst,
-                    Ordering::SeqCst,
-                );
-                self.pending_requests.set_finished(&req_id);
+                self.finish_request(req_id);
                 Ok(None)
             }
             LoadResult::Failed { req_id } => {
@@ -768,15 +1299,53 @@ impl ImageCache {
                     tex.fully_loaded = true;
                     tex.failed = true;
                 }
-                let _ = PRIORITY_REQUEST_ID.compare_exchange(
-                    req_id,
-                    NON_EXISTENT_REQUEST_ID,
-                    Ordering::SeqCst,
-                    Ordering::SeqCst,
-                );
-                self.pending_requests.set_finished(&req_id);
+                self.finish_request(req_id);
                 Err(errors::Error::FailedToLoadImage { req_id })
             }
+            LoadResult::Cancelled { req_id } => {
+                self.finish_request(req_id);
+                Ok(None)
+            }
+            // These are handled by `receive_prefetched`
+            LoadResult::Thumbnail { .. } => Ok(None),
+        }
+    }
+
+    /// Called with the last result of a request
+    fn finish_request(&mut self, req_id: u32) {
+        let _ = PRIORITY_REQUEST_ID.compare_exchange(
+            req_id,
+            NON_EXISTENT_REQUEST_ID,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+        self.pending_requests.set_finished(&req_id);
+        self.loader.finish_request(req_id);
+    }
+
+    /// Cancels the requests of the files other than the current one and the
+    /// ones after it that are prefetched. The partly loaded images of these
+    /// are removed from the cache.
+    fn cancel_distant_requests(&mut self) {
+        let curr_index = match self.dir.curr_img_index() {
+            Some(index) => index,
+            None => return,
+        };
+        let window = curr_index..curr_index + Self::MAX_PENDING_REQUESTS;
+        let nearby: Vec<u32> = window
+            .filter_map(|index| {
+                self.dir.image_by_index(index).map(|desc| desc.request_id)
+            })
+            .collect();
+        for (req_id, request) in self.pending_requests.iter_mut() {
+            if nearby.contains(req_id) {
+                continue;
+            }
+            request.cancel();
+            self.loader.cancel_request(*req_id);
+            if let Some(tex) = self.texture_cache.remove(req_id) {
+                self.remaining_capacity += get_anim_size_estimate(&tex.frames);
+            }
         }
     }
 
@@ -869,6 +1438,9 @@ impl ImageCache {
         let request = LoadRequest {
             req_id,
             path: file_path,
+            thumbnail_size: None,
+            page: self.requested_page(req_id),
+            tile: None,
         };
         self.pending_requests.add_request(request.clone());
         self.loader.send_load_request(request);
@@ -876,19 +1448,33 @@ impl ImageCache {
     }
 
     fn change_directory(&mut self, dir_path: &Path) -> Result<()> {
-        if self.dir.path() == dir_path {
+        if self.dir.is_folder(dir_path) {
             return Ok(());
         }
+        self.clear_cache();
+        self.dir.change_directory(dir_path)?;
+        Ok(())
+    }
+
+    /// Replaces the folder with a playlist of the files at `paths`, which
+    /// must be absolute. Next, previous and the indices follow the order of
+    /// `paths`.
+    pub fn open_playlist(&mut self, paths: Vec<PathBuf>) -> Result<()> {
+        self.clear_cache();
+        self.current_frame_idx = 0;
+        self.dir.open_playlist(paths)?;
+        Ok(())
+    }
+
+    fn clear_cache(&mut self) {
         self.texture_cache.clear();
         self.remaining_capacity = self.total_capacity;
 
         // Cancel all pending load requests
-        for (_, request) in self.pending_requests.iter_mut() {
+        for (req_id, request) in self.pending_requests.iter_mut() {
             request.cancel();
+            self.loader.cancel_request(*req_id);
         }
-
-        self.dir.change_directory(dir_path)?;
-        Ok(())
     }
 
     fn change_directory_with_filename(
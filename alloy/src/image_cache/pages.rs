@@ -0,0 +1,226 @@
+//! Multi-page TIFF files and ICO or CUR files with several images.
+//!
+//! `image` only decodes one image of these files, the first page of a TIFF
+//! and the largest image of an ICO. The other pages are decoded by presenting
+//! them to `image` as if they were the only one in the file.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use gelatin::image::{
+    self,
+    codecs::{ico::IcoDecoder, tiff::TiffDecoder},
+    DynamicImage, ImageFormat,
+};
+
+use super::image_loader::errors::*;
+
+/// Files with more pages than this are assumed to be broken
+const MAX_PAGES: usize = 10_000;
+const ICO_HEADER_LEN: usize = 6;
+const ICO_ENTRY_LEN: usize = 16;
+
+/// True for the formats whose files may have several pages
+pub fn has_pages(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Tiff | ImageFormat::Ico)
+}
+
+/// A CUR file is an ICO file with a different type in its header
+pub fn is_cur(path: &Path, file_start_bytes: &[u8]) -> bool {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    file_start_bytes.starts_with(&[0, 0, 2, 0])
+        && extension.is_some_and(|ext| ext.eq_ignore_ascii_case("cur"))
+}
+
+/// Returns the number of pages along with the decoded page. `page` is
+/// clamped to the pages of the file.
+pub fn load_page(
+    path: &Path,
+    format: ImageFormat,
+    page: usize,
+) -> Result<(image::RgbaImage, usize, usize)> {
+    let mut file = fs::File::open(path)?;
+    match format {
+        ImageFormat::Tiff => {
+            let (big_endian, offsets) = match tiff_page_offsets(&mut file)? {
+                Some(pages) => pages,
+                None => {
+                    // The offsets of BigTIFF files don't fit into the header
+                    // of a regular TIFF, so only their first page is shown
+                    file.rewind()?;
+                    let reader = BufReader::new(file);
+                    let image = image::load(reader, ImageFormat::Tiff)?;
+                    return Ok((image.into_rgba8(), 0, 1));
+                }
+            };
+            let page = page.min(offsets.len() - 1);
+            let first_ifd = if big_endian {
+                offsets[page].to_be_bytes()
+            } else {
+                offsets[page].to_le_bytes()
+            };
+            file.rewind()?;
+            let reader = FirstIfdReader {
+                inner: BufReader::new(file),
+                position: 0,
+                first_ifd,
+            };
+            let image = DynamicImage::from_decoder(TiffDecoder::new(reader)?)?;
+            Ok((image.into_rgba8(), page, offsets.len()))
+        }
+        ImageFormat::Ico => {
+            let entries = ico_entries(&mut file)?;
+            let page = page.min(entries.len() - 1);
+            let entry = &entries[page];
+            let file_len = file.metadata()?.len();
+            if entry.offset as u64 + entry.length as u64 > file_len {
+                return Err(Error::Msg(
+                    "The ICO image extends past the end of the file".into(),
+                ));
+            }
+            let mut data = Vec::with_capacity(
+                ICO_HEADER_LEN + ICO_ENTRY_LEN + entry.length as usize,
+            );
+            data.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+            data.extend_from_slice(&entry.raw[..4]);
+            // The color planes and bits per pixel, or the hotspot of a cursor
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&entry.length.to_le_bytes());
+            let offset = (ICO_HEADER_LEN + ICO_ENTRY_LEN) as u32;
+            data.extend_from_slice(&offset.to_le_bytes());
+            file.seek(SeekFrom::Start(entry.offset as u64))?;
+            file.take(entry.length as u64).read_to_end(&mut data)?;
+            let decoder = IcoDecoder::new(Cursor::new(data))?;
+            let image = DynamicImage::from_decoder(decoder)?;
+            Ok((image.into_rgba8(), page, entries.len()))
+        }
+        _ => Err(Error::Msg(format!("{format:?} files have no pages"))),
+    }
+}
+
+/// Returns the byte order of the file and the offsets of the IFDs of its
+/// pages, or `None` for a BigTIFF file
+pub fn tiff_page_offsets(
+    file: &mut fs::File,
+) -> io::Result<Option<(bool, Vec<u32>)>> {
+    let mut header = [0; 8];
+    file.read_exact(&mut header)?;
+    let big_endian = match &header[..2] {
+        b"II" => false,
+        b"MM" => true,
+        _ => return Err(invalid_data("Not a TIFF file")),
+    };
+    let u16_from = |bytes: [u8; 2]| {
+        if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    };
+    let u32_from = |bytes: [u8; 4]| {
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    if u16_from([header[2], header[3]]) != 42 {
+        return Ok(None);
+    }
+    let first = u32_from([header[4], header[5], header[6], header[7]]);
+    let mut offsets = vec![first];
+    let mut visited = HashSet::from([first]);
+    while offsets.len() < MAX_PAGES {
+        let offset = offsets[offsets.len() - 1];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut count = [0; 2];
+        file.read_exact(&mut count)?;
+        file.seek(SeekFrom::Current(u16_from(count) as i64 * 12))?;
+        let mut next = [0; 4];
+        if file.read_exact(&mut next).is_err() {
+            break;
+        }
+        let next = u32_from(next);
+        if next == 0 || !visited.insert(next) {
+            break;
+        }
+        offsets.push(next);
+    }
+    Ok(Some((big_endian, offsets)))
+}
+
+/// Reads a TIFF file as if the IFD at `first_ifd` was its first one
+struct FirstIfdReader<R> {
+    inner: R,
+    position: u64,
+    /// The offset in the byte order of the file
+    first_ifd: [u8; 4],
+}
+
+impl<R: Read> Read for FirstIfdReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        // The offset of the first IFD is stored at bytes 4 to 8
+        let start = self.position.max(4);
+        let end = (self.position + count as u64).min(8);
+        for position in start..end {
+            buf[(position - self.position) as usize] =
+                self.first_ifd[(position - 4) as usize];
+        }
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Seek> Seek for FirstIfdReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+struct IcoEntry {
+    /// The entry as it's stored in the file
+    raw: [u8; ICO_ENTRY_LEN],
+    length: u32,
+    offset: u32,
+}
+
+impl IcoEntry {
+    /// The images are ordered the same way `image` picks the best one
+    fn sort_key(&self) -> (u16, u32) {
+        let size = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+        let bits_per_pixel = u16::from_le_bytes([self.raw[6], self.raw[7]]);
+        (bits_per_pixel, size(self.raw[0]) * size(self.raw[1]))
+    }
+}
+
+/// Returns the entries of the images, the best one first
+fn ico_entries(file: &mut fs::File) -> io::Result<Vec<IcoEntry>> {
+    let mut header = [0; ICO_HEADER_LEN];
+    file.read_exact(&mut header)?;
+    let count = u16::from_le_bytes([header[4], header[5]]) as usize;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        let mut raw = [0; ICO_ENTRY_LEN];
+        file.read_exact(&mut raw)?;
+        entries.push(IcoEntry {
+            raw,
+            length: u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]),
+            offset: u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]]),
+        });
+    }
+    if entries.is_empty() {
+        return Err(invalid_data("The ICO file has no images"));
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.sort_key()));
+    Ok(entries)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
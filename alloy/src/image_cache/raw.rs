@@ -0,0 +1,321 @@
+//! Viewing camera RAW files.
+//!
+//! The RAW formats that are supported are all based on TIFF and contain a
+//! JPEG preview of the photo as the camera developed it. Decoding the preview
+//! is a lot faster than developing the sensor data, so that's what's shown by
+//! default. The sensor data is only developed when `DEVELOP_RAW` is set.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::atomic::Ordering,
+};
+
+use gelatin::image::{self, ImageFormat};
+
+use super::image_loader::{errors::*, Orientation, DEVELOP_RAW};
+
+pub const RAW_EXTENSIONS: [&str; 5] = ["cr2", "nef", "nrw", "arw", "dng"];
+
+/// Files with more IFDs than this are assumed to be broken
+const MAX_IFDS: usize = 64;
+
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_PHOTOMETRIC: u16 = 0x106;
+const TAG_STRIP_OFFSETS: u16 = 0x111;
+const TAG_ORIENTATION: u16 = 0x112;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x117;
+const TAG_SUB_IFDS: u16 = 0x14a;
+const TAG_JPEG_OFFSET: u16 = 0x201;
+const TAG_JPEG_LENGTH: u16 = 0x202;
+
+/// The `Compression` values of JPEG data
+const COMPRESSION_JPEG: [u32; 2] = [6, 7];
+/// The `PhotometricInterpretation` values of sensor data
+const PHOTOMETRIC_RAW: [u32; 2] = [32803, 34892];
+
+pub fn is_raw_file(path: &Path) -> bool {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    extension.is_some_and(|ext| {
+        RAW_EXTENSIONS
+            .iter()
+            .any(|raw_ext| ext.eq_ignore_ascii_case(raw_ext))
+    })
+}
+
+/// Loads the embedded preview, or develops the sensor data if `DEVELOP_RAW`
+/// is set
+pub fn load_raw(path: &Path) -> Result<(image::RgbaImage, Orientation)> {
+    if DEVELOP_RAW.load(Ordering::SeqCst) {
+        develop_raw(path)
+    } else {
+        load_raw_preview(path)
+    }
+}
+
+/// Decodes the largest JPEG preview within the file. The orientation is the
+/// one of the RAW file, the previews don't have their own.
+pub fn load_raw_preview(
+    path: &Path,
+) -> Result<(image::RgbaImage, Orientation)> {
+    let mut file = fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let tiff = read_tiff_structure(&mut file)?;
+    let mut previews = tiff.previews;
+    previews.retain(|&(offset, length)| {
+        length > 0 && offset.saturating_add(length) <= file_len
+    });
+    previews.sort_unstable_by_key(|&(_, length)| std::cmp::Reverse(length));
+    for (offset, length) in previews {
+        let mut data = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        // The sensor data of some formats is stored as lossless JPEG
+        if !is_lossy_jpeg(&data) {
+            continue;
+        }
+        match image::load_from_memory_with_format(&data, ImageFormat::Jpeg) {
+            Ok(image) => return Ok((image.into_rgba8(), tiff.orientation)),
+            Err(e) => eprintln!("Could not decode a preview in {path:?}: {e}"),
+        }
+    }
+    Err(Error::Msg("The RAW file has no usable preview".into()))
+}
+
+/// Demosaics the sensor data and applies the color processing of the camera.
+/// The result is already rotated.
+fn develop_raw(path: &Path) -> Result<(image::RgbaImage, Orientation)> {
+    let developed =
+        imagepipe::simple_decode_8bit(path, 0, 0).map_err(Error::Msg)?;
+    let mut pixels = Vec::with_capacity(developed.width * developed.height * 4);
+    for rgb in developed.data.chunks_exact(3) {
+        pixels.extend_from_slice(rgb);
+        pixels.push(255);
+    }
+    let image = image::RgbaImage::from_raw(
+        developed.width as u32,
+        developed.height as u32,
+        pixels,
+    )
+    .ok_or_else(|| Error::Msg("Invalid size of the developed image".into()))?;
+    Ok((image, Orientation::Deg0))
+}
+
+struct TiffStructure {
+    orientation: Orientation,
+    /// The offset and the length of each JPEG within the file
+    previews: Vec<(u64, u64)>,
+}
+
+/// Walks the IFDs of the file, including the ones referred to as SubIFDs
+fn read_tiff_structure(file: &mut fs::File) -> io::Result<TiffStructure> {
+    let mut header = [0; 8];
+    file.read_exact(&mut header)?;
+    let big_endian = match &header[..2] {
+        b"II" => false,
+        b"MM" => true,
+        _ => return Err(invalid_data("Not a TIFF based RAW file")),
+    };
+    let reader = TiffReader { big_endian };
+    if reader.u16(&header[2..4]) != 42 {
+        return Err(invalid_data("Not a TIFF based RAW file"));
+    }
+
+    let mut structure = TiffStructure {
+        orientation: Orientation::Deg0,
+        previews: Vec::new(),
+    };
+    let mut queue = vec![reader.u32(&header[4..8])];
+    let mut visited = HashSet::new();
+    while let Some(ifd_offset) = queue.pop() {
+        if ifd_offset == 0 || !visited.insert(ifd_offset) {
+            continue;
+        }
+        if visited.len() > MAX_IFDS {
+            break;
+        }
+        let is_first = visited.len() == 1;
+        // A broken IFD doesn't make the previews in the others unusable
+        let ifd = match reader.read_ifd(file, ifd_offset) {
+            Ok(ifd) => ifd,
+            Err(_) => continue,
+        };
+        let value = |tag| ifd.entries.iter().find(|entry| entry.tag == tag);
+        let mut first_value = |tag| {
+            value(tag).and_then(|entry| {
+                reader.values(file, entry).ok()?.first().copied()
+            })
+        };
+        if is_first {
+            if let Some(orientation) = first_value(TAG_ORIENTATION)
+                .and_then(|value| Orientation::from_exif(value as u16))
+            {
+                structure.orientation = orientation;
+            }
+        }
+        if let (Some(offset), Some(length)) =
+            (first_value(TAG_JPEG_OFFSET), first_value(TAG_JPEG_LENGTH))
+        {
+            structure.previews.push((offset as u64, length as u64));
+        }
+        let compression = first_value(TAG_COMPRESSION);
+        let photometric = first_value(TAG_PHOTOMETRIC);
+        let is_jpeg =
+            compression.is_some_and(|c| COMPRESSION_JPEG.contains(&c));
+        let is_sensor_data =
+            photometric.is_some_and(|p| PHOTOMETRIC_RAW.contains(&p));
+        if is_jpeg && !is_sensor_data {
+            let offsets = value(TAG_STRIP_OFFSETS)
+                .and_then(|entry| reader.values(file, entry).ok());
+            let lengths = value(TAG_STRIP_BYTE_COUNTS)
+                .and_then(|entry| reader.values(file, entry).ok());
+            // Only JPEGs that are stored in a single strip are complete
+            if let (Some([offset]), Some([length])) =
+                (offsets.as_deref(), lengths.as_deref())
+            {
+                structure.previews.push((*offset as u64, *length as u64));
+            }
+        }
+        if let Some(entry) = value(TAG_SUB_IFDS) {
+            if let Ok(sub_ifds) = reader.values(file, entry) {
+                queue.extend(sub_ifds);
+            }
+        }
+        queue.push(ifd.next);
+    }
+    Ok(structure)
+}
+
+struct IfdEntry {
+    tag: u16,
+    field_type: u16,
+    count: u32,
+    /// The value itself if it fits into 4 bytes, its offset otherwise
+    value: [u8; 4],
+}
+
+struct Ifd {
+    entries: Vec<IfdEntry>,
+    next: u32,
+}
+
+struct TiffReader {
+    big_endian: bool,
+}
+
+impl TiffReader {
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    fn read_ifd(&self, file: &mut fs::File, offset: u32) -> io::Result<Ifd> {
+        file.seek(SeekFrom::Start(offset as u64))?;
+        let mut count = [0; 2];
+        file.read_exact(&mut count)?;
+        let count = self.u16(&count) as usize;
+        let mut data = vec![0; count * 12 + 4];
+        file.read_exact(&mut data)?;
+        let entries = data
+            .chunks_exact(12)
+            .map(|entry| IfdEntry {
+                tag: self.u16(&entry[0..2]),
+                field_type: self.u16(&entry[2..4]),
+                count: self.u32(&entry[4..8]),
+                value: [entry[8], entry[9], entry[10], entry[11]],
+            })
+            .collect();
+        let next = self.u32(&data[count * 12..]);
+        Ok(Ifd { entries, next })
+    }
+
+    /// Reads the values of an entry of the `SHORT`, `LONG` or `IFD` type
+    fn values(
+        &self,
+        file: &mut fs::File,
+        entry: &IfdEntry,
+    ) -> io::Result<Vec<u32>> {
+        let size = match entry.field_type {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return Err(invalid_data("Unexpected TIFF field type")),
+        };
+        let count = entry.count as usize;
+        if count > MAX_IFDS * 1024 {
+            return Err(invalid_data("Too many TIFF values"));
+        }
+        let data = if count * size <= 4 {
+            entry.value[..count * size].to_vec()
+        } else {
+            let mut data = vec![0; count * size];
+            file.seek(SeekFrom::Start(self.u32(&entry.value) as u64))?;
+            file.read_exact(&mut data)?;
+            data
+        };
+        Ok(data
+            .chunks_exact(size)
+            .map(|value| {
+                if size == 2 {
+                    self.u16(value) as u32
+                } else {
+                    self.u32(value)
+                }
+            })
+            .collect())
+    }
+}
+
+/// Returns true if `data` is a baseline or progressive JPEG, which are the
+/// ones that can be decoded
+fn is_lossy_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return false;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xff {
+            return false;
+        }
+        let marker = data[pos + 1];
+        match marker {
+            // Fill bytes
+            0xff => {
+                pos += 1;
+                continue;
+            }
+            0xc0..=0xc2 => return true,
+            // The other start of frame markers and the start of scan
+            0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf | 0xda => {
+                return false
+            }
+            // Markers without a length
+            0x01 | 0xd0..=0xd7 => {
+                pos += 2;
+                continue;
+            }
+            _ => (),
+        }
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]);
+        pos += 2 + length as usize;
+    }
+    false
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
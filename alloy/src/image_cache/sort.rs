@@ -0,0 +1,225 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use gelatin::image;
+use rand::{seq::SliceRandom, thread_rng};
+
+/// The property the files are sorted by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// The natural order of the file names, or of the paths relative to the
+    /// folder in recursive mode
+    #[default]
+    Name,
+    Modified,
+    Size,
+    /// The `DateTimeOriginal` EXIF tag
+    ExifDate,
+    /// The number of pixels
+    Dimensions,
+    Extension,
+    Shuffle,
+}
+
+impl SortKey {
+    const ALL: [SortKey; 7] = [
+        SortKey::Name,
+        SortKey::Modified,
+        SortKey::Size,
+        SortKey::ExifDate,
+        SortKey::Dimensions,
+        SortKey::Extension,
+        SortKey::Shuffle,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Modified => "modified",
+            SortKey::Size => "size",
+            SortKey::ExifDate => "exif_date",
+            SortKey::Dimensions => "dimensions",
+            SortKey::Extension => "extension",
+            SortKey::Shuffle => "shuffle",
+        }
+    }
+
+    /// The key after this one, wrapping around at the end
+    pub fn next(self) -> SortKey {
+        let index = SortKey::ALL.iter().position(|&k| k == self).unwrap();
+        SortKey::ALL[(index + 1) % SortKey::ALL.len()]
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.key.name())?;
+        if self.descending && self.key != SortKey::Shuffle {
+            f.write_str("_desc")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    /// Parses the name of a key, optionally followed by `_asc` or `_desc`,
+    /// for example `modified_desc`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key_name, descending) = if let Some(key) = s.strip_suffix("_desc")
+        {
+            (key, true)
+        } else {
+            (s.strip_suffix("_asc").unwrap_or(s), false)
+        };
+        match SortKey::ALL.iter().find(|k| k.name() == key_name) {
+            Some(&key) => Ok(SortOrder { key, descending }),
+            None => {
+                let names = SortKey::ALL.map(|k| k.name()).join("`, `");
+                Err(format!(
+                    "invalid sort order `{s}`, expected one of `{names}`, optionally followed by `_asc` or `_desc`"
+                ))
+            }
+        }
+    }
+}
+
+fn exif_date(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    let mut bufreader = std::io::BufReader::new(&file);
+    let exif = exif::Reader::new()
+        .read_from_container(&mut bufreader)
+        .ok()?;
+    let field =
+        exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    match &field.value {
+        // The format is "YYYY:MM:DD HH:MM:SS", which sorts correctly as text
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|date| String::from_utf8_lossy(date).into_owned()),
+        _ => None,
+    }
+}
+
+/// The name that the files are ordered by when they are sorted by name
+pub fn sort_name(path: &Path, root: Option<&Path>) -> String {
+    match root.and_then(|root| path.strip_prefix(root).ok()) {
+        Some(rel_path) => rel_path.to_string_lossy().into_owned(),
+        None => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// The value of the property that a file is sorted by. Only values of the
+/// same key are ever compared.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SortValue {
+    /// Every file has the same value when sorting by name, so they are only
+    /// ordered by their name
+    Name,
+    Time(SystemTime),
+    Number(u64),
+    Text(String),
+}
+
+/// Reads the value of `key` for the file at `path`. Returns `None` if the
+/// file doesn't have a value, for example a file without EXIF data when
+/// sorting by EXIF date, and for `SortKey::Shuffle`.
+///
+/// This may read the header of the file, so it should not be called for many
+/// files on the main thread.
+pub fn sort_value(path: &Path, key: SortKey) -> Option<SortValue> {
+    match key {
+        SortKey::Name => Some(SortValue::Name),
+        SortKey::Modified => fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(SortValue::Time),
+        SortKey::Size => {
+            fs::metadata(path).ok().map(|m| SortValue::Number(m.len()))
+        }
+        SortKey::ExifDate => exif_date(path).map(SortValue::Text),
+        SortKey::Dimensions => image::image_dimensions(path)
+            .ok()
+            .map(|(w, h)| SortValue::Number(w as u64 * h as u64)),
+        SortKey::Extension => path
+            .extension()
+            .map(|ext| SortValue::Text(ext.to_string_lossy().to_lowercase())),
+        SortKey::Shuffle => None,
+    }
+}
+
+/// Compares two files by their values and names. Files without a value come
+/// last and files with the same value are ordered by name.
+pub fn compare(
+    (a_value, a_name): (Option<&SortValue>, &str),
+    (b_value, b_name): (Option<&SortValue>, &str),
+    descending: bool,
+) -> Ordering {
+    let by_name = || lexical_sort::natural_lexical_cmp(a_name, b_name);
+    match (a_value, b_value) {
+        (Some(a), Some(b)) => {
+            let ordering = a.cmp(b).then_with(by_name);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => by_name(),
+    }
+}
+
+/// Sorts `paths` according to `order` and returns the value of each file. The
+/// names are compared relative to `root` if it's given.
+///
+/// This reads the metadata or even the header of every file, so it should not
+/// be called on the main thread.
+pub fn sort_paths(
+    paths: &mut Vec<PathBuf>,
+    order: SortOrder,
+    root: Option<&Path>,
+) -> HashMap<PathBuf, SortValue> {
+    if order.key == SortKey::Shuffle {
+        paths.shuffle(&mut thread_rng());
+        return HashMap::new();
+    }
+    let mut items = paths
+        .drain(..)
+        .map(|path| {
+            (sort_value(&path, order.key), sort_name(&path, root), path)
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|(a_value, a_name, _), (b_value, b_name, _)| {
+        compare(
+            (a_value.as_ref(), a_name),
+            (b_value.as_ref(), b_name),
+            order.descending,
+        )
+    });
+    let mut values = HashMap::with_capacity(items.len());
+    for (value, _, path) in items {
+        if let Some(value) = value {
+            values.insert(path.clone(), value);
+        }
+        paths.push(path);
+    }
+    values
+}
@@ -0,0 +1,216 @@
+//! Rendering of SVG images at the zoom level they are shown at.
+//!
+//! An SVG is rasterised into a frame at its own size (see `load_svg`). When
+//! it's zoomed in beyond that, the visible part of it is rendered again at
+//! the zoom level and drawn over the frame. The parsed trees are kept on the
+//! thread of the `SvgRenderer`, because they can't be sent to other threads.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    thread,
+    time::SystemTime,
+};
+
+use gelatin::image;
+use resvg::{
+    tiny_skia::{Pixmap, Transform},
+    usvg::{self, TreeParsing},
+    Tree,
+};
+
+use super::image_loader::errors::*;
+
+/// The longest edge of the frame of an SVG and of the rendered parts of it
+pub const MAX_SVG_SIZE: f32 = 4096.0;
+
+/// The number of parsed trees that are kept around
+const TREE_CACHE_LEN: usize = 4;
+
+pub fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+}
+
+/// How much an SVG of `size` is scaled when it's rasterised into a frame.
+/// Small images are kept at their own size, large ones are scaled down.
+pub fn frame_zoom(size: usvg::Size) -> f32 {
+    (MAX_SVG_SIZE / size.width().max(size.height())).min(1.0)
+}
+
+/// A rectangle in the pixels of a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameRect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+/// The part of an SVG that was rendered
+pub struct SvgRender {
+    pub path: PathBuf,
+    pub image: image::RgbaImage,
+    /// The part of the frame that `image` covers
+    pub region: FrameRect,
+}
+
+struct RenderRequest {
+    id: u32,
+    path: PathBuf,
+    region: FrameRect,
+    /// The number of rendered pixels per frame pixel
+    scale: f32,
+}
+
+pub struct SvgRenderer {
+    request_tx: Sender<RenderRequest>,
+    render_rx: Receiver<(u32, Option<SvgRender>)>,
+    last_request_id: u32,
+    last_render_id: u32,
+}
+
+impl SvgRenderer {
+    pub fn new() -> SvgRenderer {
+        let (request_tx, request_rx) = channel();
+        let (render_tx, render_rx) = channel();
+        // The thread stops once the renderer is dropped
+        thread::spawn(move || render_loop(request_rx, render_tx));
+        SvgRenderer {
+            request_tx,
+            render_rx,
+            last_request_id: 0,
+            last_render_id: 0,
+        }
+    }
+
+    /// Renders the `region` of the frame of the SVG at `path` with `scale`
+    /// pixels for each pixel of the frame. Earlier requests that haven't been
+    /// started yet are dropped.
+    pub fn request(&mut self, path: PathBuf, region: FrameRect, scale: f32) {
+        self.last_request_id += 1;
+        let request = RenderRequest {
+            id: self.last_request_id,
+            path,
+            region,
+            scale,
+        };
+        if self.request_tx.send(request).is_err() {
+            eprintln!("The SVG renderer has stopped");
+        }
+    }
+
+    /// Returns the last finished render, if there's a new one
+    pub fn receive(&mut self) -> Option<SvgRender> {
+        let mut latest = None;
+        loop {
+            match self.render_rx.try_recv() {
+                Ok((id, render)) => {
+                    self.last_render_id = id;
+                    latest = render.or(latest);
+                }
+                Err(TryRecvError::Empty) => return latest,
+                Err(TryRecvError::Disconnected) => {
+                    self.last_render_id = self.last_request_id;
+                    return latest;
+                }
+            }
+        }
+    }
+
+    /// True if the last request hasn't been rendered yet
+    pub fn pending(&self) -> bool {
+        self.last_render_id != self.last_request_id
+    }
+}
+
+fn render_loop(
+    request_rx: Receiver<RenderRequest>,
+    render_tx: Sender<(u32, Option<SvgRender>)>,
+) {
+    let mut trees = TreeCache::default();
+    while let Ok(mut request) = request_rx.recv() {
+        // Only the latest view matters
+        while let Ok(newer) = request_rx.try_recv() {
+            request = newer;
+        }
+        let render = trees.get(&request.path).and_then(|tree| {
+            render_region(tree, request.region, request.scale)
+        });
+        let render = match render {
+            Ok((image, region)) => Some(SvgRender {
+                path: request.path,
+                image,
+                region,
+            }),
+            Err(e) => {
+                eprintln!("Could not render {:?}: {e}", request.path);
+                None
+            }
+        };
+        if render_tx.send((request.id, render)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Renders `region` of the frame of the SVG. Returns the image along with
+/// the region that it covers exactly, which is `region` aligned to the
+/// rendered pixels.
+fn render_region(
+    tree: &Tree,
+    region: FrameRect,
+    scale: f32,
+) -> Result<(image::RgbaImage, FrameRect)> {
+    let scale = scale
+        .min(MAX_SVG_SIZE / region.w)
+        .min(MAX_SVG_SIZE / region.h);
+    let left = (region.x * scale).floor();
+    let top = (region.y * scale).floor();
+    let width = ((region.x + region.w) * scale).ceil() - left;
+    let height = ((region.y + region.h) * scale).ceil() - top;
+    let mut pixmap = Pixmap::new(width.max(1.0) as u32, height.max(1.0) as u32)
+        .ok_or_else(|| Error::Msg("Invalid SVG region".into()))?;
+    let zoom = frame_zoom(tree.size) * scale;
+    let transform = Transform::from_row(zoom, 0.0, 0.0, zoom, -left, -top);
+    tree.render(transform, &mut pixmap.as_mut());
+    let rendered = FrameRect {
+        x: left / scale,
+        y: top / scale,
+        w: pixmap.width() as f32 / scale,
+        h: pixmap.height() as f32 / scale,
+    };
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let image = image::RgbaImage::from_raw(width, height, pixmap.take())
+        .ok_or_else(|| Error::Msg("Invalid SVG render size".into()))?;
+    Ok((image, rendered))
+}
+
+/// The most recently used trees, the latest first
+#[derive(Default)]
+struct TreeCache {
+    trees: Vec<(PathBuf, Option<SystemTime>, Tree)>,
+}
+
+impl TreeCache {
+    /// Parses the file unless its tree is in the cache already
+    fn get(&mut self, path: &Path) -> Result<&Tree> {
+        let mod_time = fs::metadata(path)?.modified().ok();
+        let cached = self.trees.iter().position(|(tree_path, tree_time, _)| {
+            tree_path == path && *tree_time == mod_time
+        });
+        let entry = match cached {
+            Some(index) => self.trees.remove(index),
+            None => {
+                let data = fs::read(path)?;
+                let tree =
+                    usvg::Tree::from_data(&data, &usvg::Options::default())?;
+                (path.to_owned(), mod_time, Tree::from_usvg(&tree))
+            }
+        };
+        self.trees.insert(0, entry);
+        self.trees.truncate(TREE_CACHE_LEN);
+        Ok(&self.trees[0].2)
+    }
+}
@@ -0,0 +1,247 @@
+//! Reads and writes thumbnails in the shared thumbnail cache described by the
+//! freedesktop.org Thumbnail Managing Standard, so that the thumbnails are
+//! shared with file managers and other image viewers.
+//!
+//! A thumbnail is a PNG file in `~/.cache/thumbnails/normal` (at most 128
+//! pixels) or `~/.cache/thumbnails/large` (at most 256 pixels), named after
+//! the MD5 hash of the URI of the image. The `Thumb::URI` and `Thumb::MTime`
+//! text chunks tell which file the thumbnail belongs to and whether it's still
+//! up to date.
+
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use gelatin::image::{self, ImageFormat};
+use lazy_static::lazy_static;
+use log::debug;
+
+use super::image_loader::{errors::*, fit_to_size, load_thumbnail};
+
+const URI_KEY: &str = "Thumb::URI";
+const MTIME_KEY: &str = "Thumb::MTime";
+const SIZE_KEY: &str = "Thumb::Size";
+const SOFTWARE_KEY: &str = "Software";
+
+lazy_static! {
+    static ref THUMBNAILS_DIR: Option<PathBuf> = thumbnails_dir();
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn thumbnails_dir() -> Option<PathBuf> {
+    // `cache_dir` is `$XDG_CACHE_HOME` or `~/.cache`
+    directories_next::BaseDirs::new()
+        .map(|dirs| dirs.cache_dir().join("thumbnails"))
+}
+
+/// The standard only applies to freedesktop.org systems
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn thumbnails_dir() -> Option<PathBuf> {
+    None
+}
+
+/// The size classes of the standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ThumbnailSize {
+    Normal,
+    Large,
+}
+
+impl ThumbnailSize {
+    /// The smallest size class that holds thumbnails of `max_size` pixels
+    fn for_max_size(max_size: u32) -> Option<ThumbnailSize> {
+        if max_size <= ThumbnailSize::Normal.pixels() {
+            Some(ThumbnailSize::Normal)
+        } else if max_size <= ThumbnailSize::Large.pixels() {
+            Some(ThumbnailSize::Large)
+        } else {
+            None
+        }
+    }
+
+    fn pixels(self) -> u32 {
+        match self {
+            ThumbnailSize::Normal => 128,
+            ThumbnailSize::Large => 256,
+        }
+    }
+
+    fn dir_name(self) -> &'static str {
+        match self {
+            ThumbnailSize::Normal => "normal",
+            ThumbnailSize::Large => "large",
+        }
+    }
+}
+
+/// Loads the thumbnail of the image at `path` from the cache if it's up to
+/// date. Otherwise the thumbnail is created and stored in the cache.
+///
+/// The thumbnail fits into a square with sides of `max_size` pixels.
+pub fn load_cached_thumbnail(
+    path: &Path,
+    max_size: u32,
+) -> Result<image::RgbaImage> {
+    let size_class = ThumbnailSize::for_max_size(max_size);
+    let (dir, size) = match (&*THUMBNAILS_DIR, size_class) {
+        // Thumbnails of thumbnails are not stored
+        (Some(dir), Some(size)) if !path.starts_with(dir) => {
+            (dir.join(size.dir_name()), size)
+        }
+        _ => return load_thumbnail(path, max_size),
+    };
+    let path = if path.is_absolute() {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(path.canonicalize()?)
+    };
+    let uri = file_uri(&path);
+    let mtime = fs::metadata(&path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::Msg(e.to_string()))?
+        .as_secs();
+    let thumbnail_path = dir.join(format!("{}.png", md5_hex(uri.as_bytes())));
+
+    let image = match read_thumbnail(&thumbnail_path, &uri, mtime) {
+        Some(image) => image,
+        None => {
+            let image = load_thumbnail(&path, size.pixels())?;
+            let file_size = fs::metadata(&path)?.len();
+            if let Err(e) = write_thumbnail(
+                &dir,
+                &thumbnail_path,
+                &image,
+                &uri,
+                mtime,
+                file_size,
+            ) {
+                debug!("Could not save the thumbnail of {:?}: {}", path, e);
+            }
+            image
+        }
+    };
+    Ok(fit_to_size(image, max_size))
+}
+
+/// Returns `None` if the thumbnail doesn't exist, can't be read or doesn't
+/// belong to the current version of the file
+fn read_thumbnail(
+    thumbnail_path: &Path,
+    uri: &str,
+    mtime: u64,
+) -> Option<image::RgbaImage> {
+    let data = fs::read(thumbnail_path).ok()?;
+    let reader = png::Decoder::new(Cursor::new(&data)).read_info().ok()?;
+    let info = reader.info();
+    let text = |key| {
+        info.uncompressed_latin1_text
+            .iter()
+            .find(|chunk| chunk.keyword == key)
+            .map(|chunk| chunk.text.as_str())
+    };
+    if text(URI_KEY) != Some(uri) {
+        return None;
+    }
+    if text(MTIME_KEY)?.parse::<u64>().ok()? != mtime {
+        return None;
+    }
+    let image = image::load_from_memory_with_format(&data, ImageFormat::Png);
+    image.ok().map(|image| image.into_rgba8())
+}
+
+fn write_thumbnail(
+    dir: &Path,
+    thumbnail_path: &Path,
+    image: &image::RgbaImage,
+    uri: &str,
+    mtime: u64,
+    file_size: u64,
+) -> Result<()> {
+    create_private_dir(dir)?;
+    // The thumbnail is written to a temporary file first so that other
+    // programs never read an incomplete one
+    let temp_path = dir.join(format!(
+        "alloy-{}-{}.png.tmp",
+        std::process::id(),
+        md5_hex(uri.as_bytes())
+    ));
+    let mut data = Vec::new();
+    {
+        let mut encoder =
+            png::Encoder::new(&mut data, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.add_text_chunk(URI_KEY.to_string(), uri.to_string())?;
+        encoder.add_text_chunk(MTIME_KEY.to_string(), mtime.to_string())?;
+        encoder.add_text_chunk(SIZE_KEY.to_string(), file_size.to_string())?;
+        encoder
+            .add_text_chunk(SOFTWARE_KEY.to_string(), "Alloy".to_string())?;
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(image.as_raw())?;
+        writer.finish()?;
+    }
+    let result = create_private_file(&temp_path)
+        .and_then(|mut file| file.write_all(&data))
+        .and_then(|()| fs::rename(&temp_path, thumbnail_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+/// The standard asks for the folders to be only accessible by the user
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+fn create_private_file(path: &Path) -> io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// The `file://` URI of an absolute path, escaped the way GLib does it
+pub fn file_uri(path: &Path) -> String {
+    #[cfg(unix)]
+    let bytes = {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    };
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().replace('\\', "/").into_bytes();
+
+    let mut uri = String::from("file://");
+    for byte in bytes {
+        let unescaped = byte.is_ascii_alphanumeric()
+            || b"-._~!$&'()*+,=:@/".contains(&byte);
+        if unescaped {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+/// The MD5 hash of `data` as lowercase hex digits, which is how the thumbnail
+/// files are named
+pub fn md5_hex(data: &[u8]) -> String {
+    format!("{:x}", md5::compute(data))
+}
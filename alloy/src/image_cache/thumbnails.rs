@@ -0,0 +1,134 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use gelatin::{
+    glium::{
+        self,
+        texture::{MipmapsOption, RawImage2d, SrgbTexture2d},
+    },
+    image,
+};
+
+/// The thumbnails are loaded to fit into a square of this many pixels
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+/// The number of thumbnail textures kept around. The least recently used ones
+/// are dropped first.
+const MAX_THUMBNAILS: usize = 512;
+
+/// Thumbnail requests are sent gradually to leave room for the requests of
+/// the full images
+const MAX_PENDING_THUMBNAILS: usize = 8;
+
+#[derive(Clone)]
+pub enum Thumbnail {
+    Loading,
+    Ready(Rc<SrgbTexture2d>),
+    Failed,
+}
+
+struct ThumbnailEntry {
+    thumbnail: Thumbnail,
+    /// The value of `Thumbnails::use_count` when this was last requested
+    last_used: u64,
+}
+
+/// The thumbnails of the images, identified by the request id of their
+/// `DirItem`
+pub struct Thumbnails {
+    entries: HashMap<u32, ThumbnailEntry>,
+    pending: HashSet<u32>,
+    /// Decoded thumbnails that haven't been uploaded to the GPU yet
+    received: Vec<(u32, Option<image::RgbaImage>)>,
+    use_count: u64,
+}
+
+impl Thumbnails {
+    pub fn new() -> Thumbnails {
+        Thumbnails {
+            entries: HashMap::new(),
+            pending: HashSet::new(),
+            received: Vec::new(),
+            use_count: 0,
+        }
+    }
+
+    /// Returns `None` if the thumbnail has to be requested
+    pub fn get(&mut self, req_id: u32) -> Option<Thumbnail> {
+        self.use_count += 1;
+        if self.pending.contains(&req_id) {
+            return Some(Thumbnail::Loading);
+        }
+        let entry = self.entries.get_mut(&req_id)?;
+        entry.last_used = self.use_count;
+        Some(entry.thumbnail.clone())
+    }
+
+    pub fn can_request(&self) -> bool {
+        self.pending.len() < MAX_PENDING_THUMBNAILS
+    }
+
+    pub fn add_request(&mut self, req_id: u32) {
+        self.pending.insert(req_id);
+    }
+
+    /// Stores a thumbnail sent by the loader until `upload` is called
+    pub fn add_result(&mut self, req_id: u32, image: Option<image::RgbaImage>) {
+        if self.pending.remove(&req_id) {
+            self.received.push((req_id, image));
+        }
+    }
+
+    /// Creates the textures of the received thumbnails
+    pub fn upload(&mut self, display: &glium::Display) {
+        for (req_id, image) in self.received.drain(..) {
+            let thumbnail = match image {
+                Some(image) => {
+                    let dimensions = image.dimensions();
+                    let raw_image =
+                        RawImage2d::from_raw_rgba(image.into_raw(), dimensions);
+                    match SrgbTexture2d::with_mipmaps(
+                        display,
+                        raw_image,
+                        MipmapsOption::AutoGeneratedMipmaps,
+                    ) {
+                        Ok(texture) => Thumbnail::Ready(Rc::new(texture)),
+                        Err(e) => {
+                            eprintln!(
+                                "Could not create a thumbnail texture: {e}"
+                            );
+                            Thumbnail::Failed
+                        }
+                    }
+                }
+                None => Thumbnail::Failed,
+            };
+            self.entries.insert(
+                req_id,
+                ThumbnailEntry {
+                    thumbnail,
+                    last_used: self.use_count,
+                },
+            );
+        }
+        if self.entries.len() > MAX_THUMBNAILS {
+            let mut by_last_use = self
+                .entries
+                .iter()
+                .map(|(&req_id, entry)| (entry.last_used, req_id))
+                .collect::<Vec<_>>();
+            by_last_use.sort_unstable();
+            let excess = self.entries.len() - MAX_THUMBNAILS;
+            for (_, req_id) in by_last_use.into_iter().take(excess) {
+                self.entries.remove(&req_id);
+            }
+        }
+    }
+
+    /// Drops the thumbnail so that it's loaded again the next time
+    pub fn remove(&mut self, req_id: u32) {
+        self.entries.remove(&req_id);
+    }
+}
@@ -0,0 +1,1074 @@
+//! Images that are too large to be kept in memory and on the GPU at once.
+//!
+//! Such an image is cut into tiles on a loader thread, along with copies of
+//! it that are halved in size until they fit into a single tile. The tiles
+//! are written to a file and only the ones in view are read back, on the
+//! loader threads, and uploaded to the GPU at the level of detail they're
+//! shown at.
+//!
+//! PNG files are read row by row and TIFF files strip by strip, or one row of
+//! tiles at a time. JPEG files are decoded at once, at the largest fraction of
+//! their resolution that fits into `MAX_DECODED_BYTES`, so the pyramid of a
+//! very large JPEG starts below the full resolution. Other formats, and TIFF
+//! files with colors that can't be streamed, are decoded at once within the
+//! default limits of `image`. The decoded image is dropped once its tiles are
+//! written.
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc::{channel, Receiver, Sender},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use gelatin::{
+    glium,
+    image::{self, codecs::jpeg::JpegDecoder, ImageDecoder, ImageFormat},
+};
+use moxcms::Transform8BitExecutor;
+
+use super::{
+    get_image_size_estimate,
+    icc::to_display_colors,
+    image_loader::{errors::*, is_cancelled, FrameImage, LoadRequest},
+    pages::{load_page, tiff_page_offsets},
+    texture_from_img_rect,
+    thumbnail_cache::{file_uri, md5_hex},
+    CellTexture, FrameRect,
+};
+use crate::PROJECT_DIRS;
+
+pub const TILE_SIZE: u32 = 512;
+/// Images with more pixels than this are tiled
+const MIN_TILED_PIXELS: u64 = 64 * 1024 * 1024;
+/// The number of tiles that are kept on the GPU, unless more are in view
+const MAX_RESIDENT_TILES: usize = 128;
+/// The number of tiles that are read at once, so that the loader threads stay
+/// available for loading images
+const MAX_PENDING_READS: usize = 8;
+/// The number of tile files that are kept in the cache folder
+const MAX_KEPT_FILES: usize = 8;
+/// Partially written tile files of other processes that weren't modified for
+/// this long are assumed to be left over from a crash
+const STALE_FILE_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+/// JPEG images are scaled down while they are decoded until they take at
+/// most this much memory. This is the default allocation limit of `image`.
+const MAX_DECODED_BYTES: u64 = 512 * 1024 * 1024;
+const MAGIC: &[u8; 8] = b"ALLOYTL2";
+/// The magic, the modification time of the image, the size of the first
+/// level, the page and the size of the image
+const HEADER_LEN: u64 = 40;
+
+/// Keeps the tiles in the cache folder, so that large images open faster the
+/// next time. Otherwise they are deleted once the image leaves the cache.
+pub static KEEP_TILES: AtomicBool = AtomicBool::new(false);
+
+static TEMP_FILE_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// True if the image is large enough to be tiled
+pub fn is_huge_image(path: &Path, format: ImageFormat) -> bool {
+    use ImageFormat::*;
+    if !matches!(format, Png | Jpeg | Tiff | Bmp | Tga | Pnm) {
+        return false;
+    }
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    image::io::Reader::with_format(BufReader::new(file), format)
+        .into_dimensions()
+        .is_ok_and(|(w, h)| w as u64 * h as u64 > MIN_TILED_PIXELS)
+}
+
+/// Cuts the image into tiles, or finds its tiles in the cache folder. Returns
+/// the tiles along with the loaded page and the number of pages of the file.
+/// The colors are converted with `transform` before the tiles are written.
+pub fn load_tiles(
+    path: &Path,
+    format: ImageFormat,
+    page: usize,
+    transform: Option<&Transform8BitExecutor>,
+    req_id: u32,
+) -> Result<(TilePyramid, usize, usize)> {
+    let (page, page_count) = if format == ImageFormat::Tiff {
+        match tiff_page_offsets(&mut fs::File::open(path)?)? {
+            Some((_, offsets)) => (page.min(offsets.len() - 1), offsets.len()),
+            None => (0, 1),
+        }
+    } else {
+        (0, 1)
+    };
+    let mtime = fs::metadata(path)?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::Msg(e.to_string()))?
+        .as_secs();
+    let header = Header {
+        mtime,
+        page: page as u64,
+    };
+    let kept_path = match tiles_dir() {
+        Some(dir) if KEEP_TILES.load(Ordering::SeqCst) => {
+            let path = path.canonicalize()?;
+            let key =
+                format!("{}#{page}#{}", file_uri(&path), transform.is_some());
+            Some(dir.join(format!("{}.tiles", md5_hex(key.as_bytes()))))
+        }
+        _ => None,
+    };
+    if let Some(kept_path) = &kept_path {
+        if let Some(layout) = read_header(kept_path, header) {
+            let pyramid = TilePyramid::open(layout, kept_path.clone(), false)?;
+            return Ok((pyramid, page, page_count));
+        }
+    }
+    let tile_path = match &kept_path {
+        Some(kept_path) => kept_path.with_extension("part"),
+        None => temp_path(),
+    };
+    if let Some(dir) = tile_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let written =
+        write_tiles(&tile_path, path, format, header, transform, req_id);
+    let layout = match written {
+        Ok(layout) => layout,
+        Err(e) => {
+            let _ = fs::remove_file(&tile_path);
+            return Err(e);
+        }
+    };
+    let pyramid = match kept_path {
+        Some(kept_path) => {
+            fs::rename(&tile_path, &kept_path)?;
+            TilePyramid::open(layout, kept_path, false)?
+        }
+        None => TilePyramid::open(layout, tile_path, true)?,
+    };
+    if let Some(dir) = tiles_dir() {
+        remove_old_files(&dir);
+    }
+    Ok((pyramid, page, page_count))
+}
+
+fn tiles_dir() -> Option<PathBuf> {
+    PROJECT_DIRS
+        .as_ref()
+        .map(|dirs| dirs.cache_dir().join("tiles"))
+}
+
+fn temp_path() -> PathBuf {
+    let name = format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNT.fetch_add(1, Ordering::SeqCst)
+    );
+    tiles_dir().unwrap_or_else(std::env::temp_dir).join(name)
+}
+
+/// Keeps the most recently written tile files, and removes the temporary
+/// and partially written files that were left behind by other processes
+fn remove_old_files(dir: &Path) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let own_prefix = format!("{}-", std::process::id());
+    let now = SystemTime::now();
+    let mut kept = Vec::new();
+    let mut stale = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let mtime = match entry.metadata().and_then(|m| m.modified()) {
+            Ok(mtime) => mtime,
+            Err(_) => continue,
+        };
+        let age = now.duration_since(mtime).unwrap_or_default();
+        let is_own =
+            entry.file_name().to_string_lossy().starts_with(&own_prefix);
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("tiles") => kept.push((mtime, path)),
+            Some("tmp" | "part") if !is_own && age > STALE_FILE_AGE => {
+                stale.push(path)
+            }
+            _ => (),
+        }
+    }
+    kept.sort_by_key(|(mtime, _)| Reverse(*mtime));
+    let old = kept.into_iter().skip(MAX_KEPT_FILES).map(|(_, path)| path);
+    for path in old.chain(stale) {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Could not remove the tiles at {path:?}: {e}");
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Header {
+    mtime: u64,
+    page: u64,
+}
+
+/// Returns the layout of the tiles at `path` if they are complete and belong
+/// to the image described by `header`
+fn read_header(path: &Path, header: Header) -> Option<Layout> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut bytes = [0; HEADER_LEN as usize];
+    file.read_exact(&mut bytes).ok()?;
+    let u32_at = |i: usize| {
+        u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+    };
+    let u64_at = |i: usize| u32_at(i) as u64 | (u32_at(i + 4) as u64) << 32;
+    let stored = Header {
+        mtime: u64_at(8),
+        page: u64_at(24),
+    };
+    if &bytes[..8] != MAGIC || stored != header {
+        return None;
+    }
+    let full_size = (u32_at(32), u32_at(36));
+    let layout = Layout::new(u32_at(16), u32_at(20), full_size);
+    let complete = file.metadata().ok()?.len() == layout.file_len();
+    complete.then_some(layout)
+}
+
+/// Decodes the image and writes its tiles to `tile_path`
+fn write_tiles(
+    tile_path: &Path,
+    source: &Path,
+    format: ImageFormat,
+    header: Header,
+    transform: Option<&Transform8BitExecutor>,
+    req_id: u32,
+) -> Result<Layout> {
+    let page = header.page as usize;
+    decode_rows(source, format, page, transform, |(w, h), full_size| {
+        let layout = Layout::new(w, h, full_size);
+        PyramidWriter::create(tile_path, layout, header, req_id)
+    })?
+    .finish()
+}
+
+/// Decodes the image at a size that fits into a square with sides of at
+/// least `max_size` pixels, without keeping all of it in memory where
+/// possible. JPEG files are decoded at a fraction of their resolution, the
+/// rows of other images are halved as they're decoded.
+pub fn load_downscaled(
+    path: &Path,
+    format: ImageFormat,
+    max_size: u32,
+    transform: Option<&Transform8BitExecutor>,
+) -> Result<image::RgbaImage> {
+    if format == ImageFormat::Jpeg {
+        let mut decoder =
+            JpegDecoder::new(BufReader::new(fs::File::open(path)?))?;
+        let (w, h) = decoder.dimensions();
+        let scale = max_size as f32 / w.max(h) as f32;
+        let scaled_w = (w as f32 * scale).ceil() as u16;
+        let scaled_h = (h as f32 * scale).ceil() as u16;
+        decoder.scale(scaled_w, scaled_h)?;
+        let mut image =
+            image::DynamicImage::from_decoder(decoder)?.into_rgba8();
+        if let Some(transform) = transform {
+            to_display_colors(&mut image, transform)?;
+        }
+        return Ok(image);
+    }
+    decode_rows(path, format, 0, transform, |(w, h), _| {
+        Ok(Shrinker::new(w, h, max_size))
+    })?
+    .finish()
+}
+
+/// Receives the rows of an image from `decode_rows`
+trait RowSink {
+    fn add_row(&mut self, row: &[u8]) -> Result<()>;
+}
+
+/// Decodes the image and passes its RGBA rows to the sink that `create_sink`
+/// returns. The sink is created for the size of the decoded image and the
+/// size of the full image, which only differ for large JPEG files. The colors
+/// are converted with `transform` first.
+fn decode_rows<S: RowSink>(
+    source: &Path,
+    format: ImageFormat,
+    page: usize,
+    transform: Option<&Transform8BitExecutor>,
+    create_sink: impl FnOnce((u32, u32), (u32, u32)) -> Result<S>,
+) -> Result<S> {
+    let file = BufReader::new(fs::File::open(source)?);
+    match format {
+        ImageFormat::Png => {
+            let mut decoder = png::Decoder::new(file);
+            decoder.set_transformations(
+                png::Transformations::normalize_to_color8(),
+            );
+            let mut reader = decoder.read_info().map_err(png_error)?;
+            // The rows of interlaced images don't arrive in order
+            if !reader.info().interlaced {
+                let (w, h) = (reader.info().width, reader.info().height);
+                let channels = match reader.output_color_type().0 {
+                    png::ColorType::Grayscale => 1,
+                    png::ColorType::GrayscaleAlpha => 2,
+                    png::ColorType::Rgb => 3,
+                    png::ColorType::Rgba => 4,
+                    png::ColorType::Indexed => {
+                        return Err(Error::Msg("Unexpected indexed PNG".into()))
+                    }
+                };
+                let mut sink = create_sink((w, h), (w, h))?;
+                let mut rgba = Vec::with_capacity(w as usize * 4);
+                while let Some(row) = reader.next_row().map_err(png_error)? {
+                    samples_to_rgba(row.data(), channels, &mut rgba);
+                    add_row(&mut sink, &mut rgba, transform)?;
+                }
+                return Ok(sink);
+            }
+        }
+        ImageFormat::Tiff => {
+            let mut decoder = tiff::decoder::Decoder::new(file)?;
+            decoder.seek_to_image(page)?;
+            if let Some(channels) = tiff_channels(decoder.colortype()?) {
+                return decode_tiff_rows(
+                    decoder,
+                    channels,
+                    transform,
+                    create_sink,
+                );
+            }
+            let image = load_page(source, format, page)?.0;
+            let (w, h) = image.dimensions();
+            let image = image::DynamicImage::ImageRgba8(image);
+            return push_image_rows(image, (w, h), transform, create_sink);
+        }
+        ImageFormat::Jpeg => {
+            let mut decoder = JpegDecoder::new(file)?;
+            let (w, h) = decoder.dimensions();
+            // The DCT can scale the image by 1/2, 1/4 or 1/8 while decoding
+            let mut factor = 1;
+            let bytes = |factor| {
+                w.div_ceil(factor) as u64 * h.div_ceil(factor) as u64 * 4
+            };
+            while factor < 8 && bytes(factor) > MAX_DECODED_BYTES {
+                factor *= 2;
+            }
+            if factor > 1 {
+                let scaled_w = w.div_ceil(factor) as u16;
+                let scaled_h = h.div_ceil(factor) as u16;
+                decoder.scale(scaled_w, scaled_h)?;
+            }
+            let image = image::DynamicImage::from_decoder(decoder)?;
+            return push_image_rows(image, (w, h), transform, create_sink);
+        }
+        _ => (),
+    }
+    // These formats can't be read row by row, so the image has to fit into
+    // the default limits of `image`
+    let file = BufReader::new(fs::File::open(source)?);
+    let image = image::io::Reader::with_format(file, format).decode()?;
+    let full_size = (image.width(), image.height());
+    push_image_rows(image, full_size, transform, create_sink)
+}
+
+/// Passes the rows of a TIFF image to the sink, reading one strip or one row
+/// of tiles at a time
+fn decode_tiff_rows<R: Read + Seek, S: RowSink>(
+    mut decoder: tiff::decoder::Decoder<R>,
+    channels: usize,
+    transform: Option<&Transform8BitExecutor>,
+    create_sink: impl FnOnce((u32, u32), (u32, u32)) -> Result<S>,
+) -> Result<S> {
+    let (w, h) = decoder.dimensions()?;
+    let (chunk_w, chunk_h) = decoder.chunk_dimensions();
+    let cols = match decoder.get_chunk_type() {
+        tiff::decoder::ChunkType::Strip => 1,
+        tiff::decoder::ChunkType::Tile => w.div_ceil(chunk_w),
+    };
+    let band_len = w as u64 * chunk_h as u64 * 4;
+    if band_len > MAX_DECODED_BYTES {
+        return Err(Error::Msg("The strips of the TIFF are too large".into()));
+    }
+    let mut sink = create_sink((w, h), (w, h))?;
+    let mut band = vec![0; band_len as usize];
+    let mut rgba = Vec::with_capacity(chunk_w as usize * 4);
+    for band_index in 0..h.div_ceil(chunk_h) {
+        let band_h = chunk_h.min(h - band_index * chunk_h) as usize;
+        for col in 0..cols {
+            let chunk_index = band_index * cols + col;
+            let (data_w, data_h) = decoder.chunk_data_dimensions(chunk_index);
+            let samples = match decoder.read_chunk(chunk_index)? {
+                tiff::decoder::DecodingResult::U8(samples) => samples,
+                tiff::decoder::DecodingResult::U16(samples) => {
+                    samples.iter().map(|&sample| (sample >> 8) as u8).collect()
+                }
+                _ => {
+                    return Err(Error::Msg(
+                        "Unexpected sample format in TIFF".into(),
+                    ))
+                }
+            };
+            let row_len = data_w as usize * channels;
+            let rows = samples.chunks_exact(row_len).take(data_h as usize);
+            for (y, row) in rows.enumerate() {
+                samples_to_rgba(row, channels, &mut rgba);
+                let start = (y * w as usize + (col * chunk_w) as usize) * 4;
+                band[start..start + rgba.len()].copy_from_slice(&rgba);
+            }
+        }
+        for row in band.chunks_exact_mut(w as usize * 4).take(band_h) {
+            if let Some(transform) = transform {
+                convert_colors(row, transform)?;
+            }
+            sink.add_row(row)?;
+        }
+    }
+    Ok(sink)
+}
+
+/// The number of channels of the TIFF color types that are read row by row
+fn tiff_channels(color_type: tiff::ColorType) -> Option<usize> {
+    use tiff::ColorType::*;
+    match color_type {
+        Gray(8 | 16) => Some(1),
+        GrayA(8 | 16) => Some(2),
+        RGB(8 | 16) => Some(3),
+        RGBA(8 | 16) => Some(4),
+        _ => None,
+    }
+}
+
+/// Passes the rows of a decoded image to the sink, converting only a row at
+/// a time to RGBA
+fn push_image_rows<S: RowSink>(
+    image: image::DynamicImage,
+    full_size: (u32, u32),
+    transform: Option<&Transform8BitExecutor>,
+    create_sink: impl FnOnce((u32, u32), (u32, u32)) -> Result<S>,
+) -> Result<S> {
+    let (w, h) = (image.width(), image.height());
+    let mut sink = create_sink((w, h), full_size)?;
+    let (samples, channels) = match image {
+        image::DynamicImage::ImageLuma8(image) => (image.into_raw(), 1),
+        image::DynamicImage::ImageLumaA8(image) => (image.into_raw(), 2),
+        image::DynamicImage::ImageRgb8(image) => (image.into_raw(), 3),
+        image => (image.into_rgba8().into_raw(), 4),
+    };
+    let mut rgba = Vec::with_capacity(w as usize * 4);
+    for row in samples.chunks_exact(w as usize * channels) {
+        samples_to_rgba(row, channels, &mut rgba);
+        add_row(&mut sink, &mut rgba, transform)?;
+    }
+    Ok(sink)
+}
+
+fn add_row<S: RowSink>(
+    sink: &mut S,
+    rgba: &mut [u8],
+    transform: Option<&Transform8BitExecutor>,
+) -> Result<()> {
+    if let Some(transform) = transform {
+        convert_colors(rgba, transform)?;
+    }
+    sink.add_row(rgba)
+}
+
+fn png_error(error: png::DecodingError) -> Error {
+    Error::Msg(format!("PNG decoding failed: {error}"))
+}
+
+/// Converts a row of 8 bit gray, gray and alpha, RGB or RGBA samples to RGBA
+fn samples_to_rgba(row: &[u8], channels: usize, rgba: &mut Vec<u8>) {
+    rgba.clear();
+    match channels {
+        1 => {
+            for &gray in row {
+                rgba.extend_from_slice(&[gray, gray, gray, 255]);
+            }
+        }
+        2 => {
+            for pixel in row.chunks_exact(2) {
+                rgba.extend_from_slice(&[
+                    pixel[0], pixel[0], pixel[0], pixel[1],
+                ]);
+            }
+        }
+        3 => {
+            for pixel in row.chunks_exact(3) {
+                rgba.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 255]);
+            }
+        }
+        _ => rgba.extend_from_slice(row),
+    }
+}
+
+fn convert_colors(
+    row: &mut [u8],
+    transform: &Transform8BitExecutor,
+) -> Result<()> {
+    let source = row.to_vec();
+    transform
+        .transform(&source, row)
+        .map_err(|e| Error::Msg(format!("Color conversion failed: {e}")))
+}
+
+/// The sizes of the levels of a pyramid and where their tiles are stored.
+///
+/// The tiles of a level are stored row by row after the ones of the previous
+/// level, each one as the RGBA pixels of its rows.
+#[derive(Clone)]
+struct Layout {
+    /// The decoded image first, every further level is half the size of the
+    /// previous one. The last one fits into a single tile.
+    levels: Vec<(u32, u32)>,
+    /// Where the first tile of each level starts in the file
+    level_offsets: Vec<u64>,
+    /// The size of the image, which is larger than the first level if the
+    /// image was scaled down while it was decoded
+    full_size: (u32, u32),
+    /// The number of times the full image was halved to get the first level
+    first_level: usize,
+}
+
+impl Layout {
+    fn new(w: u32, h: u32, full_size: (u32, u32)) -> Layout {
+        let scale = full_size.0.max(full_size.1) as f32 / w.max(h) as f32;
+        let first_level = scale.log2().round().max(0.0) as usize;
+        let mut levels = vec![(w, h)];
+        let mut level_offsets = vec![HEADER_LEN];
+        let (mut w, mut h) = (w, h);
+        while w.max(h) > TILE_SIZE {
+            let last_offset = level_offsets[level_offsets.len() - 1];
+            level_offsets.push(last_offset + w as u64 * h as u64 * 4);
+            (w, h) = (w.div_ceil(2), h.div_ceil(2));
+            levels.push((w, h));
+        }
+        Layout {
+            levels,
+            level_offsets,
+            full_size,
+            first_level,
+        }
+    }
+
+    fn file_len(&self) -> u64 {
+        let last = self.levels.len() - 1;
+        let (w, h) = self.levels[last];
+        self.level_offsets[last] + w as u64 * h as u64 * 4
+    }
+
+    /// The number of columns and rows of tiles of a level
+    fn tile_counts(&self, level: usize) -> (u32, u32) {
+        let (w, h) = self.levels[level];
+        (w.div_ceil(TILE_SIZE), h.div_ceil(TILE_SIZE))
+    }
+
+    fn tile_size(&self, level: usize, col: u32, row: u32) -> (u32, u32) {
+        let (w, h) = self.levels[level];
+        let tile_w = (w - col * TILE_SIZE).min(TILE_SIZE);
+        let tile_h = (h - row * TILE_SIZE).min(TILE_SIZE);
+        (tile_w, tile_h)
+    }
+
+    fn tile_offset(&self, level: usize, col: u32, row: u32) -> u64 {
+        let (w, _) = self.levels[level];
+        let (_, tile_h) = self.tile_size(level, col, row);
+        let tile_row_len = TILE_SIZE as u64 * w as u64 * 4;
+        let tile_offset = col as u64 * TILE_SIZE as u64 * tile_h as u64 * 4;
+        self.level_offsets[level] + row as u64 * tile_row_len + tile_offset
+    }
+
+    /// The part of the full image that a tile covers
+    fn tile_rect(&self, level: usize, col: u32, row: u32) -> FrameRect {
+        let (full_w, full_h) = self.full_size;
+        let (w, h) = self.levels[level];
+        let scale_x = full_w as f32 / w as f32;
+        let scale_y = full_h as f32 / h as f32;
+        let (tile_w, tile_h) = self.tile_size(level, col, row);
+        FrameRect {
+            x: (col * TILE_SIZE) as f32 * scale_x,
+            y: (row * TILE_SIZE) as f32 * scale_y,
+            w: tile_w as f32 * scale_x,
+            h: tile_h as f32 * scale_y,
+        }
+    }
+}
+
+/// Writes the tiles of all levels while the rows of the full image are
+/// pushed, so that only a row of tiles of each level is kept in memory
+struct PyramidWriter {
+    layout: Layout,
+    file: fs::File,
+    levels: Vec<LevelWriter>,
+    /// Writing stops if the request is cancelled
+    req_id: u32,
+}
+
+#[derive(Default)]
+struct LevelWriter {
+    /// The pixels of the rows of the current row of tiles
+    band: Vec<u8>,
+    /// The index of the first row in `band`
+    band_start: u32,
+    /// A row that is halved along with the next one
+    unpaired: Option<Vec<u8>>,
+}
+
+impl PyramidWriter {
+    fn create(
+        path: &Path,
+        layout: Layout,
+        header: Header,
+        req_id: u32,
+    ) -> Result<PyramidWriter> {
+        let (w, h) = layout.levels[0];
+        let (full_w, full_h) = layout.full_size;
+        let mut file = fs::File::create(path)?;
+        file.write_all(MAGIC)?;
+        file.write_all(&header.mtime.to_le_bytes())?;
+        file.write_all(&w.to_le_bytes())?;
+        file.write_all(&h.to_le_bytes())?;
+        file.write_all(&header.page.to_le_bytes())?;
+        file.write_all(&full_w.to_le_bytes())?;
+        file.write_all(&full_h.to_le_bytes())?;
+        let levels = layout.levels.iter().map(|_| Default::default()).collect();
+        Ok(PyramidWriter {
+            layout,
+            file,
+            levels,
+            req_id,
+        })
+    }
+
+    fn push_row(&mut self, level: usize, row: &[u8]) -> Result<()> {
+        let (w, h) = self.layout.levels[level];
+        if level + 1 < self.levels.len() {
+            match self.levels[level].unpaired.take() {
+                Some(above) => {
+                    self.push_row(level + 1, &halve(&above, row, w))?
+                }
+                None => self.levels[level].unpaired = Some(row.to_vec()),
+            }
+        }
+        let writer = &mut self.levels[level];
+        writer.band.extend_from_slice(row);
+        let band_rows = (writer.band.len() / (w as usize * 4)) as u32;
+        if band_rows == TILE_SIZE || writer.band_start + band_rows == h {
+            self.write_band(level)?;
+        }
+        Ok(())
+    }
+
+    fn write_band(&mut self, level: usize) -> Result<()> {
+        // Checked once for each row of tiles of the full image
+        if level == 0 && is_cancelled(self.req_id) {
+            return Err(Error::Cancelled);
+        }
+        let (w, _) = self.layout.levels[level];
+        let (cols, _) = self.layout.tile_counts(level);
+        let writer = &mut self.levels[level];
+        let row_len = w as usize * 4;
+        let tile_row = writer.band_start / TILE_SIZE;
+        for col in 0..cols {
+            let (tile_w, _) = self.layout.tile_size(level, col, tile_row);
+            let start = (col * TILE_SIZE) as usize * 4;
+            let end = start + tile_w as usize * 4;
+            let tile: Vec<u8> = writer
+                .band
+                .chunks_exact(row_len)
+                .flat_map(|row| &row[start..end])
+                .copied()
+                .collect();
+            let offset = self.layout.tile_offset(level, col, tile_row);
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.write_all(&tile)?;
+        }
+        writer.band_start += (writer.band.len() / row_len) as u32;
+        writer.band.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Layout> {
+        // The last row of an image with an odd height is halved on its own
+        for level in 0..self.levels.len() - 1 {
+            if let Some(row) = self.levels[level].unpaired.take() {
+                let (w, _) = self.layout.levels[level];
+                self.push_row(level + 1, &halve(&row, &row, w))?;
+            }
+        }
+        let complete = self
+            .levels
+            .iter()
+            .zip(&self.layout.levels)
+            .all(|(writer, &(_, h))| writer.band_start == h);
+        if !complete {
+            return Err(Error::Msg("The image ended early".into()));
+        }
+        self.file.flush()?;
+        Ok(self.layout)
+    }
+}
+
+impl RowSink for PyramidWriter {
+    fn add_row(&mut self, row: &[u8]) -> Result<()> {
+        self.push_row(0, row)
+    }
+}
+
+/// Halves an image while its rows are added, until it fits into a square
+/// with sides of at least `max_size` pixels. Only the result is kept in
+/// memory.
+struct Shrinker {
+    /// The width of the image before each halving, the full width first
+    widths: Vec<u32>,
+    /// The row of each halving that is halved along with the next one
+    unpaired: Vec<Option<Vec<u8>>>,
+    /// The rows of the result
+    pixels: Vec<u8>,
+}
+
+impl Shrinker {
+    fn new(w: u32, h: u32, max_size: u32) -> Shrinker {
+        let mut widths = vec![w];
+        let (mut w, mut h) = (w, h);
+        while w.max(h) > 1 && w.max(h).div_ceil(2) >= max_size {
+            (w, h) = (w.div_ceil(2), h.div_ceil(2));
+            widths.push(w);
+        }
+        Shrinker {
+            unpaired: vec![None; widths.len() - 1],
+            widths,
+            pixels: Vec::new(),
+        }
+    }
+
+    fn push_row(&mut self, step: usize, row: &[u8]) {
+        if step == self.unpaired.len() {
+            self.pixels.extend_from_slice(row);
+            return;
+        }
+        match self.unpaired[step].take() {
+            Some(above) => {
+                let half = halve(&above, row, self.widths[step]);
+                self.push_row(step + 1, &half);
+            }
+            None => self.unpaired[step] = Some(row.to_vec()),
+        }
+    }
+
+    fn finish(mut self) -> Result<image::RgbaImage> {
+        // The last row of an image with an odd height is halved on its own
+        for step in 0..self.unpaired.len() {
+            if let Some(row) = self.unpaired[step].take() {
+                let half = halve(&row, &row, self.widths[step]);
+                self.push_row(step + 1, &half);
+            }
+        }
+        let w = self.widths[self.widths.len() - 1];
+        let h = self.pixels.len().checked_div(w as usize * 4).unwrap_or(0);
+        image::RgbaImage::from_raw(w, h as u32, self.pixels).ok_or_else(|| {
+            Error::Msg("Invalid size of the scaled image".into())
+        })
+    }
+}
+
+impl RowSink for Shrinker {
+    fn add_row(&mut self, row: &[u8]) -> Result<()> {
+        self.push_row(0, row);
+        Ok(())
+    }
+}
+
+/// Averages the pixels of two rows in blocks of 2x2
+fn halve(above: &[u8], below: &[u8], w: u32) -> Vec<u8> {
+    let w = w as usize;
+    let mut half = Vec::with_capacity(w.div_ceil(2) * 4);
+    for x in (0..w).step_by(2) {
+        let left = x * 4;
+        let right = (x + 1).min(w - 1) * 4;
+        for channel in 0..4 {
+            let sum = above[left + channel] as u32
+                + above[right + channel] as u32
+                + below[left + channel] as u32
+                + below[right + channel] as u32;
+            half.push(((sum + 2) / 4) as u8);
+        }
+    }
+    half
+}
+
+/// The tiles of an image in a file, see the module docs
+pub struct TilePyramid {
+    layout: Layout,
+    path: PathBuf,
+    /// True if the file is deleted along with the pyramid
+    temporary: bool,
+    /// The single tile of the smallest level, which is the whole image scaled
+    /// to fit into a tile. It's read on the loader thread along with the
+    /// layout.
+    overview: image::RgbaImage,
+}
+
+impl TilePyramid {
+    fn open(layout: Layout, path: PathBuf, temporary: bool) -> Result<Self> {
+        let level = layout.levels.len() - 1;
+        let offset = layout.tile_offset(level, 0, 0);
+        let overview = read_tile(&path, offset, layout.tile_size(level, 0, 0));
+        let mut pyramid = TilePyramid {
+            layout,
+            path,
+            temporary,
+            overview: Default::default(),
+        };
+        // The file is removed by `drop` if the overview can't be read
+        pyramid.overview = overview?;
+        Ok(pyramid)
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.layout.full_size
+    }
+}
+
+impl Drop for TilePyramid {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(e) = fs::remove_file(&self.path) {
+                eprintln!("Could not remove the tiles at {:?}: {e}", self.path);
+            }
+        }
+    }
+}
+
+fn read_tile(
+    path: &Path,
+    offset: u64,
+    (tile_w, tile_h): (u32, u32),
+) -> Result<image::RgbaImage> {
+    let mut pixels = vec![0; tile_w as usize * tile_h as usize * 4];
+    let mut file = fs::File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut pixels)?;
+    image::RgbaImage::from_raw(tile_w, tile_h, pixels)
+        .ok_or_else(|| Error::Msg("Invalid tile size".into()))
+}
+
+type TileKey = (usize, u32, u32);
+
+type TileResult = (TileKey, Result<image::RgbaImage>);
+
+/// A tile that is read from the file of a `TilePyramid` on a loader thread,
+/// see `LoadRequest::tile`
+#[derive(Debug, Clone)]
+pub struct TileRequest {
+    key: TileKey,
+    offset: u64,
+    size: (u32, u32),
+    result_tx: Sender<TileResult>,
+}
+
+impl TileRequest {
+    /// Reads the tile from the file at `path` and sends it to its texture
+    pub fn read(self, path: &Path) {
+        let tile = read_tile(path, self.offset, self.size);
+        // The texture may have been dropped in the meantime
+        let _ = self.result_tx.send((self.key, tile));
+    }
+}
+
+struct ResidentTile {
+    tex: Rc<CellTexture>,
+    /// The draw in which the tile was last in view
+    last_drawn: u64,
+}
+
+/// The tiles of a `TilePyramid` that are on the GPU
+pub struct TiledTexture {
+    pyramid: TilePyramid,
+    /// The request the pyramid was loaded by, its tiles are read under the
+    /// same id
+    req_id: u32,
+    /// The single tile of the smallest level. It's drawn below the other
+    /// tiles, so that it shows where they haven't been uploaded yet.
+    overview: Rc<CellTexture>,
+    resident: RefCell<HashMap<TileKey, ResidentTile>>,
+    draw_count: Cell<u64>,
+    request_tx: Sender<LoadRequest>,
+    result_tx: Sender<TileResult>,
+    result_rx: Receiver<TileResult>,
+    /// The tiles that are being read on the loader threads
+    pending: RefCell<HashSet<TileKey>>,
+    /// The tiles that couldn't be read or uploaded, they aren't tried again
+    failed: RefCell<HashSet<TileKey>>,
+}
+
+impl TiledTexture {
+    /// The tiles are read by sending requests to the loader threads through
+    /// `request_tx`
+    pub fn new(
+        display: &glium::Display,
+        mut pyramid: TilePyramid,
+        req_id: u32,
+        request_tx: Sender<LoadRequest>,
+    ) -> super::Result<TiledTexture> {
+        let tile = FrameImage::from(std::mem::take(&mut pyramid.overview));
+        let (w, h) = tile.dimensions();
+        let overview = texture_from_img_rect(display, &tile, 0, 0, w, h)?;
+        let (result_tx, result_rx) = channel();
+        Ok(TiledTexture {
+            pyramid,
+            req_id,
+            overview: Rc::new(overview),
+            resident: RefCell::new(HashMap::new()),
+            draw_count: Cell::new(0),
+            request_tx,
+            result_tx,
+            result_rx,
+            pending: RefCell::new(HashSet::new()),
+            failed: RefCell::new(HashSet::new()),
+        })
+    }
+
+    /// The memory that the tiles on the GPU are expected to take
+    pub fn size_estimate(&self) -> isize {
+        let tile_size = get_image_size_estimate(TILE_SIZE, TILE_SIZE, false);
+        tile_size * (MAX_RESIDENT_TILES + 1) as isize
+    }
+
+    /// Returns the tiles that show the image at `level`, the overview first.
+    /// The level is the number of times the full image is halved. `in_view`
+    /// tells whether a part of the image is visible.
+    ///
+    /// The tiles in view that aren't on the GPU are read on the loader
+    /// threads, at most `MAX_PENDING_READS` at once, and uploaded in a later
+    /// draw. The returned bool is false while there are tiles in view that
+    /// haven't been uploaded yet. The tiles that have been out of view for
+    /// the longest are evicted.
+    pub fn tiles_in_view(
+        &self,
+        display: &glium::Display,
+        level: usize,
+        in_view: impl Fn(&FrameRect) -> bool,
+    ) -> (Vec<(Rc<CellTexture>, FrameRect)>, bool) {
+        let layout = &self.pyramid.layout;
+        let level = level.saturating_sub(layout.first_level);
+        let level = level.min(layout.levels.len() - 1);
+        let draw = self.draw_count.get() + 1;
+        self.draw_count.set(draw);
+        let overview_rect = layout.tile_rect(layout.levels.len() - 1, 0, 0);
+        let mut tiles = vec![(self.overview.clone(), overview_rect)];
+        let mut resident = self.resident.borrow_mut();
+        let mut pending = self.pending.borrow_mut();
+        let mut failed = self.failed.borrow_mut();
+        // The tiles that went out of view while they were read are dropped
+        let mut read_tiles = HashMap::new();
+        for (key, result) in self.result_rx.try_iter() {
+            pending.remove(&key);
+            match result {
+                Ok(tile) => {
+                    read_tiles.insert(key, tile);
+                }
+                Err(e) => self.report_failure(&mut failed, key, "read", e),
+            }
+        }
+        let mut complete = true;
+        let (cols, rows) = layout.tile_counts(level);
+        for row in 0..rows {
+            for col in 0..cols {
+                let rect = layout.tile_rect(level, col, row);
+                if !in_view(&rect) {
+                    continue;
+                }
+                let key = (level, col, row);
+                if let Some(tile) = resident.get_mut(&key) {
+                    tile.last_drawn = draw;
+                    tiles.push((tile.tex.clone(), rect));
+                    continue;
+                }
+                if failed.contains(&key) {
+                    continue;
+                }
+                if let Some(tile) = read_tiles.remove(&key) {
+                    let tile = FrameImage::from(tile);
+                    let (w, h) = tile.dimensions();
+                    match texture_from_img_rect(display, &tile, 0, 0, w, h) {
+                        Ok(tex) => {
+                            let tex = Rc::new(tex);
+                            tiles.push((tex.clone(), rect));
+                            let tile = ResidentTile {
+                                tex,
+                                last_drawn: draw,
+                            };
+                            resident.insert(key, tile);
+                        }
+                        Err(e) => {
+                            self.report_failure(&mut failed, key, "upload", e)
+                        }
+                    }
+                    continue;
+                }
+                complete = false;
+                if pending.len() < MAX_PENDING_READS && pending.insert(key) {
+                    self.request_tile(key);
+                }
+            }
+        }
+        if resident.len() > MAX_RESIDENT_TILES {
+            let mut out_of_view: Vec<_> = resident
+                .iter()
+                .filter(|(_, tile)| tile.last_drawn != draw)
+                .map(|(key, tile)| (tile.last_drawn, *key))
+                .collect();
+            out_of_view.sort_unstable();
+            let excess = resident.len() - MAX_RESIDENT_TILES;
+            for (_, key) in out_of_view.into_iter().take(excess) {
+                resident.remove(&key);
+            }
+        }
+        (tiles, complete)
+    }
+
+    fn request_tile(&self, (level, col, row): TileKey) {
+        let layout = &self.pyramid.layout;
+        let tile = TileRequest {
+            key: (level, col, row),
+            offset: layout.tile_offset(level, col, row),
+            size: layout.tile_size(level, col, row),
+            result_tx: self.result_tx.clone(),
+        };
+        let request = LoadRequest {
+            req_id: self.req_id,
+            path: self.pyramid.path.clone(),
+            thumbnail_size: None,
+            page: 0,
+            tile: Some(tile),
+        };
+        // Fails only once the loader threads are gone
+        let _ = self.request_tx.send(request);
+    }
+
+    /// Only the first failure of a texture is printed, the others are likely
+    /// to have the same cause
+    fn report_failure(
+        &self,
+        failed: &mut HashSet<TileKey>,
+        key: TileKey,
+        action: &str,
+        error: impl std::fmt::Display,
+    ) {
+        if failed.is_empty() {
+            eprintln!(
+                "Could not {action} a tile of {:?}: {error}",
+                self.pyramid.path
+            );
+        }
+        failed.insert(key);
+    }
+}
@@ -0,0 +1,315 @@
+//! Watches the folders of a `Directory` so that the list of files can be kept
+//! up to date while the window is open.
+//!
+//! This uses inotify and is only available on Linux. On other platforms
+//! `FolderWatcher::new` fails and the folder is only read again when the
+//! window gains focus.
+
+use std::path::PathBuf;
+
+pub use imp::FolderWatcher;
+
+/// A change in one of the watched folders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchEvent {
+    /// A file was written and closed or moved into a watched folder
+    Written(PathBuf),
+    /// A file was deleted or moved out of a watched folder
+    Removed(PathBuf),
+    /// A subfolder was added or removed, a watched folder was removed itself
+    /// or some events were lost. The folder has to be read again.
+    Rescan,
+}
+
+/// Called from the watcher thread after new events were sent, to wake up the
+/// event loop
+pub type WakeFn = Box<dyn Fn() + Send>;
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{
+        collections::{HashMap, HashSet},
+        ffi::{CString, OsStr},
+        io, mem,
+        os::unix::ffi::OsStrExt,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            mpsc::{channel, Receiver, Sender},
+            Arc, Mutex,
+        },
+        thread::{self, JoinHandle},
+    };
+
+    use log::debug;
+
+    use super::{WakeFn, WatchEvent};
+
+    const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE
+        | libc::IN_CREATE
+        | libc::IN_MOVED_TO
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_DELETE_SELF
+        | libc::IN_MOVE_SELF
+        | libc::IN_ONLYDIR;
+
+    /// How often the watcher thread checks if it should stop
+    const POLL_TIMEOUT_MS: i32 = 250;
+
+    const EVENT_HEADER_SIZE: usize = mem::size_of::<libc::inotify_event>();
+
+    /// Maps the watch descriptors to the folders they watch
+    type Watches = Arc<Mutex<HashMap<i32, PathBuf>>>;
+
+    pub struct FolderWatcher {
+        fd: i32,
+        watches: Watches,
+        receiver: Receiver<WatchEvent>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl FolderWatcher {
+        /// Starts a thread that waits for changes. `wake` is called whenever
+        /// there are new events to get with `try_get_events`.
+        pub fn new(wake: WakeFn) -> io::Result<FolderWatcher> {
+            let fd = unsafe {
+                libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC)
+            };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let watches = Watches::default();
+            let stop = Arc::new(AtomicBool::new(false));
+            let (sender, receiver) = channel();
+            let thread = {
+                let watches = watches.clone();
+                let stop = stop.clone();
+                thread::Builder::new()
+                    .name("folder-watcher".to_owned())
+                    .spawn(move || {
+                        read_events(fd, &watches, &sender, &wake, &stop)
+                    })
+            };
+            match thread {
+                Ok(thread) => Ok(FolderWatcher {
+                    fd,
+                    watches,
+                    receiver,
+                    stop,
+                    thread: Some(thread),
+                }),
+                Err(e) => {
+                    unsafe { libc::close(fd) };
+                    Err(e)
+                }
+            }
+        }
+
+        /// Watches exactly the folders in `folders` from now on
+        pub fn set_folders(&mut self, folders: &[PathBuf]) {
+            let fd = self.fd;
+            let folders = folders.iter().collect::<HashSet<_>>();
+            let mut watches = self.watches.lock().unwrap();
+            watches.retain(|&wd, folder| {
+                let keep = folders.contains(folder);
+                if !keep {
+                    unsafe { libc::inotify_rm_watch(fd, wd) };
+                }
+                keep
+            });
+            let watched = watches.values().cloned().collect::<HashSet<_>>();
+            for folder in folders {
+                if watched.contains(folder) {
+                    continue;
+                }
+                match add_watch(fd, folder) {
+                    Ok(wd) => {
+                        watches.insert(wd, folder.clone());
+                    }
+                    Err(e) => {
+                        debug!("Could not watch the folder {:?}: {}", folder, e)
+                    }
+                }
+            }
+        }
+
+        /// Returns the events that arrived since the last call
+        pub fn try_get_events(&self) -> Vec<WatchEvent> {
+            self.receiver.try_iter().collect()
+        }
+    }
+
+    impl Drop for FolderWatcher {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+            unsafe { libc::close(self.fd) };
+        }
+    }
+
+    fn add_watch(fd: i32, folder: &Path) -> io::Result<i32> {
+        let path = CString::new(folder.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd =
+            unsafe { libc::inotify_add_watch(fd, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(wd)
+        }
+    }
+
+    fn read_events(
+        fd: i32,
+        watches: &Watches,
+        sender: &Sender<WatchEvent>,
+        wake: &WakeFn,
+        stop: &AtomicBool,
+    ) {
+        // `u64` to get the alignment of `inotify_event`
+        let mut buffer = [0u64; 1024];
+        while !stop.load(Ordering::Relaxed) {
+            let mut poll_fd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, POLL_TIMEOUT_MS) };
+            if ready < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                eprintln!("Stopped watching the folder for changes: {e}");
+                return;
+            }
+            if ready == 0 {
+                continue;
+            }
+            let len = unsafe {
+                libc::read(
+                    fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    mem::size_of_val(&buffer),
+                )
+            };
+            if len <= 0 {
+                continue;
+            }
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    buffer.as_ptr() as *const u8,
+                    len as usize,
+                )
+            };
+            let events = parse_events(bytes, &watches.lock().unwrap());
+            if events.is_empty() {
+                continue;
+            }
+            for event in events {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+            wake();
+        }
+    }
+
+    fn parse_events(
+        mut bytes: &[u8],
+        watches: &HashMap<i32, PathBuf>,
+    ) -> Vec<WatchEvent> {
+        let mut events = Vec::new();
+        while bytes.len() >= EVENT_HEADER_SIZE {
+            let event = unsafe {
+                std::ptr::read_unaligned(
+                    bytes.as_ptr() as *const libc::inotify_event
+                )
+            };
+            let name_end =
+                (EVENT_HEADER_SIZE + event.len as usize).min(bytes.len());
+            // The name is padded with NUL bytes
+            let name = bytes[EVENT_HEADER_SIZE..name_end]
+                .split(|&b| b == 0)
+                .next()
+                .unwrap_or_default();
+            bytes = &bytes[name_end..];
+            if let Some(event) =
+                to_watch_event(&event, OsStr::from_bytes(name), watches)
+            {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    fn to_watch_event(
+        event: &libc::inotify_event,
+        name: &OsStr,
+        watches: &HashMap<i32, PathBuf>,
+    ) -> Option<WatchEvent> {
+        let mask = event.mask;
+        if mask
+            & (libc::IN_Q_OVERFLOW | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF)
+            != 0
+        {
+            return Some(WatchEvent::Rescan);
+        }
+        // Events of removed watches are ignored
+        let folder = watches.get(&event.wd)?;
+        if name.is_empty() {
+            return None;
+        }
+        let path = folder.join(name);
+        let added = libc::IN_CREATE | libc::IN_MOVED_TO;
+        let removed = libc::IN_DELETE | libc::IN_MOVED_FROM;
+        if mask & libc::IN_ISDIR != 0 {
+            if mask & (added | removed) != 0 {
+                return Some(WatchEvent::Rescan);
+            }
+            None
+        } else if mask & (libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) != 0 {
+            Some(WatchEvent::Written(path))
+        } else if mask & libc::IN_CREATE != 0 {
+            // Regular files are reported once they are written, but symlinks
+            // are never written
+            let metadata = path.symlink_metadata().ok()?;
+            metadata
+                .file_type()
+                .is_symlink()
+                .then_some(WatchEvent::Written(path))
+        } else if mask & removed != 0 {
+            Some(WatchEvent::Removed(path))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod imp {
+    use std::{io, path::PathBuf};
+
+    use super::{WakeFn, WatchEvent};
+
+    pub struct FolderWatcher;
+
+    impl FolderWatcher {
+        pub fn new(_wake: WakeFn) -> io::Result<FolderWatcher> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "watching folders is only supported on Linux",
+            ))
+        }
+
+        pub fn set_folders(&mut self, _folders: &[PathBuf]) {}
+
+        pub fn try_get_events(&self) -> Vec<WatchEvent> {
+            Vec::new()
+        }
+    }
+}
@@ -3,12 +3,14 @@ use std::{cell::RefCell, collections::HashMap, process::Command, rc::Rc};
 use gelatin::glium::glutin::event::ModifiersState;
 use lazy_static::lazy_static;
 
-use crate::configuration::Configuration;
+use crate::{command_runner::CommandRunner, configuration::Configuration};
 
 pub static TOGGLE_FULLSCREEN_NAME: &str = "toggle_fullscreen";
 pub static ESCAPE_NAME: &str = "escape";
 pub static IMG_NEXT_NAME: &str = "img_next";
 pub static IMG_PREV_NAME: &str = "img_prev";
+pub static PAGE_NEXT_NAME: &str = "page_next";
+pub static PAGE_PREV_NAME: &str = "page_prev";
 pub static IMG_ORIG_NAME: &str = "img_orig";
 pub static IMG_FIT_NAME: &str = "img_fit";
 pub static IMG_FIT_BEST_NAME: &str = "img_fit_best";
@@ -20,6 +22,18 @@ pub static PLAY_PRESENT_NAME: &str = "play_present";
 pub static PLAY_PRESENT_RND_NAME: &str = "play_present_rnd";
 pub static TOGGLE_ANTIALIAS_NAME: &str = "toggle_antialias";
 pub static SET_AUTOMATIC_ANTIALIAS_NAME: &str = "automatic_antialias";
+pub static TOGGLE_RECURSIVE_NAME: &str = "toggle_recursive";
+pub static CYCLE_SORT_NAME: &str = "cycle_sort";
+pub static REVERSE_SORT_NAME: &str = "reverse_sort";
+pub static TOGGLE_FOLLOW_NAME: &str = "toggle_follow";
+pub static TOGGLE_GALLERY_NAME: &str = "toggle_gallery";
+pub static TOGGLE_FILMSTRIP_NAME: &str = "toggle_filmstrip";
+#[cfg(feature = "raw")]
+pub static TOGGLE_RAW_DEVELOP_NAME: &str = "toggle_raw_develop";
+pub static TOGGLE_COLOR_MANAGEMENT_NAME: &str = "toggle_color_management";
+pub static CYCLE_TONE_MAP_NAME: &str = "cycle_tone_map";
+pub static EXPOSURE_UP_NAME: &str = "exposure_up";
+pub static EXPOSURE_DOWN_NAME: &str = "exposure_down";
 pub static ZOOM_IN_NAME: &str = "zoom_in";
 pub static ZOOM_OUT_NAME: &str = "zoom_out";
 pub static PAN_LEFT_NAME: &str = "pan_left";
@@ -34,6 +48,8 @@ lazy_static! {
         m.insert(ESCAPE_NAME, vec!["Escape"]);
         m.insert(IMG_NEXT_NAME, vec!["D", "Right", "PageDown"]);
         m.insert(IMG_PREV_NAME, vec!["A", "Left", "PageUp"]);
+        m.insert(PAGE_NEXT_NAME, vec!["Alt+Right", "Alt+PageDown"]);
+        m.insert(PAGE_PREV_NAME, vec!["Alt+Left", "Alt+PageUp"]);
         m.insert(IMG_ORIG_NAME, vec!["Q", "1"]);
         m.insert(IMG_FIT_NAME, vec!["F"]);
         m.insert(IMG_FIT_BEST_NAME, vec!["E"]);
@@ -45,6 +61,18 @@ lazy_static! {
         m.insert(PLAY_PRESENT_RND_NAME, vec!["Alt+P"]);
         m.insert(TOGGLE_ANTIALIAS_NAME, vec!["S"]);
         m.insert(SET_AUTOMATIC_ANTIALIAS_NAME, vec!["Alt+S"]);
+        m.insert(TOGGLE_RECURSIVE_NAME, vec!["R"]);
+        m.insert(CYCLE_SORT_NAME, vec!["O"]);
+        m.insert(REVERSE_SORT_NAME, vec!["Alt+O"]);
+        m.insert(TOGGLE_FOLLOW_NAME, vec!["Alt+F"]);
+        m.insert(TOGGLE_GALLERY_NAME, vec!["G"]);
+        m.insert(TOGGLE_FILMSTRIP_NAME, vec!["T"]);
+        #[cfg(feature = "raw")]
+        m.insert(TOGGLE_RAW_DEVELOP_NAME, vec!["Alt+R"]);
+        m.insert(TOGGLE_COLOR_MANAGEMENT_NAME, vec!["Alt+C"]);
+        m.insert(CYCLE_TONE_MAP_NAME, vec!["Alt+T"]);
+        m.insert(EXPOSURE_UP_NAME, vec!["Alt+Up"]);
+        m.insert(EXPOSURE_DOWN_NAME, vec!["Alt+Down"]);
         m
     };
 }
@@ -63,49 +91,87 @@ pub fn char_to_input_key(ch: char) -> String {
     input_key
 }
 
+/// Replaces every `${name}` in `string` with the value of `name` from `var_map`.
+///
+/// Variables that are not in `var_map` are left untouched and `$${` produces a
+/// literal `${`. The substituted values are never searched for variables, so a
+/// file name containing `${` is passed on unchanged.
 fn substitute_command_parameters(
     string: &str,
-    var_map: &HashMap<&str, &str>,
+    var_map: &HashMap<&str, String>,
 ) -> String {
-    let mut result = String::from(string);
-    for (&var_name, &substitute) in var_map.iter() {
-        result = result.replace(var_name, substitute);
+    let mut result = String::with_capacity(string.len());
+    let mut rest = string;
+    while let Some(dollar_pos) = rest.find('$') {
+        result.push_str(&rest[..dollar_pos]);
+        rest = &rest[dollar_pos..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let value = after
+                .find('}')
+                .and_then(|end| Some((var_map.get(&after[..end])?, end)));
+            if let Some((value, end)) = value {
+                result.push_str(value);
+                rest = &after[end + 1..];
+            } else {
+                result.push_str("${");
+                rest = after;
+            }
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
     }
+    result.push_str(rest);
     result
 }
 
 /// Execute all custom commands that were triggered by the input key and modifier set.
-/// Note: img_path and folder_path both have to be str instead of Path because we
-/// wouldn't be able to construct a command from them if they cannot be converted to
-/// valid UTF-8.
+///
+/// `var_map` holds the values of the variables that may appear in the
+/// program, the arguments and the environment variables of the commands. See
+/// `configuration::Command` for the list of variables.
+///
+/// The commands are started on the `runner` and are only waited on if the
+/// command is configured with `wait = true`.
 pub fn execute_triggered_commands(
     config: Rc<RefCell<Configuration>>,
+    runner: &mut CommandRunner,
     input_key: &str,
     modifiers: ModifiersState,
-    img_path: &str,
-    folder_path: &str,
+    var_map: &HashMap<&str, String>,
 ) {
     let config = config.borrow();
     if let Some(ref commands) = config.commands {
-        let mut var_map = HashMap::with_capacity(2);
-        var_map.insert("${img}", img_path);
-        var_map.insert("${folder}", folder_path);
         for command in commands.iter() {
             if keys_triggered(&command.input, input_key, modifiers) {
-                let mut cmd = Command::new(&command.program);
+                let mut cmd = Command::new(substitute_command_parameters(
+                    &command.program,
+                    var_map,
+                ));
                 if let Some(ref args) = command.args {
                     cmd.args(args.iter().map(|arg| {
-                        substitute_command_parameters(arg, &var_map)
+                        substitute_command_parameters(arg, var_map)
                     }));
                 }
                 if let Some(ref envs) = command.envs {
                     cmd.envs(envs.iter().map(|env_var| {
-                        (env_var.name.as_str(), env_var.value.as_str())
+                        (
+                            env_var.name.as_str(),
+                            substitute_command_parameters(
+                                &env_var.value,
+                                var_map,
+                            ),
+                        )
                     }));
                 }
-                if let Err(e) = cmd.status() {
-                    eprintln!("Error while executing the following user command. See the error below.\n{:?}\nError: {:?}", command, e);
-                }
+                runner.run(
+                    cmd,
+                    command.wait.unwrap_or(false),
+                    command.reload_after.unwrap_or(false),
+                );
             }
         }
     }
@@ -173,3 +239,52 @@ pub fn action_triggered(
         keys_triggered(keys.as_slice(), input_key, modifiers)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<&'static str, String> {
+        let mut map = HashMap::new();
+        map.insert("path", "/images/a.png".to_owned());
+        map.insert("name", "${path}.png".to_owned());
+        map
+    }
+
+    #[test]
+    fn substitutes_known_variables() {
+        assert_eq!(
+            substitute_command_parameters("open ${path} now", &vars()),
+            "open /images/a.png now"
+        );
+    }
+
+    #[test]
+    fn escaped_dollar_produces_literal() {
+        assert_eq!(
+            substitute_command_parameters("$${path} ${path}", &vars()),
+            "${path} /images/a.png"
+        );
+    }
+
+    #[test]
+    fn unknown_variable_is_left_as_is() {
+        assert_eq!(
+            substitute_command_parameters("${unknown} $HOME", &vars()),
+            "${unknown} $HOME"
+        );
+    }
+
+    #[test]
+    fn unterminated_variable_is_left_as_is() {
+        assert_eq!(substitute_command_parameters("${path", &vars()), "${path");
+    }
+
+    #[test]
+    fn values_are_not_substituted_again() {
+        assert_eq!(
+            substitute_command_parameters("${name}", &vars()),
+            "${path}.png"
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! Remote control of a running instance through a Unix domain socket.
+//!
+//! Every line sent to the socket is a JSON object with a `cmd` field and every
+//! request is answered with a single line of JSON. The `cmd` is either the name
+//! of an action (for example `img_next`, `img_fit` or `toggle_fullscreen`, see
+//! `input_handling`) or one of the following:
+//!
+//! - `{"cmd": "jump_to_path", "path": "/absolute/path.jpg"}`
+//! - `{"cmd": "jump_to_index", "index": 3}`
+//! - `{"cmd": "open_playlist", "paths": ["/a/b.jpg", "/c/d.png"]}`
+//! - `{"cmd": "zoom", "percent": 150}`
+//! - `{"cmd": "state"}`: does nothing, only returns the state
+//! - `{"cmd": "raise"}`: brings the window to the front
+//! - `{"cmd": "quit"}`
+//!
+//! The response looks like `{"ok": true, "path": "/a/b.jpg", "index": 2, "count": 10}`
+//! where `index` and `count` describe the position of the current image in its
+//! folder. If the request failed `ok` is false and `error` holds the reason.
+//! Note that loading images is asynchronous, so the state in the response to
+//! `img_next` still describes the image shown before.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{DirBuilderExt, MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use gelatin::{
+    application::request_exit, glium::glutin::event_loop::EventLoopProxy,
+    window::Window,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{widgets::picture_widget::PictureWidget, PROJECT_DIRS};
+
+const SOCKET_NAME: &str = "alloy.sock";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Request {
+    pub cmd: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paths: Option<Vec<PathBuf>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percent: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub path: Option<PathBuf>,
+    pub index: Option<usize>,
+    pub count: Option<usize>,
+}
+
+impl Response {
+    fn error(msg: String) -> Response {
+        Response {
+            ok: false,
+            error: Some(msg),
+            ..Default::default()
+        }
+    }
+}
+
+/// A request received by the server that's waiting for a response
+pub struct PendingRequest {
+    pub request: Request,
+    response_sender: Sender<Response>,
+}
+
+impl PendingRequest {
+    pub fn respond(self, response: Response) {
+        // The client may have disconnected in the meantime, that's fine.
+        let _ = self.response_sender.send(response);
+    }
+}
+
+/// Returns the path of the socket that the server listens on.
+///
+/// This is in the runtime directory (`$XDG_RUNTIME_DIR`) when there's one, and
+/// in a directory of the current user within the temp directory otherwise.
+pub fn socket_path() -> PathBuf {
+    let runtime_dir = PROJECT_DIRS.as_ref().and_then(|d| d.runtime_dir());
+    match runtime_dir {
+        Some(dir) => dir.join(SOCKET_NAME),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            std::env::temp_dir()
+                .join(format!("alloy-{}", uid))
+                .join(SOCKET_NAME)
+        }
+    }
+}
+
+/// Creates the directory of the socket, accessible only by the current user.
+/// Fails if the directory exists but others can access it too.
+fn create_socket_dir(dir: &Path) -> io::Result<()> {
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    let metadata = fs::metadata(dir)?;
+    let uid = unsafe { libc::getuid() };
+    if metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} is accessible by other users", dir),
+        ));
+    }
+    Ok(())
+}
+
+pub struct IpcServer {
+    socket_path: PathBuf,
+    receiver: Receiver<PendingRequest>,
+}
+
+impl IpcServer {
+    /// Starts listening on `socket_path`. The event loop is woken up through
+    /// `proxy` whenever a request arrives.
+    ///
+    /// Fails with `AddrInUse` if another instance is already listening on the
+    /// socket.
+    pub fn start(
+        socket_path: PathBuf,
+        proxy: EventLoopProxy<()>,
+    ) -> io::Result<IpcServer> {
+        if socket_path.exists() {
+            if UnixStream::connect(&socket_path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{:?} is used by another instance", socket_path),
+                ));
+            }
+            // Nobody is listening, this was left behind by a crashed instance.
+            fs::remove_file(&socket_path)?;
+        }
+        if let Some(parent) = socket_path.parent() {
+            create_socket_dir(parent)?;
+        }
+        let listener = UnixListener::bind(&socket_path)?;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let sender = sender.clone();
+                        let proxy = proxy.clone();
+                        thread::spawn(move || {
+                            if let Err(e) =
+                                Self::serve_client(stream, sender, proxy)
+                            {
+                                eprintln!("Error while serving a remote control client: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => eprintln!(
+                        "Error while accepting a remote control client: {e}"
+                    ),
+                }
+            }
+        });
+        Ok(IpcServer {
+            socket_path,
+            receiver,
+        })
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Returns the requests that arrived since the last call.
+    pub fn try_get_requests(&self) -> Vec<PendingRequest> {
+        self.receiver.try_iter().collect()
+    }
+
+    fn serve_client(
+        stream: UnixStream,
+        sender: Sender<PendingRequest>,
+        proxy: EventLoopProxy<()>,
+    ) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    let (response_sender, response_receiver) = channel();
+                    let pending = PendingRequest {
+                        request,
+                        response_sender,
+                    };
+                    if sender.send(pending).is_err()
+                        || proxy.send_event(()).is_err()
+                    {
+                        // The application is shutting down
+                        return Ok(());
+                    }
+                    match response_receiver.recv() {
+                        Ok(response) => response,
+                        Err(_) => return Ok(()),
+                    }
+                }
+                Err(e) => Response::error(format!("Invalid request: {e}")),
+            };
+            let mut response = serde_json::to_string(&response)?;
+            response.push('\n');
+            writer.write_all(response.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes `request` on the picture widget and returns the state afterwards.
+pub fn handle_request(
+    window: &Window,
+    picture_widget: &PictureWidget,
+    request: &Request,
+) -> Response {
+    let result = match request.cmd.as_str() {
+        "jump_to_path" => match &request.path {
+            Some(path) => {
+                picture_widget.jump_to_path(path);
+                Ok(())
+            }
+            None => Err("`jump_to_path` requires a `path`".to_string()),
+        },
+        "open_playlist" => match &request.paths {
+            Some(paths) => picture_widget.open_playlist(paths.clone()),
+            None => Err("`open_playlist` requires `paths`".to_string()),
+        },
+        "jump_to_index" => match request.index {
+            Some(index) => {
+                picture_widget.jump_to_index(index);
+                Ok(())
+            }
+            None => Err("`jump_to_index` requires an `index`".to_string()),
+        },
+        "zoom" => match request.percent {
+            Some(percent) if percent > 0.0 => {
+                picture_widget.set_zoom(percent);
+                Ok(())
+            }
+            _ => Err("`zoom` requires a positive `percent`".to_string()),
+        },
+        "raise" => {
+            window.focus();
+            Ok(())
+        }
+        "quit" => {
+            request_exit();
+            Ok(())
+        }
+        "state" => Ok(()),
+        action => {
+            if picture_widget.perform_action(action) {
+                Ok(())
+            } else {
+                Err(format!("Unknown command `{action}`"))
+            }
+        }
+    };
+    let (index, count) = picture_widget.folder_position();
+    Response {
+        ok: result.is_ok(),
+        error: result.err(),
+        path: picture_widget.shown_file_path(),
+        index,
+        count,
+    }
+}
+
+/// Sends `request` to the running instance and returns its response.
+pub fn send_request(
+    socket_path: &Path,
+    request: &Request,
+) -> io::Result<Response> {
+    let stream = UnixStream::connect(socket_path)?;
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    (&stream).write_all(line.as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+/// Asks the instance listening on the socket to show `file_path`, or the
+/// `playlist` if there's one, and to bring its window to the front.
+///
+/// Returns false if there's no instance running or if it couldn't be asked to
+/// show the files. The caller should open its own window in that case.
+pub fn forward_to_running_instance(
+    file_path: Option<&str>,
+    playlist: Option<&[PathBuf]>,
+) -> bool {
+    let mut requests = Vec::with_capacity(2);
+    let open_request = match (playlist, file_path) {
+        (Some(playlist), _) => Some(playlist_request(playlist)),
+        (None, Some(file_path)) => {
+            Some(make_request("jump_to_path", Some(file_path)))
+        }
+        (None, None) => None,
+    };
+    match open_request {
+        Some(Ok(request)) => requests.push(request),
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            return false;
+        }
+        None => (),
+    }
+    requests.push(Request {
+        cmd: "raise".to_owned(),
+        ..Default::default()
+    });
+    let socket_path = socket_path();
+    requests.iter().all(|request| {
+        match send_request(&socket_path, request) {
+            Ok(response) if response.ok => true,
+            Ok(response) => {
+                let error = response.error.unwrap_or_default();
+                eprintln!(
+                    "The running instance refused `{}`: {error}",
+                    request.cmd
+                );
+                false
+            }
+            // Most likely there's no instance running
+            Err(_) => false,
+        }
+    })
+}
+
+/// Sends a single command to the running instance, prints the response and
+/// returns the exit code for the process.
+///
+/// `cmd` is either a complete JSON request or the name of a command, in which
+/// case `arg` is used as its `path`, `index` or `percent`.
+pub fn run_client(cmd: &str, arg: Option<&str>) -> i32 {
+    let request = if cmd.trim_start().starts_with('{') {
+        serde_json::from_str(cmd).map_err(|e| e.to_string())
+    } else {
+        make_request(cmd, arg)
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("Invalid remote command: {e}");
+            return 1;
+        }
+    };
+    let socket_path = socket_path();
+    match send_request(&socket_path, &request) {
+        Ok(response) => {
+            println!("{}", serde_json::to_string(&response).unwrap());
+            if response.ok {
+                0
+            } else {
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!(
+                "Could not reach a running instance at {:?}: {e}",
+                socket_path
+            );
+            1
+        }
+    }
+}
+
+fn playlist_request(playlist: &[PathBuf]) -> Result<Request, String> {
+    // The running instance may have a different working directory
+    let paths = playlist
+        .iter()
+        .map(|path| {
+            fs::canonicalize(path)
+                .map_err(|e| format!("Invalid path {path:?}: {e}"))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Request {
+        cmd: "open_playlist".to_owned(),
+        paths: Some(paths),
+        ..Default::default()
+    })
+}
+
+fn make_request(cmd: &str, arg: Option<&str>) -> Result<Request, String> {
+    let mut request = Request {
+        cmd: cmd.to_owned(),
+        ..Default::default()
+    };
+    let missing_arg = || format!("`{cmd}` requires an argument");
+    match cmd {
+        "jump_to_path" => {
+            let path = arg.ok_or_else(missing_arg)?;
+            // The running instance may have a different working directory
+            let path = fs::canonicalize(path)
+                .map_err(|e| format!("Invalid path {path:?}: {e}"))?;
+            request.path = Some(path);
+        }
+        "jump_to_index" => {
+            let index = arg.ok_or_else(missing_arg)?;
+            request.index =
+                Some(index.parse().map_err(|e| format!("Invalid index: {e}"))?);
+        }
+        "zoom" => {
+            let percent = arg.ok_or_else(missing_arg)?;
+            request.percent = Some(
+                percent.parse().map_err(|e| format!("Invalid zoom: {e}"))?,
+            );
+        }
+        _ => (),
+    }
+    Ok(request)
+}
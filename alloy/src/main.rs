@@ -3,9 +3,9 @@
 use std::{
     cell::{Cell, RefCell},
     f32,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
-    sync::{Arc, Mutex},
+    sync::{atomic::Ordering, Arc, Mutex},
 };
 
 use directories_next::ProjectDirs;
@@ -20,28 +20,34 @@ use gelatin::{
     label::*,
     line_layout_container::*,
     misc::*,
-    picture::*,
+    text::Font,
     window::{Window, WindowDescriptor},
+    NextUpdate,
 };
 use lazy_static::lazy_static;
 use log::trace;
 
 use crate::{
     configuration::{Cache, ConfigWindowSection, Configuration, Theme},
+    image_cache::{set_display_profile, sort::SortOrder, KEEP_TILES},
     widgets::{
         bottom_bar::BottomBar, copy_notification::CopyNotifications,
-        help_screen::*, picture_widget::*,
+        gallery::*, help_screen::*, picture_widget::*,
     },
 };
 
 mod clipboard_handler;
 mod cmd_line;
+mod command_runner;
 mod configuration;
 mod handle_panic;
 mod image_cache;
 mod input_handling;
+#[cfg(unix)]
+mod ipc;
 mod parallel_action;
 mod playback_manager;
+mod playlist;
 mod shaders;
 mod utils;
 mod version;
@@ -53,8 +59,59 @@ lazy_static! {
     pub static ref PROJECT_DIRS: Option<ProjectDirs> = ProjectDirs::from("", "", "Emulsion");
 }
 
-static USAGE: &[u8] = include_bytes!("../resource/usage.png");
-static LEFT_TO_PAN: &[u8] = include_bytes!("../resource/use-left-to-pan.png");
+static USAGE_INTRO: &str = "\
+Click on the 'i' at the bottom to toggle this screen.
+
+Click on the sun/moon to change the UI shade.
+
+Click on the bar at the bottom to jump to an image in the folder or hold
+and drag to scrub through the images in the folder.";
+static USAGE_SHORTCUTS: &[(&str, &str)] = &[
+    ("Quit Alloy", "Esc"),
+    ("", ""),
+    ("Previous Image", "\u{2190}  or  A  or  PgUp"),
+    ("Next Image", "\u{2192}  or  D  or  PgDown"),
+    ("Previous Page", "Alt+\u{2190}  or  Alt+PgUp"),
+    ("Next Page", "Alt+\u{2192}  or  Alt+PgDown"),
+    ("", ""),
+    ("Include Subfolders", "R"),
+    ("Next Sort Order", "O"),
+    ("Reverse Sort Order", "Alt+O"),
+    ("Follow New Images", "Alt+F"),
+    ("", ""),
+    ("Zoom In", "Scroll Up"),
+    ("Zoom Out", "Scroll Down"),
+    ("", ""),
+    ("Show at Original Scale", "Q  or  1"),
+    ("Best Fit - Use Original Size if Fits", "E"),
+    ("Fit to Borders", "F"),
+    ("", ""),
+    ("Presentation Playback", "P"),
+    ("Shuffled Presentation Playback", "Alt+P"),
+    ("Animation Playback", "Alt+A  or  Alt+V"),
+    ("", ""),
+    ("Pan Camera", "Left Mouse Button  or  Space"),
+    ("Full-Screen Mode", "Double Click  or  F11  or  Enter"),
+    ("", ""),
+    ("Toggle Antialiasing", "S"),
+    ("Automatic Antialiasing", "Alt+S"),
+    #[cfg(feature = "raw")]
+    ("Develop RAW Images", "Alt+R"),
+    ("Color Management", "Alt+C"),
+    ("Next Tone Mapping", "Alt+T"),
+    ("Exposure Up / Down", "Alt+\u{2191}  /  Alt+\u{2193}"),
+    ("", ""),
+    ("Gallery", "G"),
+    ("Filmstrip", "T"),
+    ("", ""),
+    ("Move Image to the Trash", "Del"),
+    ("Copy Image to the Clipboard", "Ctrl+C/\u{2318}+C"),
+];
+static LEFT_TO_PAN: &str = "Use the Left Mouse Button to pan";
+static UI_FONT: &[u8] =
+    include_bytes!("../resource_dev/Usage Panel/FiraSans-Regular.ttf");
+static UI_FALLBACK_FONT: &[u8] =
+    include_bytes!("../resource_dev/Usage Panel/DejaVuSans.ttf");
 
 // ========================================================
 // Not-so glorious main function
@@ -68,14 +125,65 @@ fn main() {
     let (config_path, cache_path) = get_config_and_cache_paths();
 
     let args = cmd_line::parse_args();
+    if let Some(remote_cmd) = &args.remote {
+        std::process::exit(run_remote_command(remote_cmd, args.file_path()));
+    }
+
+    let playlist = playlist::playlist_from_args(&args.file_paths);
 
     let cache = Cache::load(&cache_path);
-    let config = Configuration::load(config_path);
+    let config = match &args.config {
+        Some(config_path) => {
+            let config = Configuration::load(config_path);
+            if let Err(e) = &config {
+                eprintln!("{e}");
+            }
+            config
+        }
+        None => Configuration::load(config_path),
+    };
+
+    #[cfg(unix)]
+    let single_instance = matches!(
+        config,
+        Ok(Configuration {
+            window: Some(ConfigWindowSection {
+                single_instance: Some(true),
+                ..
+            }),
+            ..
+        })
+    );
+    #[cfg(unix)]
+    if single_instance {
+        let playlist = playlist.as_deref();
+        if ipc::forward_to_running_instance(args.file_path(), playlist) {
+            return;
+        }
+    }
 
     let first_launch = cache.is_err();
     let cache = Arc::new(Mutex::new(cache.unwrap_or_default()));
     let config = Rc::new(RefCell::new(config.unwrap_or_default()));
 
+    // The profile is read before any image is loaded
+    let display_profile = config
+        .borrow()
+        .image
+        .as_ref()
+        .and_then(|image| image.display_profile.clone());
+    if let Some(path) = display_profile {
+        if let Err(e) = set_display_profile(Path::new(&path)) {
+            eprintln!("Could not use the display profile {path:?}: {e}");
+        }
+    }
+    let keep_tiles = config
+        .borrow()
+        .image
+        .as_ref()
+        .and_then(|image| image.keep_tiles);
+    KEEP_TILES.store(keep_tiles.unwrap_or(false), Ordering::SeqCst);
+
     let mut application = Application::new();
     let window: Rc<Window> = {
         let window_cache = &mut cache.lock().unwrap().window;
@@ -121,8 +229,18 @@ fn main() {
                 window_cache.win_y = window_defaults.win_y;
             }
         }
-        let pos = PhysicalPosition::new(window_cache.win_x, window_cache.win_y);
-        let size = PhysicalSize::new(window_cache.win_w, window_cache.win_h);
+        let mut pos =
+            PhysicalPosition::new(window_cache.win_x, window_cache.win_y);
+        let mut size =
+            PhysicalSize::new(window_cache.win_w, window_cache.win_h);
+        if let Some(geometry) = args.geometry {
+            if let Some((w, h)) = geometry.size {
+                size = PhysicalSize::new(w, h);
+            }
+            if let Some((x, y)) = geometry.position {
+                pos = PhysicalPosition::new(x, y);
+            }
+        }
         let window_desc = WindowDescriptor::builder()
             .icon(Some(make_icon()))
             .size(size)
@@ -137,51 +255,126 @@ fn main() {
             .window()
             .set_outer_position(pos);
 
-        if let Some(ConfigWindowSection {
-            start_maximized: Some(true),
-            ..
-        }) = window_cfg
+        if args.maximized
+            || matches!(
+                window_cfg,
+                Some(ConfigWindowSection {
+                    start_maximized: Some(true),
+                    ..
+                })
+            )
         {
             window.set_maximized(true);
         }
 
-        if let Some(ConfigWindowSection {
-            start_fullscreen: Some(true),
-            ..
-        }) = window_cfg
+        if args.fullscreen
+            || matches!(
+                window_cfg,
+                Some(ConfigWindowSection {
+                    start_fullscreen: Some(true),
+                    ..
+                })
+            )
         {
             window.set_fullscreen(true);
         }
         window
     };
-    add_window_movement_listener(&window, cache.clone());
+    // The window area given on the command line must not end up in the cache
+    if !args.overrides_window_area() {
+        add_window_movement_listener(&window, cache.clone());
+    }
+
+    // DejaVu covers far more scripts than Fira, this makes file names in
+    // most languages readable.
+    let ui_font = Font::from_static_bytes(UI_FONT).and_then(|f| {
+        Ok(f.with_fallback(&Font::from_static_bytes(UI_FALLBACK_FONT)?))
+    });
+    match ui_font {
+        Ok(font) => Font::set_default(Some(font)),
+        Err(e) => eprintln!("Failed to load the UI font: {}", e),
+    }
 
-    let usage_img = Picture::from_encoded_bytes(USAGE);
-    let help_screen = Rc::new(HelpScreen::new(usage_img));
-    let left_to_pan_img = Picture::from_encoded_bytes(LEFT_TO_PAN);
-    let left_to_pan_hint = Rc::new(HelpScreen::new(left_to_pan_img));
+    let help_screen = Rc::new(HelpScreen::new(USAGE_INTRO, USAGE_SHORTCUTS));
+    let left_to_pan_hint = Rc::new(HelpScreen::new(LEFT_TO_PAN, &[]));
 
     let copy_notifications_widget = Rc::new(Label::new());
     let copy_notifications = CopyNotifications::new(&copy_notifications_widget);
 
     let bottom_bar = Rc::new(BottomBar::new(&config.borrow()));
+    let gallery = Rc::new(GalleryWidget::new(config.clone()));
     let picture_widget = make_picture_widget(
         &window,
         bottom_bar.clone(),
         left_to_pan_hint.clone(),
+        gallery.clone(),
         copy_notifications,
         config.clone(),
         cache.clone(),
     );
 
-    if let Some(file_path) = args.file_path {
+    let recursive_cfg = matches!(
+        config.borrow().folder,
+        Some(configuration::ConfigFolderSection {
+            recursive: Some(true),
+            ..
+        })
+    );
+    if args.recursive || recursive_cfg {
+        picture_widget.set_recursive(true);
+    }
+    let sort_cfg = config
+        .borrow()
+        .folder
+        .as_ref()
+        .and_then(|f| f.sort.as_deref())
+        .and_then(|sort| match sort.parse::<SortOrder>() {
+            Ok(order) => Some(order),
+            Err(e) => {
+                eprintln!("Illegal configuration value: {e}");
+                None
+            }
+        });
+    if let Some(order) = args.sort.or(sort_cfg) {
+        picture_widget.set_sort_order(order);
+    }
+    let (watch_cfg, follow_cfg) = match &config.borrow().folder {
+        Some(folder) => (folder.watch, folder.follow),
+        None => (None, None),
+    };
+    if watch_cfg.unwrap_or(true) {
+        picture_widget.watch_folders(application.event_loop.create_proxy());
+    }
+    if args.follow || follow_cfg.unwrap_or(false) {
+        picture_widget.set_follow(true);
+    }
+    if let Some(playlist) = playlist {
+        if let Err(e) = picture_widget.open_playlist(playlist) {
+            eprintln!("{e}");
+        }
+    } else if let Some(file_path) = args.file_path() {
         picture_widget.jump_to_path(file_path);
     }
+    match args.zoom {
+        Some(cmd_line::Zoom::Fit) => picture_widget.set_fit_scaling(false),
+        Some(cmd_line::Zoom::Stretch) => picture_widget.set_fit_scaling(true),
+        Some(cmd_line::Zoom::Orig) => picture_widget.set_img_size_to_orig(),
+        Some(cmd_line::Zoom::Percent(percent)) => {
+            picture_widget.set_zoom(percent)
+        }
+        None => (),
+    }
+    if args.slideshow || args.random {
+        picture_widget.start_presentation(args.slideshow_interval, args.random);
+    }
 
     let picture_area_container = make_picture_area_container();
+    // The gallery comes first so that it ignores the key press that opens it
+    picture_area_container.add_child(gallery);
     picture_area_container.add_child(picture_widget.clone());
     picture_area_container.add_child(copy_notifications_widget);
     picture_area_container.add_child(left_to_pan_hint);
+    picture_area_container.add_child(bottom_bar.slider_preview.clone());
     picture_area_container.add_child(help_screen.clone());
 
     let root_container = make_root_container();
@@ -189,11 +382,15 @@ fn main() {
     root_container.add_child(bottom_bar.widget.clone());
 
     let theme = {
-        Rc::new(Cell::new(match &config.borrow().window {
-            Some(ConfigWindowSection {
-                theme: Some(theme_cfg),
-                ..
-            }) => *theme_cfg,
+        Rc::new(Cell::new(match (args.theme, &config.borrow().window) {
+            (Some(theme_arg), _) => theme_arg,
+            (
+                None,
+                Some(ConfigWindowSection {
+                    theme: Some(theme_cfg),
+                    ..
+                }),
+            ) => *theme_cfg,
             _ => cache.lock().unwrap().theme(),
         }))
     };
@@ -236,6 +433,12 @@ fn main() {
             picture_widget.jump_to_index(slider.value());
         });
     }
+    {
+        let picture_widget = picture_widget.clone();
+        bottom_bar.filmstrip.set_on_click(move |index| {
+            picture_widget.jump_to_index(index as u32);
+        });
+    }
     {
         let picture_widget = picture_widget.clone();
         bottom_bar.orig_scale_button.set_on_click(move || {
@@ -248,6 +451,8 @@ fn main() {
             picture_widget.set_img_size_to_fit(false);
         });
     }
+    #[cfg(unix)]
+    let picture_widget_for_ipc = picture_widget.clone();
     {
         bottom_bar.fit_stretch_button.set_on_click(move || {
             picture_widget.set_img_size_to_fit(true);
@@ -256,7 +461,6 @@ fn main() {
     let help_visible = Cell::new(first_launch);
     help_screen.set_visible(help_visible.get());
     {
-        let help_screen = help_screen;
         let bottom_bar_clone = bottom_bar.clone();
 
         bottom_bar.help_button.set_on_click(move || {
@@ -266,15 +470,77 @@ fn main() {
         });
     }
 
+    #[cfg(unix)]
+    let ipc_socket = {
+        let ipc_enabled = matches!(
+            &config.borrow().ipc,
+            Some(configuration::IpcSection {
+                enabled: Some(true)
+            })
+        );
+        if ipc_enabled || single_instance {
+            start_ipc_server(&mut application, &window, picture_widget_for_ipc)
+        } else {
+            None
+        }
+    };
+
     window.set_root(root_container);
 
     application.set_at_exit(Some(move || {
         cache.lock().unwrap().save(cache_path).unwrap();
+        #[cfg(unix)]
+        if let Some(socket_path) = ipc_socket {
+            let _ = std::fs::remove_file(socket_path);
+        }
     }));
     application.start_event_loop();
 }
 // ========================================================
 
+#[cfg(unix)]
+fn run_remote_command(cmd: &str, arg: Option<&str>) -> i32 {
+    ipc::run_client(cmd, arg)
+}
+
+#[cfg(not(unix))]
+fn run_remote_command(_cmd: &str, _arg: Option<&str>) -> i32 {
+    eprintln!("Remote control is only supported on Unix-like systems");
+    1
+}
+
+/// Returns the path of the socket if the server was started
+#[cfg(unix)]
+fn start_ipc_server(
+    application: &mut Application,
+    window: &Rc<Window>,
+    picture_widget: Rc<PictureWidget>,
+) -> Option<PathBuf> {
+    let window = window.clone();
+    let proxy = application.event_loop.create_proxy();
+    match ipc::IpcServer::start(ipc::socket_path(), proxy) {
+        Ok(server) => {
+            let socket_path = server.socket_path().to_owned();
+            application.add_global_event_handler(move |_| {
+                for pending in server.try_get_requests() {
+                    let response = ipc::handle_request(
+                        &window,
+                        &picture_widget,
+                        &pending.request,
+                    );
+                    pending.respond(response);
+                }
+                NextUpdate::Latest
+            });
+            Some(socket_path)
+        }
+        Err(e) => {
+            eprintln!("Could not start the remote control server: {e}");
+            None
+        }
+    }
+}
+
 fn make_icon() -> Icon {
     let img =
         image::load_from_memory(include_bytes!("../resource/emulsion48.png"))
@@ -332,6 +598,7 @@ fn make_picture_widget(
     window: &Rc<Window>,
     bottom_bar: Rc<BottomBar>,
     left_to_pan_hint: Rc<HelpScreen>,
+    gallery: Rc<GalleryWidget>,
     copy_notifications: CopyNotifications,
     config: Rc<RefCell<Configuration>>,
     cache: Arc<Mutex<Cache>>,
@@ -341,6 +608,7 @@ fn make_picture_widget(
         window,
         bottom_bar,
         left_to_pan_hint,
+        gallery,
         copy_notifications,
         config,
         cache,
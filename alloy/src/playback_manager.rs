@@ -2,6 +2,7 @@ use std::{
     io::Write,
     marker::PhantomData,
     mem,
+    ops::Range,
     path::{Path, PathBuf},
     rc::Rc,
     time::{Duration, Instant},
@@ -11,9 +12,15 @@ use gelatin::{glium::Display, window::Window};
 use log::{debug, trace};
 use rand::{seq::SliceRandom, thread_rng};
 
-use crate::image_cache::{self, AnimationFrameTexture, ImageCache};
+use crate::image_cache::{
+    self, sort::SortOrder, watcher::WakeFn, AnimationFrameTexture, FrameRect,
+    ImageCache, Thumbnail,
+};
 
 const NANOS_PER_SEC: u64 = 1_000_000_000;
+const DEFAULT_PRESENT_INTERVAL: Duration = Duration::from_secs(6);
+/// How often to check whether an SVG has been rendered
+const SVG_RENDER_POLL_INTERVAL: Duration = Duration::from_millis(20);
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum LoadRequest {
@@ -161,6 +168,13 @@ pub struct PlaybackManager {
     // filename: Option<OsString>,
     folder_player: ImgSequencePlayer<FolderPlayback>,
     image_player: ImgSequencePlayer<AnimPlayback>,
+
+    /// Show every image that's added to the folder
+    follow: bool,
+
+    /// The number of times the current animation has been played through
+    loops_played: u32,
+    prev_frame_index: usize,
 }
 
 impl PlaybackManager {
@@ -189,6 +203,9 @@ impl PlaybackManager {
             image_cache: ImageCache::new(cache_capaxity, thread_count),
             folder_player: ImgSequencePlayer::new(),
             image_player: ImgSequencePlayer::new(),
+            follow: false,
+            loops_played: 0,
+            prev_frame_index: 0,
         }
     }
 
@@ -224,6 +241,11 @@ impl PlaybackManager {
         // self.playback_state = PlaybackState::Present;
     }
 
+    /// Sets the time each image is shown for during presentations
+    pub fn set_present_interval(&mut self, interval: Duration) {
+        self.folder_player.present_interval = interval;
+    }
+
     /// Returns None when the folder hasn't finished filtering
     pub fn current_file_index(&mut self) -> Option<usize> {
         self.image_cache.current_file_index()
@@ -234,6 +256,25 @@ impl PlaybackManager {
         self.image_cache.current_dir_len()
     }
 
+    /// Returns None when the folder hasn't finished filtering
+    pub fn image_path_at_index(&mut self, index: usize) -> Option<PathBuf> {
+        self.image_cache.image_path_at_index(index)
+    }
+
+    /// The thumbnails of the images in `range`. See `ImageCache::thumbnails`
+    pub fn thumbnails(
+        &mut self,
+        display: &Display,
+        range: Range<usize>,
+    ) -> Vec<(usize, Thumbnail)> {
+        self.image_cache.thumbnails(display, range)
+    }
+
+    /// The index of the shown animation frame. This is 0 for still images.
+    pub fn current_frame_index(&self) -> usize {
+        self.image_cache.current_frame_index()
+    }
+
     pub fn update_directory(&mut self) -> image_cache::Result<()> {
         debug!("In `update_directory`");
         if let LoadRequest::None = self.folder_player.load_request {
@@ -258,6 +299,156 @@ impl PlaybackManager {
         Ok(())
     }
 
+    /// Keeps the list of images up to date while the folder changes. `wake`
+    /// is called when `process_folder_changes` has changes to apply.
+    pub fn watch_folders(&mut self, wake: WakeFn) -> std::io::Result<()> {
+        self.image_cache.watch_folders(wake)
+    }
+
+    /// False if the folder has to be read again with `update_directory` to
+    /// notice changes
+    pub fn is_watching(&self) -> bool {
+        self.image_cache.is_watching()
+    }
+
+    /// Applies the changes in the folder. The current image is loaded again
+    /// if it was modified or removed, and in follow mode the newest image is
+    /// shown.
+    ///
+    /// Returns true if the list of images changed.
+    pub fn process_folder_changes(&mut self) -> bool {
+        let changes = self.image_cache.process_folder_changes();
+        if self.follow {
+            if let Some(newest) = changes.added.last() {
+                self.request_load(LoadRequest::FilePath(newest.clone()));
+                return true;
+            }
+        }
+        if changes.current_changed {
+            if let LoadRequest::None = self.folder_player.load_request {
+                let path = self
+                    .image_cache
+                    .current_file_path()
+                    .unwrap_or_else(PathBuf::new);
+                self.request_load(LoadRequest::FilePath(path));
+            }
+        }
+        changes.current_changed
+            || !changes.added.is_empty()
+            || !changes.modified.is_empty()
+            || !changes.removed.is_empty()
+    }
+
+    /// Shows every image that's added to the folder while it's open
+    pub fn set_follow(&mut self, follow: bool) {
+        self.follow = follow;
+    }
+
+    pub fn follow(&self) -> bool {
+        self.follow
+    }
+
+    /// Includes or excludes the images in subfolders and keeps showing the
+    /// current image.
+    pub fn set_recursive(
+        &mut self,
+        recursive: bool,
+    ) -> image_cache::Result<()> {
+        self.image_cache.set_recursive(recursive)?;
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.request_load(LoadRequest::FilePath(path));
+        }
+        Ok(())
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.image_cache.recursive()
+    }
+
+    /// The folder whose subfolders are included, if any
+    pub fn recursive_root(&self) -> Option<&Path> {
+        self.image_cache.recursive_root()
+    }
+
+    /// Shows the page `amount` pages away from the current one in files that
+    /// have several, like scanned documents
+    pub fn page_jump(&mut self, amount: isize) {
+        if self.image_cache.jump_page(amount) {
+            if let Some(path) = self.image_cache.current_file_path() {
+                self.request_load(LoadRequest::FilePath(path));
+            }
+        }
+    }
+
+    /// The index of the shown page and the number of pages, if the current
+    /// file has several
+    pub fn current_page(&self) -> Option<(usize, usize)> {
+        self.image_cache.current_page()
+    }
+
+    /// Shows camera RAW files by developing their sensor data instead of
+    /// using the embedded preview
+    #[cfg(feature = "raw")]
+    pub fn set_develop_raw(&mut self, develop: bool) {
+        self.image_cache.set_develop_raw(develop);
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.request_load(LoadRequest::FilePath(path));
+        }
+    }
+
+    #[cfg(feature = "raw")]
+    pub fn develop_raw(&self) -> bool {
+        self.image_cache.develop_raw()
+    }
+
+    /// Converts images with an embedded ICC profile to the colors of the
+    /// display
+    pub fn set_manage_colors(&mut self, manage: bool) {
+        self.image_cache.set_manage_colors(manage);
+        if let Some(path) = self.image_cache.current_file_path() {
+            self.request_load(LoadRequest::FilePath(path));
+        }
+    }
+
+    pub fn manage_colors(&self) -> bool {
+        self.image_cache.manage_colors()
+    }
+
+    /// Renders the `region` of the current image again, with `scale` pixels
+    /// for each of its pixels, and shows it once it's done. Does nothing if
+    /// the current image isn't an SVG.
+    pub fn request_svg_render(&mut self, region: FrameRect, scale: f32) {
+        self.image_cache.request_svg_render(region, scale);
+    }
+
+    /// Sorts the images in the background, the current image stays current
+    pub fn set_sort_order(
+        &mut self,
+        order: SortOrder,
+    ) -> image_cache::Result<()> {
+        self.image_cache.set_sort_order(order)
+    }
+
+    /// `None` if the images are in their default order
+    pub fn sort_order(&self) -> Option<SortOrder> {
+        self.image_cache.sort_order()
+    }
+
+    /// Replaces the current folder with the files at `paths` and shows the
+    /// first one. The paths must be absolute.
+    pub fn open_playlist(
+        &mut self,
+        paths: Vec<PathBuf>,
+    ) -> image_cache::Result<()> {
+        self.image_cache.open_playlist(paths)?;
+        let path = self
+            .image_cache
+            .current_file_path()
+            .unwrap_or_else(PathBuf::new);
+        self.request_load(LoadRequest::FilePath(path));
+        Ok(())
+    }
+
     pub fn request_load(&mut self, request: LoadRequest) {
         self.folder_player.request_load(request);
         self.image_player.request_load(LoadRequest::Jump(0));
@@ -287,15 +478,73 @@ impl PlaybackManager {
         if file_changed {
             self.image_player.start_playback_forward();
             self.image_player.request_load(LoadRequest::Jump(0));
+            self.loops_played = 0;
+            self.prev_frame_index = 0;
         }
         if self.image_cache.loaded_still_image() {
             self.image_player.pause_playback();
         }
+        // The preview of a large image is replaced once the image is decoded
+        let shows_preview = self
+            .image_player
+            .image_texture
+            .as_ref()
+            .is_some_and(|frame| frame.preview.is_some());
+        if shows_preview {
+            self.image_player.request_load(LoadRequest::Jump(0));
+        }
         let img_player_next_update = self
             .image_player
             .update_image(&display, &mut self.image_cache);
         trace!("Image player next update: {:?}", img_player_next_update);
-        next_update.aggregate(img_player_next_update)
+        self.stop_after_last_loop();
+        let svg_next_update = self.receive_svg_render(&display);
+        next_update
+            .aggregate(img_player_next_update)
+            .aggregate(svg_next_update)
+    }
+
+    /// Shows the latest render of the current SVG, see `request_svg_render`
+    fn receive_svg_render(&mut self, display: &Display) -> gelatin::NextUpdate {
+        match self.image_cache.receive_svg_render(display) {
+            Ok(Some(frame)) => self.image_player.image_texture = Some(frame),
+            Ok(None) => (),
+            Err(e) => eprintln!("Failed to upload the rendered SVG: {e}"),
+        }
+        if self.image_cache.svg_render_pending() {
+            gelatin::NextUpdate::WaitUntil(
+                Instant::now() + SVG_RENDER_POLL_INTERVAL,
+            )
+        } else {
+            gelatin::NextUpdate::Latest
+        }
+    }
+
+    /// Pauses animations that aren't played forever on their last frame once
+    /// they have been played as many times as they should be
+    fn stop_after_last_loop(&mut self) {
+        let frame_index = self.image_cache.current_frame_index();
+        let prev_frame_index =
+            mem::replace(&mut self.prev_frame_index, frame_index);
+        let (frame_count, loop_count) =
+            match self.image_cache.current_loop_limit() {
+                Some(limit) => limit,
+                None => return,
+            };
+        // Stepping to an earlier frame by hand doesn't count as a loop, only
+        // the playback wrapping around from the last frame to the first
+        let playing =
+            self.image_player.playback_state() == PlaybackState::Forward;
+        let wrapped = frame_index == 0
+            && prev_frame_index > 0
+            && prev_frame_index + 1 == frame_count;
+        if playing && wrapped {
+            self.loops_played += 1;
+        }
+        let last_frame = frame_index + 1 == frame_count;
+        if last_frame && self.loops_played + 1 >= loop_count {
+            self.image_player.pause_playback();
+        }
     }
 }
 
@@ -304,6 +553,7 @@ type FrameLoadResult = image_cache::Result<(AnimationFrameTexture, PathBuf)>;
 struct ImgSequencePlayer<P: Playback> {
     playback_state: PlaybackState,
     present_remaining: Vec<usize>,
+    present_interval: Duration,
 
     last_frame_change_time: Instant,
     frametime_drift_offset: i64, // in nanosecs
@@ -321,6 +571,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
         ImgSequencePlayer {
             playback_state: PlaybackState::Paused,
             present_remaining: Vec::new(),
+            present_interval: DEFAULT_PRESENT_INTERVAL,
             last_frame_change_time: Instant::now(),
             frametime_drift_offset: 0,
             //frame_count_since_playback_start: 0,
@@ -401,7 +652,7 @@ impl<P: Playback> ImgSequencePlayer<P> {
         mem::swap(&mut self.load_request, &mut load_request);
         let frame_delta_time_nanos = match self.playback_state {
             PlaybackState::Present | PlaybackState::RandomPresent => {
-                (NANOS_PER_SEC * 6) as i64
+                self.present_interval.as_nanos() as i64
             }
             _ => P::delay_nanos(self) as i64,
         };
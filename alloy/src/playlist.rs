@@ -0,0 +1,154 @@
+//! Playlists built from the paths given on the command line.
+//!
+//! A playlist is opened instead of a single folder when several paths are
+//! given, when one of the paths is a list file (`.m3u`, `.m3u8` or `.txt`
+//! with one path per line) or when the path is `-`, in which case the paths
+//! are read from the standard input. Paths read from the standard input may be
+//! separated by newlines or NUL characters (as printed by `find -print0`). They
+//! are used exactly as they are read, so they don't need to be valid UTF-8 and
+//! may contain leading or trailing spaces.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// The path that stands for the standard input
+pub const STDIN_PATH: &str = "-";
+
+const LIST_EXTENSIONS: &[&str] = &["m3u", "m3u8", "txt"];
+
+fn is_list_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => {
+            let ext = ext.to_string_lossy().to_lowercase();
+            LIST_EXTENSIONS.contains(&ext.as_str())
+        }
+        None => false,
+    }
+}
+
+/// Returns the files of the playlist described by `args`, or `None` if
+/// `args` is a single file or folder that should be opened as usual.
+///
+/// Folders in `args` add all of their files. Files that can't be found are
+/// reported and skipped.
+pub fn playlist_from_args(args: &[String]) -> Option<Vec<PathBuf>> {
+    let is_playlist = args.len() > 1
+        || args
+            .iter()
+            .any(|arg| arg == STDIN_PATH || is_list_file(Path::new(arg)));
+    if !is_playlist {
+        return None;
+    }
+    let mut playlist = Vec::new();
+    for arg in args {
+        let result = if arg == STDIN_PATH {
+            read_stdin_list(&mut playlist)
+        } else if is_list_file(Path::new(arg)) {
+            read_list_file(Path::new(arg), &mut playlist)
+        } else {
+            add_path(Path::new(arg), &mut playlist)
+        };
+        if let Err(e) = result {
+            eprintln!("Could not add {arg:?} to the playlist: {e}");
+        }
+    }
+    Some(playlist)
+}
+
+fn read_stdin_list(playlist: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut contents = Vec::new();
+    io::stdin().read_to_end(&mut contents)?;
+    let base = std::env::current_dir()?;
+    let separator = if contents.contains(&0) { 0 } else { b'\n' };
+    let entries = contents
+        .split(|&byte| byte == separator)
+        .filter(|entry| !entry.is_empty());
+    for entry in entries {
+        let entry = os_string_from_bytes(entry);
+        if let Err(e) = add_path(&base.join(&entry), playlist) {
+            eprintln!("Could not add {entry:?} to the playlist: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: &[u8]) -> OsString {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::OsStr::from_bytes(bytes).to_os_string()
+}
+
+/// Paths on other platforms are not arbitrary bytes, the best we can do is
+/// to assume that they are UTF-8.
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: &[u8]) -> OsString {
+    String::from_utf8_lossy(bytes).into_owned().into()
+}
+
+fn read_list_file(path: &Path, playlist: &mut Vec<PathBuf>) -> io::Result<()> {
+    let contents = fs::read_to_string(path)?;
+    // Relative paths in the list are relative to the list itself
+    let base = fs::canonicalize(path)?;
+    let base = base.parent().unwrap_or(&base);
+    add_list(&contents, base, playlist);
+    Ok(())
+}
+
+/// Adds the paths in `list` to the playlist. Lines starting with `#` are
+/// comments (or extended M3U directives).
+fn add_list(list: &str, base: &Path, playlist: &mut Vec<PathBuf>) {
+    let entries = list
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+    for entry in entries {
+        if let Err(e) = add_path(&base.join(entry), playlist) {
+            eprintln!("Could not add {entry:?} to the playlist: {e}");
+        }
+    }
+}
+
+/// Adds the file at `path` or all the files in the folder at `path`
+fn add_path(path: &Path, playlist: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        let mut files = fs::read_dir(path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        files.sort_unstable_by(|a, b| {
+            lexical_sort::natural_lexical_cmp(
+                &a.file_name().unwrap().to_string_lossy(),
+                &b.file_name().unwrap().to_string_lossy(),
+            )
+        });
+        for file in files {
+            playlist.push(absolute_path(&file)?);
+        }
+    } else if path.is_file() {
+        playlist.push(absolute_path(path)?);
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no such file or folder",
+        ));
+    }
+    Ok(())
+}
+
+/// Makes `path` absolute the same way the image cache does: the parent folder
+/// is canonicalized but the file name is kept, even if it's a symlink.
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the path has no file name")
+    })?;
+    let parent = match path.parent() {
+        Some(p) if p != Path::new("") => fs::canonicalize(p)?,
+        _ => fs::canonicalize(".")?,
+    };
+    Ok(parent.join(file_name))
+}
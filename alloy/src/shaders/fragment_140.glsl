@@ -2,10 +2,30 @@
 uniform sampler2D tex;
 uniform float bright_shade;
 uniform float lod_level;
+// 0 for images that aren't HDR, otherwise one of the tone mapping operators
+uniform int tone_map;
+uniform float exposure;
 in vec2 v_tex_coords;
 out vec4 f_color;
+
+vec3 tone_mapped(vec3 color) {
+    color *= exposure;
+    if (tone_map == 2) {
+        // Reinhard
+        color = color / (1.0 + color);
+    } else if (tone_map == 3) {
+        // The ACES filmic curve as fitted by Krzysztof Narkowicz
+        color = (color * (2.51 * color + 0.03))
+            / (color * (2.43 * color + 0.59) + 0.14);
+    }
+    return clamp(color, 0.0, 1.0);
+}
+
 void main() {
     vec4 color = textureLod(tex, v_tex_coords, lod_level);
+    if (tone_map != 0) {
+        color = vec4(tone_mapped(max(color.rgb, 0.0)), clamp(color.a, 0.0, 1.0));
+    }
     const float grid_size = 12.0;
     vec4 grid_color;
     if ((mod(gl_FragCoord.x, grid_size * 2.0) < grid_size)
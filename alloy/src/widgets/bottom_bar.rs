@@ -2,13 +2,20 @@ use std::{f32, rc::Rc};
 
 use gelatin::{
     button::Button,
-    line_layout_container::HorizontalLayoutContainer,
+    line_layout_container::{
+        HorizontalLayoutContainer, VerticalLayoutContainer,
+    },
     misc::{Alignment, Length},
     picture::Picture,
     slider::Slider,
+    Widget,
 };
 
-use super::picture_widget::ScalingMode;
+use super::{
+    filmstrip::{Filmstrip, FILMSTRIP_HEIGHT},
+    picture_widget::ScalingMode,
+    slider_preview::SliderPreview,
+};
 use crate::{ConfigWindowSection, Configuration, Theme};
 
 static MOON: &[u8] = include_bytes!("../../resource/moon.png");
@@ -17,8 +24,6 @@ static QUESTION_BUTTON: &[u8] =
     include_bytes!("../../resource/question_button.png");
 static QUESTION_BUTTON_LIGHT: &[u8] =
     include_bytes!("../../resource/question_button_light.png");
-static ONE: &[u8] = include_bytes!("../../resource/1.png");
-static ONE_LIGHT: &[u8] = include_bytes!("../../resource/1-light.png");
 static FIT_STRETCH: &[u8] = include_bytes!("../../resource/fit-stretch.png");
 static FIT_STRETCH_LIGHT: &[u8] =
     include_bytes!("../../resource/fit-stretch-light.png");
@@ -26,19 +31,27 @@ static FIT_BEST: &[u8] = include_bytes!("../../resource/fit-min.png");
 static FIT_BEST_LIGHT: &[u8] =
     include_bytes!("../../resource/fit-min-light.png");
 
+const DARK_TEXT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const LIGHT_TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+
 const NO_BG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
 const ACTIVE_BG_COLOR: [f32; 4] = [0.3, 0.3, 0.3, 0.5];
 
 const SMALL_BUTTON_GAP: f32 = 4.0;
 const BIG_BUTTON_GAP: f32 = 32.0;
 const BUTTON_SIZE: f32 = 24.0;
+const CONTROLS_HEIGHT: f32 = 32.0;
 
 pub struct BottomBar {
-    pub widget: Rc<HorizontalLayoutContainer>,
+    pub widget: Rc<VerticalLayoutContainer>,
+    pub filmstrip: Rc<Filmstrip>,
     pub orig_scale_button: Rc<Button>,
     pub fit_stretch_button: Rc<Button>,
     pub fit_best_button: Rc<Button>,
     pub slider: Rc<Slider>,
+    /// Shown above the slider, this has to be added to the area above the
+    /// bottom bar
+    pub slider_preview: Rc<SliderPreview>,
     pub theme_button: Rc<Button>,
     pub help_button: Rc<Button>,
 
@@ -50,8 +63,6 @@ pub struct BottomBar {
     question_light: Rc<Picture>,
     moon_img: Rc<Picture>,
     light_img: Rc<Picture>,
-    one: Rc<Picture>,
-    one_light: Rc<Picture>,
     fit_stretch: Rc<Picture>,
     fit_stretch_light: Rc<Picture>,
     fit_best: Rc<Picture>,
@@ -65,8 +76,6 @@ impl BottomBar {
             Rc::new(Picture::from_encoded_bytes(QUESTION_BUTTON_LIGHT));
         let moon_img = Rc::new(Picture::from_encoded_bytes(MOON));
         let light_img = Rc::new(Picture::from_encoded_bytes(LIGHT));
-        let one = Rc::new(Picture::from_encoded_bytes(ONE));
-        let one_light = Rc::new(Picture::from_encoded_bytes(ONE_LIGHT));
         let fit_stretch = Rc::new(Picture::from_encoded_bytes(FIT_STRETCH));
         let fit_stretch_light =
             Rc::new(Picture::from_encoded_bytes(FIT_STRETCH_LIGHT));
@@ -74,34 +83,49 @@ impl BottomBar {
         let fit_best_light =
             Rc::new(Picture::from_encoded_bytes(FIT_BEST_LIGHT));
 
-        let widget = Rc::new(HorizontalLayoutContainer::new());
+        let widget = Rc::new(VerticalLayoutContainer::new());
         widget.set_margin_left(0.0);
         widget.set_margin_right(0.0);
-        widget.set_height(Length::Fixed(32.0));
+        widget.set_height(Length::Fixed(CONTROLS_HEIGHT));
         widget.set_width(Length::Stretch {
             min: 0.0,
             max: f32::INFINITY,
         });
 
+        let filmstrip = Rc::new(Filmstrip::new());
+        filmstrip.set_visible(false);
+
+        let controls = Rc::new(HorizontalLayoutContainer::new());
+        controls.set_height(Length::Fixed(CONTROLS_HEIGHT));
+        controls.set_width(Length::Stretch {
+            min: 0.0,
+            max: f32::INFINITY,
+        });
+
         let orig_scale_button = make_icon_button(Alignment::Start);
         let fit_best_button = make_icon_button(Alignment::Start);
         let fit_stretch_button = make_icon_button(Alignment::Start);
         let slider = make_slider();
+        let slider_preview = Rc::new(SliderPreview::new());
         let theme_button = make_icon_button(Alignment::End);
         let help_button = make_icon_button(Alignment::End);
 
         orig_scale_button.set_margin_left(SMALL_BUTTON_GAP);
+        orig_scale_button.set_text("1:1");
         fit_stretch_button.set_margin_right(SMALL_BUTTON_GAP);
         theme_button.set_margin_left(SMALL_BUTTON_GAP);
         help_button.set_margin_left(SMALL_BUTTON_GAP);
         help_button.set_margin_right(SMALL_BUTTON_GAP);
 
-        widget.add_child(orig_scale_button.clone());
-        widget.add_child(fit_best_button.clone());
-        widget.add_child(fit_stretch_button.clone());
-        widget.add_child(slider.clone());
-        widget.add_child(theme_button.clone());
-        widget.add_child(help_button.clone());
+        controls.add_child(orig_scale_button.clone());
+        controls.add_child(fit_best_button.clone());
+        controls.add_child(fit_stretch_button.clone());
+        controls.add_child(slider.clone());
+        controls.add_child(theme_button.clone());
+        controls.add_child(help_button.clone());
+
+        widget.add_child(filmstrip.clone());
+        widget.add_child(controls);
 
         let should_show = if let Some(ConfigWindowSection {
             show_bottom_bar: Some(false),
@@ -114,12 +138,14 @@ impl BottomBar {
             true
         };
 
-        Self {
+        let bottom_bar = Self {
             widget,
+            filmstrip,
             orig_scale_button,
             fit_stretch_button,
             fit_best_button,
             slider,
+            slider_preview,
             theme_button,
             help_button,
             should_show,
@@ -128,19 +154,25 @@ impl BottomBar {
             question_light,
             moon_img,
             light_img,
-            one,
-            one_light,
             fit_stretch,
             fit_stretch_light,
             fit_best,
             fit_best_light,
+        };
+        if let Some(ConfigWindowSection {
+            show_filmstrip: Some(true),
+            ..
+        }) = config.window
+        {
+            bottom_bar.set_filmstrip_visible(true);
         }
+        bottom_bar
     }
 
     pub fn set_theme(&self, theme: Theme) {
         match theme {
             Theme::Light => {
-                self.orig_scale_button.set_icon(Some(self.one.clone()));
+                self.orig_scale_button.set_text_color(DARK_TEXT_COLOR);
                 self.fit_best_button.set_icon(Some(self.fit_best.clone()));
                 self.fit_stretch_button
                     .set_icon(Some(self.fit_stretch.clone()));
@@ -150,8 +182,7 @@ impl BottomBar {
                 self.help_button.set_icon(Some(self.question.clone()));
             }
             Theme::Dark => {
-                self.orig_scale_button
-                    .set_icon(Some(self.one_light.clone()));
+                self.orig_scale_button.set_text_color(LIGHT_TEXT_COLOR);
                 self.fit_best_button
                     .set_icon(Some(self.fit_best_light.clone()));
                 self.fit_stretch_button
@@ -170,6 +201,21 @@ impl BottomBar {
         self.widget.set_visible(visible && self.should_show);
     }
 
+    /// Returns true if the filmstrip is shown whenever the bottom bar is
+    pub fn filmstrip_visible(&self) -> bool {
+        self.filmstrip.visible()
+    }
+
+    pub fn set_filmstrip_visible(&self, visible: bool) {
+        self.filmstrip.set_visible(visible);
+        let height = if visible {
+            CONTROLS_HEIGHT + FILMSTRIP_HEIGHT
+        } else {
+            CONTROLS_HEIGHT
+        };
+        self.widget.set_height(Length::Fixed(height));
+    }
+
     pub fn set_help_visible(&self, visible: bool) {
         self.help_button.set_bg_color(if visible {
             ACTIVE_BG_COLOR
@@ -3,32 +3,27 @@ use std::{
     time::{Duration, Instant},
 };
 
-use gelatin::{label::Label, misc::*, picture::Picture, NextUpdate, Widget};
+use gelatin::{label::Label, misc::*, NextUpdate, Widget};
 
-static COPY_STARTED: &[u8] = include_bytes!("../../resource/copy-started.png");
-static COPY_READY: &[u8] = include_bytes!("../../resource/copy-ready.png");
-static COPY_FAILED: &[u8] = include_bytes!("../../resource/copy-failed.png");
+static COPY_STARTED: &str = "Copying...";
+static COPY_READY: &str = "Ready to Paste";
+static COPY_FAILED: &str = "Copy Failed";
 
 const READY_DISPLAY_TIME: Duration = Duration::from_secs(3);
 
 #[derive(Clone)]
 pub struct CopyNotifications {
     pub widget: Weak<Label>,
-    copy_started_img: Rc<Picture>,
-    copy_ready_img: Rc<Picture>,
-    copy_failed_img: Rc<Picture>,
     finished: bool,
     finished_time: std::time::Instant,
 }
 
 impl CopyNotifications {
     pub fn new(widget: &Rc<Label>) -> CopyNotifications {
-        let copy_started_img =
-            Rc::new(Picture::from_encoded_bytes(COPY_STARTED));
-        let copy_ready_img = Rc::new(Picture::from_encoded_bytes(COPY_READY));
-        let copy_failed_img = Rc::new(Picture::from_encoded_bytes(COPY_FAILED));
-
         widget.set_icon(None);
+        widget.set_bg_color([0.85, 0.85, 0.85, 1.0]);
+        widget.set_text_color([0.1, 0.1, 0.1, 1.0]);
+        widget.set_font_size(15.0);
         widget.set_ignore_layout(true);
         widget.set_width(Length::Fixed(128.0));
         widget.set_height(Length::Fixed(32.0));
@@ -40,9 +35,6 @@ impl CopyNotifications {
 
         CopyNotifications {
             widget: Rc::downgrade(widget),
-            copy_started_img,
-            copy_ready_img,
-            copy_failed_img,
             finished: true,
             finished_time: Instant::now(),
         }
@@ -50,19 +42,14 @@ impl CopyNotifications {
 
     pub fn set_started(&mut self) {
         let widget = self.widget.upgrade().unwrap();
-        widget.set_icon(Some(self.copy_started_img.clone()));
+        widget.set_text(COPY_STARTED);
         widget.set_visible(true);
         self.finished = false;
     }
 
     pub fn set_finished(&mut self, succeeded: bool) {
         let widget = self.widget.upgrade().unwrap();
-        let icon = if succeeded {
-            self.copy_ready_img.clone()
-        } else {
-            self.copy_failed_img.clone()
-        };
-        widget.set_icon(Some(icon));
+        widget.set_text(if succeeded { COPY_READY } else { COPY_FAILED });
         self.finished_time = Instant::now();
         self.finished = true;
     }
@@ -0,0 +1,287 @@
+use std::{cell::RefCell, ops::Range, rc::Rc, time::Instant};
+
+use gelatin::{
+    add_common_widget_functions,
+    glium::{
+        glutin::event::{ElementState, MouseButton},
+        Frame,
+    },
+    misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
+    window::{RenderValidity, Window},
+    DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData, WidgetError,
+};
+
+use super::thumbnail_cell::{draw_thumbnail_cell, same_thumbnails, CellState};
+use crate::image_cache::Thumbnail;
+
+/// The height of the filmstrip, the cells are squares of this size
+pub const FILMSTRIP_HEIGHT: f32 = 72.0;
+/// The space around the thumbnail within its cell
+const CELL_PADDING: f32 = 4.0;
+/// How quickly the filmstrip catches up with the current image
+const SCROLL_SPEED: f32 = 12.0;
+/// Jumps farther than this many cells are not animated
+const MAX_ANIMATED_JUMP: f32 = 30.0;
+
+struct FilmstripData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    image_count: usize,
+    current: usize,
+    /// The index shown at the center of the filmstrip. This follows `current`
+    /// smoothly when the user navigates.
+    center: f32,
+    last_update: Instant,
+    cursor_pos: Option<LogicalVector>,
+    pressed: Option<usize>,
+    thumbnails: Vec<(usize, Thumbnail)>,
+    on_click: Option<Rc<dyn Fn(usize)>>,
+}
+
+impl WidgetData for FilmstripData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+impl FilmstripData {
+    fn cell_size(&self) -> f32 {
+        self.drawn_bounds.size.vec.y
+    }
+
+    fn cell_rect(&self, index: usize) -> LogicalRect {
+        let cell_size = self.cell_size();
+        let center = self.drawn_bounds.center();
+        let offset = (index as f32 - self.center) * cell_size;
+        LogicalRect {
+            pos: LogicalVector::new(
+                center.vec.x + offset - cell_size * 0.5,
+                self.drawn_bounds.pos.vec.y,
+            ),
+            size: LogicalVector::new(cell_size, cell_size),
+        }
+    }
+
+    fn index_at(&self, point: LogicalVector) -> Option<usize> {
+        let cell_size = self.cell_size();
+        if !self.drawn_bounds.contains(point) || cell_size <= 0.0 {
+            return None;
+        }
+        let offset =
+            (point.vec.x - self.drawn_bounds.center().vec.x) / cell_size + 0.5;
+        let index = (self.center + offset).floor();
+        (index >= 0.0 && (index as usize) < self.image_count)
+            .then_some(index as usize)
+    }
+}
+
+/// A row of the thumbnails of the images around the current one. Clicking a
+/// thumbnail calls the function set by `set_on_click` with its index.
+pub struct Filmstrip {
+    data: RefCell<FilmstripData>,
+}
+
+impl Filmstrip {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> Filmstrip {
+        let placement = WidgetPlacement {
+            width: Length::Stretch {
+                min: 0.0,
+                max: f32::INFINITY,
+            },
+            height: Length::Fixed(FILMSTRIP_HEIGHT),
+            ..Default::default()
+        };
+        Filmstrip {
+            data: RefCell::new(FilmstripData {
+                placement,
+                drawn_bounds: Default::default(),
+                visible: true,
+                render_validity: Default::default(),
+                image_count: 0,
+                current: 0,
+                center: 0.0,
+                last_update: Instant::now(),
+                cursor_pos: None,
+                pressed: None,
+                thumbnails: Vec::new(),
+                on_click: None,
+            }),
+        }
+    }
+
+    pub fn set_on_click<F: Fn(usize) + 'static>(&self, callback: F) {
+        self.data.borrow_mut().on_click = Some(Rc::new(callback));
+    }
+
+    /// Sets the index of the image that's shown in the picture widget
+    pub fn set_current(&self, index: usize, image_count: usize) {
+        let mut borrowed = self.data.borrow_mut();
+        if borrowed.current == index && borrowed.image_count == image_count {
+            return;
+        }
+        borrowed.current = index;
+        borrowed.image_count = image_count;
+        if (index as f32 - borrowed.center).abs() > MAX_ANIMATED_JUMP {
+            borrowed.center = index as f32;
+        }
+        borrowed.last_update = Instant::now();
+        borrowed.render_validity.invalidate();
+    }
+
+    /// The indices of the images whose thumbnails should be passed to
+    /// `set_thumbnails`. This includes a cell on both sides so that they are
+    /// ready when scrolling.
+    pub fn visible_range(&self) -> Range<usize> {
+        let borrowed = self.data.borrow();
+        let cell_size = borrowed.cell_size();
+        if !borrowed.visible || cell_size <= 0.0 {
+            return 0..0;
+        }
+        let half_width = borrowed.drawn_bounds.size.vec.x / cell_size * 0.5;
+        let start = (borrowed.center - half_width - 1.0).floor().max(0.0);
+        let end = (borrowed.center + half_width + 2.0).ceil().max(0.0);
+        let end = (end as usize).min(borrowed.image_count);
+        (start as usize).min(end)..end
+    }
+
+    pub fn set_thumbnails(&self, thumbnails: Vec<(usize, Thumbnail)>) {
+        let mut borrowed = self.data.borrow_mut();
+        let changed = !same_thumbnails(&borrowed.thumbnails, &thumbnails);
+        borrowed.thumbnails = thumbnails;
+        if changed {
+            borrowed.render_validity.invalidate();
+        }
+    }
+}
+
+impl Widget for Filmstrip {
+    fn before_draw(&self, _window: &Window) -> NextUpdate {
+        let mut borrowed = self.data.borrow_mut();
+        let now = Instant::now();
+        let elapsed = now.duration_since(borrowed.last_update).as_secs_f32();
+        borrowed.last_update = now;
+        let target = borrowed.current as f32;
+        if !borrowed.visible || borrowed.center == target {
+            return NextUpdate::Latest;
+        }
+        let distance = target - borrowed.center;
+        let step = distance * (1.0 - (-SCROLL_SPEED * elapsed).exp());
+        if (distance - step).abs() < 0.01 {
+            borrowed.center = target;
+        } else {
+            borrowed.center += step;
+        }
+        borrowed.render_validity.invalidate();
+        NextUpdate::Soonest
+    }
+
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let borrowed = self.data.borrow();
+        if !borrowed.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        // The cells move under the cursor while scrolling, so the hovered
+        // one is found here
+        let hovered =
+            borrowed.cursor_pos.and_then(|pos| borrowed.index_at(pos));
+        for (index, thumbnail) in borrowed.thumbnails.iter() {
+            let state = CellState {
+                selected: *index == borrowed.current,
+                hovered: hovered == Some(*index),
+            };
+            draw_thumbnail_cell(
+                target,
+                context,
+                thumbnail,
+                borrowed.cell_rect(*index),
+                CELL_PADDING,
+                borrowed.drawn_bounds,
+                state,
+            );
+        }
+        Ok(NextUpdate::Latest)
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        self.data.borrow_mut().default_layout(available_space);
+    }
+
+    fn handle_event(&self, event: &Event) {
+        if !self.data.borrow().visible {
+            return;
+        }
+        match event.kind {
+            EventKind::MouseMove => {
+                let mut borrowed = self.data.borrow_mut();
+                let prev_hovered =
+                    borrowed.cursor_pos.and_then(|pos| borrowed.index_at(pos));
+                let inside = borrowed.drawn_bounds.contains(event.cursor_pos);
+                borrowed.cursor_pos = inside.then_some(event.cursor_pos);
+                if borrowed.index_at(event.cursor_pos) != prev_hovered {
+                    borrowed.render_validity.invalidate();
+                }
+            }
+            EventKind::MouseButton {
+                state,
+                button: MouseButton::Left,
+            } => {
+                let on_click = {
+                    let mut borrowed = self.data.borrow_mut();
+                    let index = borrowed.index_at(event.cursor_pos);
+                    match state {
+                        ElementState::Pressed => {
+                            borrowed.pressed = index;
+                            None
+                        }
+                        ElementState::Released => {
+                            let pressed = borrowed.pressed.take();
+                            match (pressed, &borrowed.on_click) {
+                                (Some(pressed), Some(on_click))
+                                    if index == Some(pressed) =>
+                                {
+                                    Some((on_click.clone(), pressed))
+                                }
+                                _ => None,
+                            }
+                        }
+                    }
+                };
+                if let Some((on_click, index)) = on_click {
+                    on_click(index);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
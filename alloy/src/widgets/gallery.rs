@@ -0,0 +1,411 @@
+use std::{
+    cell::RefCell,
+    ops::Range,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use gelatin::{
+    add_common_widget_functions,
+    glium::{
+        glutin::event::{ElementState, ModifiersState, MouseButton},
+        Frame,
+    },
+    misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
+    window::RenderValidity,
+    DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData, WidgetError,
+};
+
+use crate::{
+    configuration::Configuration,
+    image_cache::Thumbnail,
+    input_handling::*,
+    utils::{virtual_keycode_is_char, virtual_keycode_to_string},
+};
+
+use super::thumbnail_cell::{draw_thumbnail_cell, same_thumbnails, CellState};
+
+/// The smallest width and height of a cell. The cells are stretched to fill
+/// the width of the widget.
+const MIN_CELL_SIZE: f32 = 170.0;
+/// The space around the thumbnail within its cell
+const CELL_PADDING: f32 = 8.0;
+
+/// Tells the owner of the gallery what to do after the user's input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GalleryRequest {
+    /// Show the image at this index in the normal view
+    Open(usize),
+    Close,
+}
+
+struct GalleryData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    configuration: Rc<RefCell<Configuration>>,
+    image_count: usize,
+    selected: usize,
+    hovered: Option<usize>,
+    /// The distance between the top of the first row and the top of the widget
+    scroll: f32,
+    /// Set when the gallery is opened, because the size of the cells is only
+    /// known after the layout
+    scroll_to_selected: bool,
+    thumbnails: Vec<(usize, Thumbnail)>,
+    request: Option<GalleryRequest>,
+    last_click_time: Instant,
+    last_click_index: Option<usize>,
+}
+
+impl WidgetData for GalleryData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+impl GalleryData {
+    fn columns(&self) -> usize {
+        ((self.drawn_bounds.size.vec.x / MIN_CELL_SIZE) as usize).max(1)
+    }
+
+    fn cell_size(&self) -> f32 {
+        self.drawn_bounds.size.vec.x / self.columns() as f32
+    }
+
+    /// The number of rows that fit into the widget entirely
+    fn full_rows(&self) -> usize {
+        ((self.drawn_bounds.size.vec.y / self.cell_size()) as usize).max(1)
+    }
+
+    fn max_scroll(&self) -> f32 {
+        let rows = self.image_count.div_ceil(self.columns());
+        let content_height = rows as f32 * self.cell_size();
+        (content_height - self.drawn_bounds.size.vec.y).max(0.0)
+    }
+
+    fn set_scroll(&mut self, scroll: f32) {
+        self.scroll = scroll.min(self.max_scroll()).max(0.0);
+        self.render_validity.invalidate();
+    }
+
+    fn cell_rect(&self, index: usize) -> LogicalRect {
+        let cell_size = self.cell_size();
+        let col = index % self.columns();
+        let row = index / self.columns();
+        let offset = LogicalVector::new(
+            col as f32 * cell_size,
+            row as f32 * cell_size - self.scroll,
+        );
+        LogicalRect {
+            pos: self.drawn_bounds.pos + offset,
+            size: LogicalVector::new(cell_size, cell_size),
+        }
+    }
+
+    fn index_at(&self, point: LogicalVector) -> Option<usize> {
+        if !self.drawn_bounds.contains(point) {
+            return None;
+        }
+        let cell_size = self.cell_size();
+        let pos = point - self.drawn_bounds.pos;
+        let col = (pos.vec.x / cell_size) as usize;
+        let row = ((pos.vec.y + self.scroll) / cell_size) as usize;
+        let index = row * self.columns() + col.min(self.columns() - 1);
+        (index < self.image_count).then_some(index)
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.image_count.saturating_sub(1));
+        self.make_selected_visible();
+        self.render_validity.invalidate();
+    }
+
+    fn make_selected_visible(&mut self) {
+        let cell_size = self.cell_size();
+        let row_top = (self.selected / self.columns()) as f32 * cell_size;
+        let height = self.drawn_bounds.size.vec.y;
+        if row_top < self.scroll {
+            self.set_scroll(row_top);
+        } else if row_top + cell_size > self.scroll + height {
+            self.set_scroll(row_top + cell_size - height);
+        }
+    }
+}
+
+/// A scrollable grid of the thumbnails of the images in the folder. It's shown
+/// on top of the `PictureWidget`, which feeds it with the thumbnails and acts
+/// on the requests made by the user through `take_request`.
+pub struct GalleryWidget {
+    data: RefCell<GalleryData>,
+}
+
+impl GalleryWidget {
+    add_common_widget_functions!(data);
+
+    pub fn new(configuration: Rc<RefCell<Configuration>>) -> GalleryWidget {
+        let placement = WidgetPlacement {
+            width: Length::Stretch {
+                min: 0.0,
+                max: f32::INFINITY,
+            },
+            height: Length::Stretch {
+                min: 0.0,
+                max: f32::INFINITY,
+            },
+            ignore_layout: true,
+            ..Default::default()
+        };
+        GalleryWidget {
+            data: RefCell::new(GalleryData {
+                placement,
+                drawn_bounds: Default::default(),
+                visible: false,
+                render_validity: Default::default(),
+                configuration,
+                image_count: 0,
+                selected: 0,
+                hovered: None,
+                scroll: 0.0,
+                scroll_to_selected: false,
+                thumbnails: Vec::new(),
+                request: None,
+                last_click_time: Instant::now() - Duration::from_secs(10),
+                last_click_index: None,
+            }),
+        }
+    }
+
+    /// Shows the gallery with the image at `index` selected
+    pub fn open(&self, index: usize) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = true;
+        borrowed.selected = index;
+        borrowed.scroll_to_selected = true;
+        borrowed.request = None;
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn close(&self) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.visible = false;
+        borrowed.hovered = None;
+        borrowed.thumbnails.clear();
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    /// The index of the selected image
+    pub fn selected(&self) -> usize {
+        self.data.borrow().selected
+    }
+
+    pub fn set_image_count(&self, count: usize) {
+        let mut borrowed = self.data.borrow_mut();
+        if borrowed.image_count != count {
+            borrowed.image_count = count;
+            let selected = borrowed.selected;
+            borrowed.select(selected);
+        }
+    }
+
+    /// The indices of the images whose thumbnails should be passed to
+    /// `set_thumbnails`. This includes a row above and below the visible
+    /// ones so that they are ready when scrolling.
+    pub fn visible_range(&self) -> Range<usize> {
+        let borrowed = self.data.borrow();
+        let cell_size = borrowed.cell_size();
+        if !borrowed.visible || cell_size <= 0.0 {
+            return 0..0;
+        }
+        let columns = borrowed.columns();
+        let first_row = (borrowed.scroll / cell_size) as usize;
+        let last_row = ((borrowed.scroll + borrowed.drawn_bounds.size.vec.y)
+            / cell_size) as usize;
+        let start = first_row.saturating_sub(1) * columns;
+        let end = ((last_row + 2) * columns).min(borrowed.image_count);
+        start..end.max(start)
+    }
+
+    pub fn set_thumbnails(&self, thumbnails: Vec<(usize, Thumbnail)>) {
+        let mut borrowed = self.data.borrow_mut();
+        let changed = !same_thumbnails(&borrowed.thumbnails, &thumbnails);
+        borrowed.thumbnails = thumbnails;
+        if changed {
+            borrowed.render_validity.invalidate();
+        }
+    }
+
+    /// Returns what the user asked for since the last call
+    pub fn take_request(&self) -> Option<GalleryRequest> {
+        self.data.borrow_mut().request.take()
+    }
+
+    fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
+        let mut borrowed = self.data.borrow_mut();
+        let config = borrowed.configuration.clone();
+        let triggered = |action_name| {
+            action_triggered(&config, action_name, input_key, modifiers)
+        };
+        let columns = borrowed.columns() as isize;
+        let page = columns * borrowed.full_rows() as isize;
+        let selected = borrowed.selected as isize;
+        let target = match input_key {
+            "return" | "numpadenter" => {
+                borrowed.request =
+                    Some(GalleryRequest::Open(borrowed.selected));
+                return;
+            }
+            "left" => selected - 1,
+            "right" => selected + 1,
+            "up" => selected - columns,
+            "down" => selected + columns,
+            "pageup" => selected - page,
+            "pagedown" => selected + page,
+            "home" => 0,
+            "end" => borrowed.image_count as isize - 1,
+            _ if triggered(TOGGLE_GALLERY_NAME) || triggered(ESCAPE_NAME) => {
+                borrowed.request = Some(GalleryRequest::Close);
+                return;
+            }
+            _ if triggered(IMG_NEXT_NAME) => selected + 1,
+            _ if triggered(IMG_PREV_NAME) => selected - 1,
+            _ => return,
+        };
+        borrowed.select(target.max(0) as usize);
+    }
+}
+
+impl Widget for GalleryWidget {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let borrowed = self.data.borrow();
+        if !borrowed.visible {
+            return Ok(NextUpdate::Latest);
+        }
+        for (index, thumbnail) in borrowed.thumbnails.iter() {
+            let state = CellState {
+                selected: *index == borrowed.selected,
+                hovered: borrowed.hovered == Some(*index),
+            };
+            // Cuts the rows that are only partially visible
+            draw_thumbnail_cell(
+                target,
+                context,
+                thumbnail,
+                borrowed.cell_rect(*index),
+                CELL_PADDING,
+                borrowed.drawn_bounds,
+                state,
+            );
+        }
+        Ok(NextUpdate::Latest)
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.default_layout(available_space);
+        if !borrowed.visible {
+            return;
+        }
+        if borrowed.scroll_to_selected {
+            borrowed.scroll_to_selected = false;
+            borrowed.make_selected_visible();
+        }
+        // The number of columns may have changed
+        let scroll = borrowed.scroll;
+        borrowed.set_scroll(scroll);
+    }
+
+    fn handle_event(&self, event: &Event) {
+        if !self.data.borrow().visible {
+            return;
+        }
+        match event.kind {
+            EventKind::MouseMove => {
+                let mut borrowed = self.data.borrow_mut();
+                let hovered = borrowed.index_at(event.cursor_pos);
+                if borrowed.hovered != hovered {
+                    borrowed.hovered = hovered;
+                    borrowed.render_validity.invalidate();
+                }
+            }
+            EventKind::MouseButton {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+            } => {
+                let mut borrowed = self.data.borrow_mut();
+                if let Some(index) = borrowed.index_at(event.cursor_pos) {
+                    let now = Instant::now();
+                    let double_click = borrowed.last_click_index == Some(index)
+                        && now.duration_since(borrowed.last_click_time)
+                            < Duration::from_millis(250);
+                    borrowed.last_click_time = now;
+                    borrowed.last_click_index = Some(index);
+                    borrowed.select(index);
+                    if double_click {
+                        borrowed.request = Some(GalleryRequest::Open(index));
+                    }
+                }
+            }
+            EventKind::MouseScroll { delta } => {
+                let mut borrowed = self.data.borrow_mut();
+                let scroll =
+                    borrowed.scroll - delta.vec.y * borrowed.cell_size() * 0.5;
+                borrowed.set_scroll(scroll);
+                borrowed.hovered = borrowed.index_at(event.cursor_pos);
+            }
+            // With the control key held down, the character is an ASCII
+            // control character
+            EventKind::ReceivedCharacter(ch) if !event.modifiers.ctrl() => {
+                let input_key = char_to_input_key(ch);
+                self.handle_key_input(input_key.as_str(), event.modifiers);
+            }
+            EventKind::KeyInput { input } => {
+                if let Some(key) = input.virtual_keycode {
+                    let printable =
+                        !event.modifiers.ctrl() && virtual_keycode_is_char(key);
+                    if !printable && input.state == ElementState::Pressed {
+                        let input_key =
+                            virtual_keycode_to_string(key).to_lowercase();
+                        self.handle_key_input(
+                            input_key.as_str(),
+                            event.modifiers,
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
@@ -2,14 +2,29 @@ use std::{cell::RefCell, rc::Rc};
 
 use gelatin::{
     add_common_widget_functions,
-    cgmath::{Matrix4, Vector3},
+    cgmath::Matrix4,
     glium::{uniform, Frame, Surface},
     misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
-    picture::Picture,
+    text::{draw_text, Font, TextStyle},
     window::RenderValidity,
     DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
 };
 
+/// The font size of the help text in logical pixels
+const FONT_SIZE: f32 = 14.0;
+/// The space between the edge of the panel and the text
+const PADDING: f32 = 8.0;
+/// The horizontal space between the column of actions and the column of keys
+const COLUMN_GAP: f32 = 32.0;
+/// The vertical space taken by the line separating the text and the shortcuts
+const SEPARATOR_SPACE: f32 = 24.0;
+
+/// The color drawn over the picture behind the panel
+const DIM_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const PANEL_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const SEPARATOR_COLOR: [f32; 4] = [0.75, 0.75, 0.75, 1.0];
+const TEXT_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
 struct HelpScreenData {
     placement: WidgetPlacement,
     drawn_bounds: LogicalRect,
@@ -17,7 +32,13 @@ struct HelpScreenData {
     render_validity: RenderValidity,
     initiaizlied: bool,
     parent_space: LogicalRect,
-    usage_image: Picture,
+    intro: String,
+    /// The names of the actions separated by newlines
+    actions: String,
+    /// The keys of the actions separated by newlines
+    keys: String,
+    intro_size: LogicalVector,
+    actions_size: LogicalVector,
 }
 
 impl WidgetData for HelpScreenData {
@@ -41,7 +62,10 @@ pub struct HelpScreen {
 impl HelpScreen {
     add_common_widget_functions!(data);
 
-    pub fn new(usage_img: Picture) -> HelpScreen {
+    /// Creates a panel showing `intro` above a table of shortcuts. Each
+    /// shortcut is the name of an action and the keys that trigger it. An
+    /// empty shortcut leaves an empty row in the table.
+    pub fn new(intro: &str, shortcuts: &[(&str, &str)]) -> HelpScreen {
         let placement = WidgetPlacement {
             width: Length::Fixed(0.0),
             height: Length::Fixed(0.0),
@@ -58,7 +82,11 @@ impl HelpScreen {
                 render_validity: Default::default(),
                 initiaizlied: false,
                 parent_space: LogicalRect::default(),
-                usage_image: usage_img,
+                intro: intro.to_owned(),
+                actions: column_text(shortcuts.iter().map(|s| s.0)),
+                keys: column_text(shortcuts.iter().map(|s| s.1)),
+                intro_size: Default::default(),
+                actions_size: Default::default(),
             }),
         }
     }
@@ -70,99 +98,45 @@ impl Widget for HelpScreen {
         target: &mut Frame,
         context: &DrawContext,
     ) -> Result<NextUpdate, WidgetError> {
-        use gelatin::glium::{Blend, BlendingFunction, LinearBlendingFactor};
         {
             let borrowed = self.data.borrow();
             if !borrowed.visible {
                 return Ok(NextUpdate::Latest);
             }
 
-            let w = borrowed.parent_space.size.vec.x;
-            let h = borrowed.parent_space.size.vec.y;
-            let pos = borrowed.parent_space.pos.vec;
-            // Model tranform
-            let transform = Matrix4::from_nonuniform_scale(w, h, 1.0);
-            let transform =
-                Matrix4::from_translation(pos.extend(0.0)) * transform;
-            // Projection
-            let transform = context.projection_transform * transform;
-            let image_draw_params = gelatin::glium::DrawParameters {
-                viewport: Some(*context.viewport),
-                blend: Blend {
-                    color: BlendingFunction::Addition {
-                        source: LinearBlendingFactor::SourceAlpha,
-                        destination: LinearBlendingFactor::OneMinusSourceAlpha,
-                    },
-                    ..Default::default()
-                },
+            draw_rect(target, context, borrowed.parent_space, DIM_COLOR);
+            let panel = borrowed
+                .drawn_bounds
+                .align_to_pixels(context.dpi_scale_factor);
+            draw_rect(target, context, panel, PANEL_COLOR);
+
+            let text_style = TextStyle {
+                font_size: FONT_SIZE,
+                color: TEXT_COLOR,
+                horizontal_align: Alignment::Start,
+                vertical_align: Alignment::Start,
                 ..Default::default()
             };
-            let uniforms = uniform! {
-                matrix: Into::<[[f32; 4]; 4]>::into(transform),
-                color: [0.0f32, 0.0, 0.0, 0.5],
+            let mut area = LogicalRect {
+                pos: panel.pos + LogicalVector::new(PADDING, PADDING),
+                size: panel.size - LogicalVector::new(PADDING, PADDING) * 2.0,
             };
+            draw_text(target, context, &borrowed.intro, &text_style, area)?;
+            if borrowed.actions.is_empty() {
+                return Ok(NextUpdate::Latest);
+            }
 
-            target
-                .draw(
-                    context.unit_quad_vertices,
-                    context.unit_quad_indices,
-                    context.colored_program,
-                    &uniforms,
-                    &image_draw_params,
-                )
-                .unwrap();
-
-            ///////////////////////////////////////////////////////////////////////////
-            // Draw Help Image
-            //////////////////////////////////////////////////////////////////////////
-            let aligned_bounds = borrowed
-                .drawn_bounds
-                .align_to_pixels(context.dpi_scale_factor);
-            let img_w = aligned_bounds.size.vec.x;
-            let img_h = aligned_bounds.size.vec.y;
-            let pos = aligned_bounds.pos.vec;
-            // Model tranform
-            let transform = Matrix4::from_nonuniform_scale(img_w, img_h, 1.0);
-            let transform =
-                Matrix4::from_translation(pos.extend(0.0)) * transform;
-            // Projection
-            let transform = context.projection_transform * transform;
-
-            let texture_size = [img_w, img_h];
-            let texture = borrowed.usage_image.texture(context.display)?;
-            let sampler = texture
-                .sampled()
-                .wrap_function(
-                    gelatin::glium::uniforms::SamplerWrapFunction::Clamp,
-                )
-                .minify_filter(
-                    gelatin::glium::uniforms::MinifySamplerFilter::Linear,
-                )
-                .magnify_filter(
-                    gelatin::glium::uniforms::MagnifySamplerFilter::Linear,
-                );
-            let uniforms = uniform! {
-                matrix: Into::<[[f32; 4]; 4]>::into(transform),
-                tex: sampler,
-                color: [1.0f32, 0.1, 0.5, 0.5],
-                texture_size: texture_size,
-                //brighten: if self.hover { 0.15f32 } else { 0.0f32 },
-                brighten: 0.0f32,
-                shadow_color: Into::<[f32; 3]>::into(Vector3::<f32>::new(0.0, 0.0, 0.0)),
-                shadow_offset: 1.0f32
+            area.pos.vec.y += borrowed.intro_size.vec.y;
+            let separator = LogicalRect {
+                pos: area.pos + LogicalVector::new(0.0, SEPARATOR_SPACE * 0.5),
+                size: LogicalVector::new(area.size.vec.x, 1.0),
             };
-            target
-                .draw(
-                    context.unit_quad_vertices,
-                    context.unit_quad_indices,
-                    context.textured_program,
-                    &uniforms,
-                    &image_draw_params,
-                )
-                .unwrap();
-            //////////////////////////////////////////////////////////////////////////
-
-            //let uniforms
+            draw_rect(target, context, separator, SEPARATOR_COLOR);
+
+            area.pos.vec.y += SEPARATOR_SPACE;
+            draw_text(target, context, &borrowed.actions, &text_style, area)?;
+            area.pos.vec.x += borrowed.actions_size.vec.x + COLUMN_GAP;
+            draw_text(target, context, &borrowed.keys, &text_style, area)?;
         }
         Ok(NextUpdate::Latest)
     }
@@ -173,11 +147,25 @@ impl Widget for HelpScreen {
             return;
         }
         if !borrowed.initiaizlied {
+            // The default font is needed for measuring the text
+            let font = match Font::default_font() {
+                Some(font) => font,
+                None => return,
+            };
             borrowed.initiaizlied = true;
-            let img_data = borrowed.usage_image.get_metadata().unwrap();
+            borrowed.intro_size = font.measure(&borrowed.intro, FONT_SIZE);
+            let mut size = borrowed.intro_size;
+            if !borrowed.actions.is_empty() {
+                let actions = font.measure(&borrowed.actions, FONT_SIZE);
+                let keys = font.measure(&borrowed.keys, FONT_SIZE);
+                size.vec.x =
+                    size.vec.x.max(actions.vec.x + COLUMN_GAP + keys.vec.x);
+                size.vec.y += SEPARATOR_SPACE + actions.vec.y.max(keys.vec.y);
+                borrowed.actions_size = actions;
+            }
             borrowed.placement = WidgetPlacement {
-                width: Length::Fixed(img_data.width as f32 * 0.5),
-                height: Length::Fixed(img_data.height as f32 * 0.5),
+                width: Length::Fixed(size.vec.x + 2.0 * PADDING),
+                height: Length::Fixed(size.vec.y + 2.0 * PADDING),
                 horizontal_align: Alignment::Center,
                 vertical_align: Alignment::Center,
                 ignore_layout: true,
@@ -205,3 +193,46 @@ impl Widget for HelpScreen {
         self.data.borrow_mut().render_validity = render_validity;
     }
 }
+
+fn column_text<'a>(rows: impl Iterator<Item = &'a str>) -> String {
+    rows.collect::<Vec<_>>().join("\n")
+}
+
+fn draw_rect(
+    target: &mut Frame,
+    context: &DrawContext,
+    rect: LogicalRect,
+    color: [f32; 4],
+) {
+    use gelatin::glium::{Blend, BlendingFunction, LinearBlendingFactor};
+
+    let size = rect.size.vec;
+    let transform = Matrix4::from_nonuniform_scale(size.x, size.y, 1.0);
+    let transform =
+        Matrix4::from_translation(rect.pos.vec.extend(0.0)) * transform;
+    let transform = context.projection_transform * transform;
+    let uniforms = uniform! {
+        matrix: Into::<[[f32; 4]; 4]>::into(transform),
+        color: color,
+    };
+    let draw_params = gelatin::glium::DrawParameters {
+        viewport: Some(*context.viewport),
+        blend: Blend {
+            color: BlendingFunction::Addition {
+                source: LinearBlendingFactor::SourceAlpha,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    target
+        .draw(
+            context.unit_quad_vertices,
+            context.unit_quad_indices,
+            context.colored_program,
+            &uniforms,
+            &draw_params,
+        )
+        .unwrap();
+}
@@ -1,4 +1,8 @@
 pub mod bottom_bar;
 pub mod copy_notification;
+pub mod filmstrip;
+pub mod gallery;
 pub mod help_screen;
 pub mod picture_widget;
+pub mod slider_preview;
+mod thumbnail_cell;
@@ -1,5 +1,7 @@
 use std::{
     cell::{Ref, RefCell},
+    collections::HashMap,
+    ffi::OsStr,
     path::PathBuf,
     rc::{Rc, Weak},
     sync::{Arc, Mutex},
@@ -9,11 +11,16 @@ use std::{
 use gelatin::{
     add_common_widget_functions,
     application::request_exit,
-    cgmath::{Matrix4, Vector2, Vector3},
+    cgmath::{Matrix4, Vector2, Vector3, Vector4},
     glium::{
-        glutin::event::{ElementState, ModifiersState, MouseButton},
+        glutin::{
+            event::{ElementState, ModifiersState, MouseButton},
+            event_loop::EventLoopProxy,
+        },
         program, uniform,
-        uniforms::MagnifySamplerFilter,
+        uniforms::{
+            MagnifySamplerFilter, MinifySamplerFilter, SamplerWrapFunction,
+        },
         Display, Frame, Program, Surface,
     },
     misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
@@ -22,13 +29,19 @@ use gelatin::{
 };
 
 use super::{
-    bottom_bar::BottomBar, copy_notification::CopyNotifications,
+    bottom_bar::BottomBar,
+    copy_notification::CopyNotifications,
+    gallery::{GalleryRequest, GalleryWidget},
     help_screen::HelpScreen,
 };
 use crate::{
     clipboard_handler::ClipboardHandler,
-    configuration::{Antialias, Cache, Configuration},
-    image_cache::{image_loader::Orientation, AnimationFrameTexture},
+    command_runner::CommandRunner,
+    configuration::{Antialias, Cache, Configuration, ToneMap},
+    image_cache::{
+        image_loader::Orientation, is_svg, sort::SortOrder,
+        AnimationFrameTexture, CellTexture, FrameRect, Thumbnail,
+    },
     input_handling::*,
     playback_manager::*,
     shaders,
@@ -38,6 +51,11 @@ use crate::{
 const MIN_ZOOM_FACTOR: f32 = 0.0001;
 const MAX_ZOOM_FACTOR: f32 = 10000.0;
 const AA_TEXEL_SIZE_THRESHOLD: f32 = 4f32;
+/// The exposure of HDR images is changed in steps of this many stops
+const EXPOSURE_STEP: f32 = 0.5;
+const MAX_EXPOSURE: f32 = 16.0;
+/// An SVG is rendered again once the view has been still for this long
+const SVG_SETTLE_TIME: Duration = Duration::from_millis(150);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ScalingMode {
@@ -65,6 +83,15 @@ enum HoverState {
     ItemHovered { prev_path: PathBuf },
 }
 
+/// The part of an SVG that's shown, see `update_svg_render`
+struct SvgView {
+    region: FrameRect,
+    texel_size: f32,
+    changed_at: Instant,
+    /// True once the view has been sent to be rendered
+    requested: bool,
+}
+
 fn orientation_to_matrix(orientation: Orientation) -> Matrix4<f32> {
     #[rustfmt::skip]
 	let result = match orientation {
@@ -119,6 +146,8 @@ struct PictureWidgetData {
     placement: WidgetPlacement,
     drawn_bounds: LogicalRect,
     prev_draw_size: LogicalVector,
+    /// The dpi scale factor of the last draw
+    prev_dpi_scale: f32,
     visible: bool,
     render_validity: RenderValidity,
 
@@ -131,6 +160,7 @@ struct PictureWidgetData {
     // It's an option to allow manual destruction.
     clipboard_handler: Option<ClipboardHandler>,
     clipboard_request_was_pending: bool,
+    command_runner: CommandRunner,
 
     program: Program,
     bright_shade: f32,
@@ -139,6 +169,12 @@ struct PictureWidgetData {
     scaling: ScalingMode,
     img_pos: LogicalVector,
     antialiasing: Antialias,
+    tone_map: ToneMap,
+    /// The exposure of HDR images in stops, 0 leaves them unchanged
+    exposure: f32,
+    svg_view: Option<SvgView>,
+    /// True if some of the tiles in view weren't uploaded in the last draw
+    tiles_pending: bool,
 
     hor_pan_input: MovementDir,
     ver_pan_input: MovementDir,
@@ -160,6 +196,7 @@ struct PictureWidgetData {
     next_update: NextUpdate,
     bottom_bar: Rc<BottomBar>,
     left_to_pan_hint: Rc<HelpScreen>,
+    gallery: Rc<GalleryWidget>,
     copy_notifications: CopyNotifications,
     window: Weak<Window>,
 }
@@ -250,6 +287,7 @@ impl PictureWidgetData {
             }
         }
         self.prev_draw_size = self.drawn_bounds.size;
+        self.prev_dpi_scale = dpi_scale;
     }
 
     fn apply_camera_movement(&mut self, dpi_scale: f32) {
@@ -366,14 +404,174 @@ impl PictureWidgetData {
             None => "".into()
         };
 
+        let root = self.playback_manager.recursive_root();
         let name = match file_path {
-            Some(file_path) => title_config.format_file_path(file_path),
+            Some(file_path) => title_config.format_file_path(file_path, root),
             None => "[ none ]".into(),
         };
-        let title = format!("{name} {playback} {size_info}");
+        let sort = match self.playback_manager.sort_order() {
+            Some(order) => format!(" : Sorted by {order}"),
+            None => "".into(),
+        };
+        let follow = if self.playback_manager.follow() {
+            " : Following"
+        } else {
+            ""
+        };
+        let page = match self.playback_manager.current_page() {
+            Some((page, page_count)) => {
+                format!(" : Page {}/{page_count}", page + 1)
+            }
+            None => "".into(),
+        };
+        let hdr = match texture {
+            Some(texture) if texture.hdr => {
+                format!(" : {} {:+} EV", self.tone_map.name(), self.exposure)
+            }
+            _ => "".into(),
+        };
+        let colors = if self.playback_manager.manage_colors() {
+            ""
+        } else {
+            " : Unmanaged colors"
+        };
+        let title = format!(
+            "{name} {playback}{sort}{follow}{page}{hdr}{colors} {size_info}"
+        );
         window.set_title(title);
     }
 
+    /// Passes the thumbnails to the gallery and acts on the user's requests.
+    /// Returns when the gallery has to be updated again.
+    fn update_gallery(&mut self, window: &Window, now: Instant) -> NextUpdate {
+        if let Some(request) = self.gallery.take_request() {
+            if let GalleryRequest::Open(index) = request {
+                self.playback_manager
+                    .request_load(LoadRequest::LoadAtIndex(index));
+            }
+            self.gallery.close();
+            self.render_validity.invalidate();
+            return NextUpdate::Soonest;
+        }
+        let image_count = self.playback_manager.current_dir_len().unwrap_or(0);
+        self.gallery.set_image_count(image_count);
+        let range = self.gallery.visible_range();
+        let thumbnails = self
+            .playback_manager
+            .thumbnails(&window.display_mut(), range);
+        let loading = thumbnails
+            .iter()
+            .any(|(_, thumbnail)| matches!(thumbnail, Thumbnail::Loading));
+        self.gallery.set_thumbnails(thumbnails);
+
+        let selected = self.gallery.selected();
+        let name = match self.playback_manager.image_path_at_index(selected) {
+            Some(path) => {
+                let config = self.configuration.borrow();
+                let title_config = config.title.clone().unwrap_or_default();
+                let root = self.playback_manager.recursive_root();
+                title_config.format_file_path(&path, root).into_owned()
+            }
+            None => "[ none ]".into(),
+        };
+        window.set_title(format!(
+            "{name} : Gallery [{}/{image_count}]",
+            selected + 1
+        ));
+        if loading {
+            NextUpdate::WaitUntil(now + Duration::from_millis(50))
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    /// Feeds the filmstrip of the bottom bar with the thumbnails around the
+    /// current image
+    fn update_filmstrip(
+        &mut self,
+        window: &Window,
+        now: Instant,
+    ) -> NextUpdate {
+        let filmstrip = &self.bottom_bar.filmstrip;
+        let index = self.playback_manager.current_file_index();
+        let image_count = self.playback_manager.current_dir_len();
+        if let (Some(index), Some(image_count)) = (index, image_count) {
+            filmstrip.set_current(index, image_count);
+        }
+        let range = filmstrip.visible_range();
+        let thumbnails = self
+            .playback_manager
+            .thumbnails(&window.display_mut(), range);
+        let loading = thumbnails
+            .iter()
+            .any(|(_, thumbnail)| matches!(thumbnail, Thumbnail::Loading));
+        filmstrip.set_thumbnails(thumbnails);
+        if loading {
+            NextUpdate::WaitUntil(now + Duration::from_millis(50))
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    /// Shows the thumbnail and the name of the image under the cursor above
+    /// the slider of the bottom bar
+    fn update_slider_preview(
+        &mut self,
+        window: &Window,
+        now: Instant,
+    ) -> NextUpdate {
+        let preview = &self.bottom_bar.slider_preview;
+        let slider = &self.bottom_bar.slider;
+        let image_count = self.playback_manager.current_dir_len().unwrap_or(0);
+        let index = match slider.hovered_value() {
+            Some(value) if (value as usize) < image_count => value as usize,
+            _ => {
+                preview.hide();
+                return NextUpdate::Latest;
+            }
+        };
+        let path = match self.playback_manager.image_path_at_index(index) {
+            Some(path) => path,
+            None => {
+                preview.hide();
+                return NextUpdate::Latest;
+            }
+        };
+        let name = {
+            let config = self.configuration.borrow();
+            let title_config = config.title.clone().unwrap_or_default();
+            let root = self.playback_manager.recursive_root();
+            title_config.format_file_path(&path, root).into_owned()
+        };
+        // The thumbnails are kept apart from the textures of the full images,
+        // so this doesn't evict any of those
+        let thumbnail = self
+            .playback_manager
+            .thumbnails(&window.display_mut(), index..index + 1)
+            .pop()
+            .map(|(_, thumbnail)| thumbnail)
+            .unwrap_or(Thumbnail::Failed);
+        let loading = matches!(thumbnail, Thumbnail::Loading);
+        preview.show(slider.value_position(index as u32), name, thumbnail);
+        if loading {
+            NextUpdate::WaitUntil(now + Duration::from_millis(50))
+        } else {
+            NextUpdate::Latest
+        }
+    }
+
+    /// Stops panning and zooming, used when the keys that do this may be
+    /// released without the widget noticing
+    fn stop_camera_movement(&mut self) {
+        self.panning = false;
+        self.hor_pan_input = MovementDir::None;
+        self.ver_pan_input = MovementDir::None;
+        self.zoom_input = MovementDir::None;
+        self.hor_pan_vel = 0.0;
+        self.ver_pan_vel = 0.0;
+        self.zoom_vel = 0.0;
+    }
+
     fn get_texture(&self) -> Option<AnimationFrameTexture> {
         self.playback_manager.image_texture()
     }
@@ -385,11 +583,24 @@ impl PictureWidgetData {
         self.render_validity.invalidate();
     }
 
+    pub fn set_img_zoom(&mut self, texel_size: f32) {
+        self.img_texel_size =
+            texel_size.clamp(MIN_ZOOM_FACTOR, MAX_ZOOM_FACTOR);
+        self.scaling = ScalingMode::Fixed;
+        self.update_scaling_buttons();
+        self.render_validity.invalidate();
+    }
+
     pub fn set_img_size_to_fit(&mut self, stretch: bool) {
         {
             let mut cache = self.cache.lock().unwrap();
             cache.image.fit_stretches = stretch;
         }
+        self.set_fit_scaling(stretch);
+    }
+
+    /// Like `set_img_size_to_fit` but doesn't remember the choice in the cache
+    pub fn set_fit_scaling(&mut self, stretch: bool) {
         self.scaling = if stretch {
             ScalingMode::FitStretch
         } else {
@@ -399,6 +610,20 @@ impl PictureWidgetData {
         self.render_validity.invalidate();
     }
 
+    pub fn set_recursive(&mut self, recursive: bool) {
+        if let Err(e) = self.playback_manager.set_recursive(recursive) {
+            eprintln!("Error while updating directory {:?}", e);
+        }
+        self.render_validity.invalidate();
+    }
+
+    pub fn set_sort_order(&mut self, order: SortOrder) {
+        if let Err(e) = self.playback_manager.set_sort_order(order) {
+            eprintln!("Error while sorting the directory {:?}", e);
+        }
+        self.render_validity.invalidate();
+    }
+
     pub fn toggle_antialias(&mut self) {
         let aa = match self.antialiasing {
             Antialias::Auto
@@ -414,6 +639,18 @@ impl PictureWidgetData {
         self.render_validity.invalidate();
     }
 
+    pub fn cycle_tone_map(&mut self) {
+        self.tone_map = self.tone_map.next();
+        self.cache.lock().unwrap().image.tone_map = self.tone_map;
+        self.render_validity.invalidate();
+    }
+
+    pub fn change_exposure(&mut self, stops: f32) {
+        self.exposure =
+            (self.exposure + stops).clamp(-MAX_EXPOSURE, MAX_EXPOSURE);
+        self.render_validity.invalidate();
+    }
+
     pub fn set_automatic_antialias(&mut self) {
         self.antialiasing = Antialias::Auto;
         self.cache.lock().unwrap().image.antialiasing = Antialias::Auto;
@@ -452,6 +689,123 @@ impl PictureWidgetData {
         }
     }
 
+    /// Returns the values of the variables that can be used in user commands
+    /// or `None` if there's no image shown. See `configuration::Command`
+    ///
+    /// Also returns `None` if the image path is not valid UTF-8 as the commands
+    /// couldn't be constructed from it.
+    fn command_variables(&mut self) -> Option<HashMap<&'static str, String>> {
+        let img_path = self.playback_manager.shown_file_path().clone()?;
+        if img_path.to_str().is_none() {
+            eprintln!(
+                "Could not convert the image path to utf8. Path: '{:?}'",
+                img_path
+            );
+            return None;
+        }
+        // Every part of a valid UTF-8 path is valid UTF-8 as well
+        let mut vars = HashMap::new();
+        let mut insert_path = |name, path: Option<&OsStr>| {
+            if let Some(path) = path.and_then(|p| p.to_str()) {
+                vars.insert(name, path.to_owned());
+            }
+        };
+        insert_path("img", Some(img_path.as_os_str()));
+        insert_path("folder", img_path.parent().map(|p| p.as_os_str()));
+        insert_path("filename", img_path.file_name());
+        insert_path("stem", img_path.file_stem());
+        insert_path("ext", img_path.extension());
+        if let Some(index) = self.playback_manager.current_file_index() {
+            vars.insert("index", (index + 1).to_string());
+        }
+        if let Some(count) = self.playback_manager.current_dir_len() {
+            vars.insert("count", count.to_string());
+        }
+        vars.insert(
+            "frame",
+            self.playback_manager.current_frame_index().to_string(),
+        );
+        vars.insert("zoom", format!("{}", self.img_texel_size * 100.0));
+        if let Some(texture) = self.get_texture() {
+            let (w, h) = texture.oriented_dimensions();
+            vars.insert("width", w.to_string());
+            vars.insert("height", h.to_string());
+            let view = self.visible_rect(&texture);
+            vars.insert("view_x", (view.x as u32).to_string());
+            vars.insert("view_y", (view.y as u32).to_string());
+            vars.insert("view_w", (view.w as u32).to_string());
+            vars.insert("view_h", (view.h as u32).to_string());
+        }
+        Some(vars)
+    }
+
+    /// The part of the image that's visible in the widget, in whole pixels of
+    /// the oriented image
+    fn visible_rect(&self, texture: &AnimationFrameTexture) -> FrameRect {
+        let (w, h) = texture.oriented_dimensions();
+        // The size of an image pixel in logical units
+        let texel_size = self.img_texel_size / self.prev_dpi_scale;
+        let img_size = LogicalVector::new(w as f32, h as f32);
+        let img_corner = self.img_pos - img_size * texel_size * 0.5;
+        let widget_size = self.drawn_bounds.size;
+        let view_min = (LogicalVector::default() - img_corner) / texel_size;
+        let view_max = (widget_size - img_corner) / texel_size;
+        let left = view_min.vec.x.floor().clamp(0.0, img_size.vec.x);
+        let top = view_min.vec.y.floor().clamp(0.0, img_size.vec.y);
+        let right = view_max.vec.x.ceil().clamp(0.0, img_size.vec.x);
+        let bottom = view_max.vec.y.ceil().clamp(0.0, img_size.vec.y);
+        FrameRect {
+            x: left,
+            y: top,
+            w: right - left,
+            h: bottom - top,
+        }
+    }
+
+    /// Has the visible part of an SVG rendered again at the zoom level once
+    /// the view has stopped changing. SVGs aren't rotated, so the visible
+    /// rectangle is also a rectangle of the frame.
+    fn update_svg_render(&mut self, now: Instant) -> NextUpdate {
+        let shown_svg = self
+            .playback_manager
+            .shown_file_path()
+            .as_deref()
+            .is_some_and(is_svg);
+        let texture = match self.get_texture() {
+            // The frame is sharp enough unless it's zoomed in
+            Some(texture) if shown_svg && self.img_texel_size > 1.0 => texture,
+            _ => return NextUpdate::Latest,
+        };
+        let region = self.visible_rect(&texture);
+        if region.w <= 0.0 || region.h <= 0.0 {
+            return NextUpdate::Latest;
+        }
+        let texel_size = self.img_texel_size;
+        let view = match &mut self.svg_view {
+            Some(view)
+                if view.region == region && view.texel_size == texel_size =>
+            {
+                view
+            }
+            view => view.insert(SvgView {
+                region,
+                texel_size,
+                changed_at: now,
+                requested: false,
+            }),
+        };
+        if view.requested {
+            return NextUpdate::Latest;
+        }
+        let settled_at = view.changed_at + SVG_SETTLE_TIME;
+        if now < settled_at {
+            return NextUpdate::WaitUntil(settled_at);
+        }
+        view.requested = true;
+        self.playback_manager.request_svg_render(region, texel_size);
+        NextUpdate::Latest
+    }
+
     fn update_scaling_buttons(&mut self) {
         self.bottom_bar
             .update_scaling_buttons(self.scaling, self.img_texel_size);
@@ -464,11 +818,13 @@ pub struct PictureWidget {
 impl PictureWidget {
     add_common_widget_functions!(data);
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         display: &Display,
         window: &Rc<Window>,
         bottom_bar: Rc<BottomBar>,
         left_to_pan_hint: Rc<HelpScreen>,
+        gallery: Rc<GalleryWidget>,
         copy_notifications: CopyNotifications,
         configuration: Rc<RefCell<Configuration>>,
         cache: Arc<Mutex<Cache>>,
@@ -519,11 +875,14 @@ impl PictureWidget {
             }
         };
 
+        let tone_map = cache.lock().unwrap().image.tone_map;
+
         let mut data = PictureWidgetData {
             placement: Default::default(),
             drawn_bounds: Default::default(),
             visible: true,
             prev_draw_size: Default::default(),
+            prev_dpi_scale: 1.0,
             click: false,
             hover: false,
             configuration,
@@ -531,6 +890,7 @@ impl PictureWidget {
             playback_manager: PlaybackManager::new(),
             clipboard_handler: Some(ClipboardHandler::new()),
             clipboard_request_was_pending: false,
+            command_runner: CommandRunner::new(),
             render_validity: Default::default(),
 
             program,
@@ -539,6 +899,10 @@ impl PictureWidget {
             scaling,
             img_pos: Default::default(),
             antialiasing,
+            tone_map,
+            exposure: 0.0,
+            svg_view: None,
+            tiles_pending: false,
             hor_pan_input: MovementDir::None,
             ver_pan_input: MovementDir::None,
             zoom_input: MovementDir::None,
@@ -554,6 +918,7 @@ impl PictureWidget {
             next_update: NextUpdate::Latest,
             bottom_bar,
             left_to_pan_hint,
+            gallery,
             copy_notifications,
             window: Rc::downgrade(window),
         };
@@ -579,6 +944,28 @@ impl PictureWidget {
         borrowed.set_img_size_to_fit(stretch);
     }
 
+    /// Fits the image to the widget for this session only, the scaling stored
+    /// in the cache is left unchanged.
+    pub fn set_fit_scaling(&self, stretch: bool) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.set_fit_scaling(stretch);
+    }
+
+    /// Starts the presentation of the images in the folder. `interval` is the
+    /// time each image is shown for, the default is used if it's `None`.
+    pub fn start_presentation(&self, interval: Option<Duration>, random: bool) {
+        let mut borrowed = self.data.borrow_mut();
+        if let Some(interval) = interval {
+            borrowed.playback_manager.set_present_interval(interval);
+        }
+        if random {
+            borrowed.playback_manager.start_random_presentation();
+        } else {
+            borrowed.playback_manager.start_presentation();
+        }
+        borrowed.render_validity.invalidate();
+    }
+
     pub fn jump_to_index(&self, index: u32) {
         let mut borrowed = self.data.borrow_mut();
         borrowed
@@ -595,16 +982,113 @@ impl PictureWidget {
         borrowed.render_validity.invalidate();
     }
 
+    /// Includes the images in the subfolders of the folder when browsing
+    pub fn set_recursive(&self, recursive: bool) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.set_recursive(recursive);
+    }
+
+    /// Sets the order in which the images are browsed
+    pub fn set_sort_order(&self, order: SortOrder) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.set_sort_order(order);
+    }
+
+    /// Keeps the images up to date with the folder. The event loop is woken
+    /// up through `proxy` when the folder changes.
+    pub fn watch_folders(&self, proxy: EventLoopProxy<()>) {
+        let mut borrowed = self.data.borrow_mut();
+        let wake = Box::new(move || {
+            let _ = proxy.send_event(());
+        });
+        if let Err(e) = borrowed.playback_manager.watch_folders(wake) {
+            // The folder is read again when the window gains focus instead
+            if e.kind() != std::io::ErrorKind::Unsupported {
+                eprintln!("Could not watch the folder for changes: {e}");
+            }
+        }
+    }
+
+    /// Jumps to every image that's added to the folder
+    pub fn set_follow(&self, follow: bool) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.playback_manager.set_follow(follow);
+        borrowed.render_validity.invalidate();
+    }
+
+    /// Shows the files at `paths` instead of the files of a folder. Next,
+    /// previous and the slider follow the order of `paths`.
+    pub fn open_playlist(&self, paths: Vec<PathBuf>) -> Result<(), String> {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.render_validity.invalidate();
+        borrowed
+            .playback_manager
+            .open_playlist(paths)
+            .map_err(|e| format!("Could not open the playlist: {e}"))
+    }
+
+    /// Performs the action with the given name as if a key bound to it was
+    /// pressed. Returns false if there's no such action.
+    ///
+    /// Actions that last as long as a key is held down (like `pan_left`) can't
+    /// be performed this way.
+    pub fn perform_action(&self, action_name: &str) -> bool {
+        self.handle_actions(|name| name == action_name)
+    }
+
+    /// Sets the zoom level, where 100 means that one image pixel is displayed
+    /// on one physical screen pixel.
+    pub fn set_zoom(&self, percent: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.set_img_zoom(percent / 100.0);
+    }
+
+    /// The path of the image that's currently shown
+    pub fn shown_file_path(&self) -> Option<PathBuf> {
+        let borrowed = self.data.borrow();
+        borrowed.playback_manager.shown_file_path().clone()
+    }
+
+    /// Returns the index of the current image and the number of images in the
+    /// current folder. Either is `None` while the folder is being read.
+    pub fn folder_position(&self) -> (Option<usize>, Option<usize>) {
+        let mut borrowed = self.data.borrow_mut();
+        (
+            borrowed.playback_manager.current_file_index(),
+            borrowed.playback_manager.current_dir_len(),
+        )
+    }
+
     fn handle_key_input(&self, input_key: &str, modifiers: ModifiersState) {
+        let config = self.data.borrow().configuration.clone();
+        self.handle_actions(|action_name| {
+            action_triggered(&config, action_name, input_key, modifiers)
+        });
         let mut borrowed = self.data.borrow_mut();
+        if let Some(var_map) = borrowed.command_variables() {
+            execute_triggered_commands(
+                borrowed.configuration.clone(),
+                &mut borrowed.command_runner,
+                input_key,
+                modifiers,
+                &var_map,
+            );
+        }
+    }
+
+    /// Performs every action for which `is_triggered` returns true. Returns
+    /// true if any action was performed.
+    fn handle_actions<F: Fn(&str) -> bool>(&self, is_triggered: F) -> bool {
+        let mut borrowed = self.data.borrow_mut();
+        let mut any_triggered = false;
         macro_rules! triggered {
             ($action_name:ident) => {
-                action_triggered(
-                    &borrowed.configuration,
-                    $action_name,
-                    input_key,
-                    modifiers,
-                )
+                if is_triggered($action_name) {
+                    any_triggered = true;
+                    true
+                } else {
+                    false
+                }
             };
         }
         if triggered!(TOGGLE_FULLSCREEN_NAME) {
@@ -644,6 +1128,14 @@ impl PictureWidget {
                 .request_load(LoadRequest::LoadNext);
             borrowed.render_validity.invalidate();
         }
+        if triggered!(PAGE_PREV_NAME) {
+            borrowed.playback_manager.page_jump(-1);
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(PAGE_NEXT_NAME) {
+            borrowed.playback_manager.page_jump(1);
+            borrowed.render_validity.invalidate();
+        }
         if triggered!(IMG_FIT_NAME) {
             borrowed.set_img_size_to_fit(true);
         }
@@ -659,6 +1151,65 @@ impl PictureWidget {
         if triggered!(SET_AUTOMATIC_ANTIALIAS_NAME) {
             borrowed.set_automatic_antialias();
         }
+        if triggered!(TOGGLE_RECURSIVE_NAME) {
+            let recursive = !borrowed.playback_manager.recursive();
+            borrowed.set_recursive(recursive);
+        }
+        if triggered!(CYCLE_SORT_NAME) {
+            let mut order =
+                borrowed.playback_manager.sort_order().unwrap_or_default();
+            order.key = order.key.next();
+            borrowed.set_sort_order(order);
+        }
+        if triggered!(REVERSE_SORT_NAME) {
+            let mut order =
+                borrowed.playback_manager.sort_order().unwrap_or_default();
+            order.descending = !order.descending;
+            borrowed.set_sort_order(order);
+        }
+        if triggered!(TOGGLE_FOLLOW_NAME) {
+            let follow = !borrowed.playback_manager.follow();
+            borrowed.playback_manager.set_follow(follow);
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(TOGGLE_COLOR_MANAGEMENT_NAME) {
+            let manage = !borrowed.playback_manager.manage_colors();
+            borrowed.playback_manager.set_manage_colors(manage);
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(CYCLE_TONE_MAP_NAME) {
+            borrowed.cycle_tone_map();
+        }
+        if triggered!(EXPOSURE_UP_NAME) {
+            borrowed.change_exposure(EXPOSURE_STEP);
+        }
+        if triggered!(EXPOSURE_DOWN_NAME) {
+            borrowed.change_exposure(-EXPOSURE_STEP);
+        }
+        #[cfg(feature = "raw")]
+        if triggered!(TOGGLE_RAW_DEVELOP_NAME) {
+            let develop = !borrowed.playback_manager.develop_raw();
+            borrowed.playback_manager.set_develop_raw(develop);
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(TOGGLE_FILMSTRIP_NAME) {
+            let visible = !borrowed.bottom_bar.filmstrip_visible();
+            borrowed.bottom_bar.set_filmstrip_visible(visible);
+            borrowed.render_validity.invalidate();
+        }
+        if triggered!(TOGGLE_GALLERY_NAME) {
+            if borrowed.gallery.is_open() {
+                borrowed.gallery.close();
+            } else {
+                let index =
+                    borrowed.playback_manager.current_file_index().unwrap_or(0);
+                borrowed.gallery.open(index);
+                // The gallery gets the key and mouse input from now on
+                borrowed.stop_camera_movement();
+                borrowed.left_to_pan_hint.set_visible(false);
+            }
+            borrowed.render_validity.invalidate();
+        }
         if triggered!(PLAY_PRESENT_NAME) {
             match borrowed.playback_manager.playback_state() {
                 PlaybackState::Present => {
@@ -695,11 +1246,15 @@ impl PictureWidget {
             if let Some(path) =
                 borrowed.playback_manager.shown_file_path().clone()
             {
+                let page = borrowed
+                    .playback_manager
+                    .current_page()
+                    .map_or(0, |(page, _)| page);
                 let request_started;
                 if let Some(clipboard_handler) = &mut borrowed.clipboard_handler
                 {
                     request_started = true;
-                    clipboard_handler.request_copy(path);
+                    clipboard_handler.request_copy(path, page);
                     borrowed.copy_notifications.set_started();
                 } else {
                     request_started = false;
@@ -709,27 +1264,7 @@ impl PictureWidget {
                 }
             }
         }
-        if let Some(img_path) = borrowed.playback_manager.shown_file_path() {
-            if let Some(folder_path) = img_path.parent() {
-                let img_and_folder = (img_path.to_str(), folder_path.to_str());
-                if let (Some(img_path), Some(folder_path)) = img_and_folder {
-                    execute_triggered_commands(
-                        borrowed.configuration.clone(),
-                        input_key,
-                        modifiers,
-                        img_path,
-                        folder_path,
-                    );
-                } else {
-                    eprintln!("Could not convert the image path to utf8. Path: '{:?}'", img_path);
-                }
-            } else {
-                eprintln!(
-                    "Could not get parent folder for the image path {:?}",
-                    img_path
-                );
-            }
-        }
+        any_triggered
     }
 }
 
@@ -748,6 +1283,9 @@ impl Widget for PictureWidget {
             return data.next_update;
         }
         let now = Instant::now();
+        if data.playback_manager.process_folder_changes() {
+            data.render_validity.invalidate();
+        }
         let prev_texture = data.playback_manager.image_texture();
         data.next_update = data.playback_manager.update_image(window);
         let new_texture = data.playback_manager.image_texture();
@@ -760,14 +1298,40 @@ impl Widget for PictureWidget {
                 .slider
                 .set_steps(curr_dir_len as u32, curr_file_index as u32);
         }
+        if curr_dir_len.is_none()
+            && data.playback_manager.shown_file_path().is_some()
+        {
+            // The folder is still being read, update the slider once it's done
+            let next_update = now + Duration::from_millis(100);
+            data.next_update = data
+                .next_update
+                .aggregate(NextUpdate::WaitUntil(next_update));
+        }
         //data.slider.set_step_bg(data.playback_manager.cached_from_dir());
-        let playback_state = data.playback_manager.playback_state();
-        data.set_window_title_filename(
-            window,
-            playback_state,
-            data.playback_manager.shown_file_path(),
-            &new_texture
-        );
+        if data.gallery.is_open() {
+            let gallery_update = data.update_gallery(window, now);
+            data.next_update = data.next_update.aggregate(gallery_update);
+        } else {
+            let playback_state = data.playback_manager.playback_state();
+            data.set_window_title_filename(
+                window,
+                playback_state,
+                data.playback_manager.shown_file_path(),
+                &new_texture,
+            );
+        }
+        if data.bottom_bar.widget.visible()
+            && data.bottom_bar.filmstrip_visible()
+        {
+            let filmstrip_update = data.update_filmstrip(window, now);
+            data.next_update = data.next_update.aggregate(filmstrip_update);
+        }
+        if data.bottom_bar.widget.visible() {
+            let preview_update = data.update_slider_preview(window, now);
+            data.next_update = data.next_update.aggregate(preview_update);
+        } else {
+            data.bottom_bar.slider_preview.hide();
+        }
         if prev_texture.is_none() != new_texture.is_none() {
             data.render_validity.invalidate();
         } else if let (Some(prev_tex), Some(new_tex)) =
@@ -775,8 +1339,17 @@ impl Widget for PictureWidget {
         {
             if !Rc::ptr_eq(&prev_tex.tex_grid, &new_tex.tex_grid) {
                 data.render_validity.invalidate();
+                data.svg_view = None;
+            } else if prev_tex.detail.as_ref().map(Rc::as_ptr)
+                != new_tex.detail.as_ref().map(Rc::as_ptr)
+            {
+                data.render_validity.invalidate();
             }
         }
+        if !data.gallery.is_open() {
+            let svg_update = data.update_svg_render(now);
+            data.next_update = data.next_update.aggregate(svg_update);
+        }
         if let Some(clipboard_handler) = &data.clipboard_handler {
             let clipboard_result = clipboard_handler.try_get_result();
             let request_pending = clipboard_result.is_none();
@@ -795,6 +1368,28 @@ impl Widget for PictureWidget {
                     .aggregate(NextUpdate::WaitUntil(next_update));
             }
         }
+        let mut reload_dir = false;
+        for result in data.command_runner.try_get_results() {
+            result.report();
+            reload_dir |= result.reload_after;
+        }
+        if reload_dir {
+            if let Err(e) = data.playback_manager.update_directory() {
+                eprintln!("{e}");
+            }
+            data.render_validity.invalidate();
+        }
+        if data.command_runner.is_busy() {
+            let next_update = now + Duration::from_millis(100);
+            data.next_update = data
+                .next_update
+                .aggregate(NextUpdate::WaitUntil(next_update));
+        }
+        if data.tiles_pending {
+            // The rest of the tiles in view are uploaded in the next draws
+            data.render_validity.invalidate();
+            data.next_update = NextUpdate::Soonest;
+        }
         if data.zoom_input.moving()
             || data.hor_pan_input.moving()
             || data.ver_pan_input.moving()
@@ -821,12 +1416,20 @@ impl Widget for PictureWidget {
             data.update_image_transform(context.dpi_scale_factor);
             data.apply_camera_movement(context.dpi_scale_factor);
             texture = data.get_texture();
+            if data.gallery.is_open() {
+                // The gallery is drawn in place of the image
+                return Ok(data.next_update);
+            }
         }
         if let Some(texture) = texture {
             let data = self.data.borrow();
-            draw_tex_grid(data, target, context, texture);
+            let tiles_complete = draw_tex_grid(data, target, context, texture);
+            self.data.borrow_mut().tiles_pending = !tiles_complete;
         }
         let borrowed = self.data.borrow();
+        if borrowed.tiles_pending {
+            return Ok(NextUpdate::Soonest);
+        }
         Ok(borrowed.next_update)
     }
 
@@ -838,8 +1441,23 @@ impl Widget for PictureWidget {
     }
 
     fn handle_event(&self, event: &Event) {
-        if !self.data.borrow().visible {
-            return;
+        {
+            let borrowed = self.data.borrow();
+            if !borrowed.visible {
+                return;
+            }
+            // The gallery handles the input while it's open
+            let input = matches!(
+                event.kind,
+                EventKind::MouseMove
+                    | EventKind::MouseButton { .. }
+                    | EventKind::MouseScroll { .. }
+                    | EventKind::ReceivedCharacter(_)
+                    | EventKind::KeyInput { .. }
+            );
+            if input && borrowed.gallery.is_open() {
+                return;
+            }
         }
         match event.kind {
             EventKind::MouseMove => {
@@ -1045,8 +1663,10 @@ impl Widget for PictureWidget {
                 borrowed.render_validity.invalidate();
             }
             EventKind::Focused(focused) => {
-                if focused {
-                    let mut borrowed = self.data.borrow_mut();
+                let mut borrowed = self.data.borrow_mut();
+                // Without a watcher, changes are only noticed when the user
+                // comes back to the window
+                if focused && !borrowed.playback_manager.is_watching() {
                     if let Err(e) = borrowed.playback_manager.update_directory()
                     {
                         eprintln!("{e}");
@@ -1086,12 +1706,13 @@ impl Drop for PictureWidget {
     }
 }
 
+/// Returns false if there are tiles in view that haven't been uploaded yet
 fn draw_tex_grid(
     data: Ref<PictureWidgetData>,
     target: &mut Frame,
     context: &DrawContext,
     texture: AnimationFrameTexture,
-) {
+) -> bool {
     let size = data.drawn_bounds.size.vec;
     let projection_transform =
         gelatin::cgmath::ortho(0.0, size.x, size.y, 0.0, -1.0, 1.0);
@@ -1149,72 +1770,127 @@ fn draw_tex_grid(
         0.0,
     ));
 
-    // let img_logical_w = img_w / context.dpi_scale_factor;
-    // let img_logical_h = img_h / context.dpi_scale_factor;
-    let cell_phy_step = texture.cell_step_size;
-    for cell_tex in texture.tex_grid.iter() {
-        let (cell_phys_w, cell_phys_h) = cell_tex.tex.dimensions();
-
-        let cell_phy_offset_x = cell_phy_step * cell_tex.col;
-        let cell_phy_offset_y = cell_phy_step * cell_tex.row;
-        // let cell_logical_offset_x = cell_phy_offset_x as f32 / context.dpi_scale_factor;
-        // let cell_logical_offset_y = cell_phy_offset_y as f32 / context.dpi_scale_factor;
-
+    // Maps the unit quad onto a rectangle of the image
+    let rect_transform = |rect: &FrameRect| {
         // The grid is constructed so that it is exactly of size (1, 1) and is located at (0, 0)
         // This allows to leave most of the image transformation logic unchanged.
         let cell_scaling = Matrix4::from_nonuniform_scale(
-            cell_phys_w as f32 / img_phys_w,
-            cell_phys_h as f32 / img_phys_h,
+            rect.w / img_phys_w,
+            rect.h / img_phys_h,
             1.0,
         );
         let cell_translation = Matrix4::from_translation(Vector3::new(
-            cell_phy_offset_x as f32 / img_phys_w,
-            cell_phy_offset_y as f32 / img_phys_h,
+            rect.x / img_phys_w,
+            rect.y / img_phys_h,
             0.0,
         ));
-
         let transform = img_translation
             * orientation
             * img_scaling
             * cell_translation
             * cell_scaling;
         // Projection tranform
-        let transform = projection_transform * transform;
+        projection_transform * transform
+    };
 
-        let sampler = cell_tex
-			.tex
-			.sampled()
-			.minify_filter(gelatin::glium::uniforms::MinifySamplerFilter::LinearMipmapLinear)
-			.wrap_function(gelatin::glium::uniforms::SamplerWrapFunction::Clamp);
+    let cell_phy_step = texture.cell_step_size;
+    let grid_cells = texture.tex_grid.iter().map(|cell_tex| {
+        let (cell_phys_w, cell_phys_h) = cell_tex.tex.dimensions();
+        let cell_rect = FrameRect {
+            x: (cell_phy_step * cell_tex.col) as f32,
+            y: (cell_phy_step * cell_tex.row) as f32,
+            w: cell_phys_w as f32,
+            h: cell_phys_h as f32,
+        };
+        (&cell_tex.tex, cell_rect)
+    });
+    // The preview of an image that's still loading covers all of it
+    let preview = texture.preview.as_ref().map(|tex| {
+        let rect = FrameRect {
+            x: 0.0,
+            y: 0.0,
+            w: img_phys_w,
+            h: img_phys_h,
+        };
+        (&**tex, rect)
+    });
+    // The sharper render of an SVG is drawn over the grid
+    let detail = texture
+        .detail
+        .as_ref()
+        .map(|detail| (&detail.tex, detail.region));
+    // Only the tiles in view are drawn, at the level of detail they're shown
+    // at. Each level is half the size of the previous one.
+    let (tiles, tiles_complete) = match &texture.tiles {
+        Some(tiles) => {
+            let level = (1.0 / data.img_texel_size).log2().floor().max(0.0);
+            tiles.tiles_in_view(context.display, level as usize, |rect| {
+                let transform = rect_transform(rect);
+                let a = transform * Vector4::new(0.0, 0.0, 0.0, 1.0);
+                let b = transform * Vector4::new(1.0, 1.0, 0.0, 1.0);
+                a.x.min(b.x) < 1.0
+                    && a.x.max(b.x) > -1.0
+                    && a.y.min(b.y) < 1.0
+                    && a.y.max(b.y) > -1.0
+            })
+        }
+        None => (Vec::new(), true),
+    };
+    let tile_cells = tiles.iter().map(|(tex, rect)| (&**tex, *rect));
+    let cells = grid_cells.chain(preview).chain(detail).chain(tile_cells);
+    for (cell_tex, cell_rect) in cells {
+        // The size of a texel of this texture in physical pixels
+        let texel_size =
+            data.img_texel_size * cell_rect.w / cell_tex.dimensions().0 as f32;
+        let transform = rect_transform(&cell_rect);
 
         let filter = match data.antialiasing {
-            Antialias::Auto
-                if data.img_texel_size < AA_TEXEL_SIZE_THRESHOLD =>
-            {
+            Antialias::Auto if texel_size < AA_TEXEL_SIZE_THRESHOLD => {
                 MagnifySamplerFilter::Linear
             }
             Antialias::Auto | Antialias::Never => MagnifySamplerFilter::Nearest,
             Antialias::Always => MagnifySamplerFilter::Linear,
         };
-        let sampler = sampler.magnify_filter(filter);
 
         // building the uniforms
-        let lod_level =
-            ((1.0 / data.img_texel_size).log2().max(0.0) + 0.125).floor();
-        let uniforms = uniform! {
-            matrix: Into::<[[f32; 4]; 4]>::into(transform),
-            bright_shade: data.bright_shade,
-            tex: sampler,
-            lod_level: lod_level,
+        let lod_level = ((1.0 / texel_size).log2().max(0.0) + 0.125).floor();
+        // The values of the operators in the fragment shader
+        let tone_map: i32 = match data.tone_map {
+            ToneMap::Clamp => 1,
+            ToneMap::Reinhard => 2,
+            ToneMap::Aces => 3,
         };
-        target
-            .draw(
-                context.unit_quad_vertices,
-                context.unit_quad_indices,
-                &data.program,
-                &uniforms,
-                &image_draw_params,
-            )
-            .unwrap();
+        let exposure = data.exposure.exp2();
+        macro_rules! draw_cell {
+            ($tex:expr, $tone_map:expr) => {{
+                let sampler = $tex
+                    .sampled()
+                    .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+                    .wrap_function(SamplerWrapFunction::Clamp)
+                    .magnify_filter(filter);
+                let uniforms = uniform! {
+                    matrix: Into::<[[f32; 4]; 4]>::into(transform),
+                    bright_shade: data.bright_shade,
+                    tex: sampler,
+                    lod_level: lod_level,
+                    tone_map: $tone_map,
+                    exposure: exposure,
+                };
+                target
+                    .draw(
+                        context.unit_quad_vertices,
+                        context.unit_quad_indices,
+                        &data.program,
+                        &uniforms,
+                        &image_draw_params,
+                    )
+                    .unwrap();
+            }};
+        }
+        match cell_tex {
+            CellTexture::Srgb(tex) => draw_cell!(tex, 0),
+            CellTexture::Float(tex) => draw_cell!(tex, tone_map),
+        }
     }
+    tiles_complete
 }
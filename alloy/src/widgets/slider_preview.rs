@@ -0,0 +1,248 @@
+use std::{cell::RefCell, rc::Rc};
+
+use gelatin::{
+    add_common_widget_functions,
+    glium::Frame,
+    misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
+    text::{draw_text, Font, TextStyle},
+    window::RenderValidity,
+    DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
+};
+
+use super::thumbnail_cell::{draw_thumbnail_cell, same_thumbnail, CellState};
+use crate::image_cache::Thumbnail;
+
+/// The width and height of the area of the thumbnail
+const PREVIEW_SIZE: f32 = 144.0;
+const PADDING: f32 = 6.0;
+const FONT_SIZE: f32 = 13.0;
+const TEXT_HEIGHT: f32 = 20.0;
+/// The space between the preview and the point it belongs to
+const GAP: f32 = 6.0;
+const BG_COLOR: [f32; 4] = [0.08, 0.08, 0.08, 0.9];
+const TEXT_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+
+struct Preview {
+    /// The point above which the preview is shown
+    anchor: LogicalVector,
+    name: String,
+    /// The name shortened to fit under the thumbnail. This only changes with
+    /// the name as the width is fixed.
+    elided_name: String,
+    thumbnail: Thumbnail,
+}
+
+struct SliderPreviewData {
+    placement: WidgetPlacement,
+    drawn_bounds: LogicalRect,
+    visible: bool,
+    render_validity: RenderValidity,
+
+    preview: Option<Preview>,
+}
+
+impl WidgetData for SliderPreviewData {
+    fn placement(&mut self) -> &mut WidgetPlacement {
+        &mut self.placement
+    }
+
+    fn drawn_bounds(&mut self) -> &mut LogicalRect {
+        &mut self.drawn_bounds
+    }
+
+    fn visible(&mut self) -> &mut bool {
+        &mut self.visible
+    }
+}
+
+impl SliderPreviewData {
+    /// The area of the whole preview, kept within the bounds of the widget
+    fn preview_rect(&self, anchor: LogicalVector) -> LogicalRect {
+        let size = LogicalVector::new(
+            PREVIEW_SIZE + PADDING * 2.0,
+            PREVIEW_SIZE + TEXT_HEIGHT + PADDING * 2.0,
+        );
+        let bounds = self.drawn_bounds;
+        let max_left = (bounds.right() - size.vec.x).max(bounds.left());
+        let left = (anchor.vec.x - size.vec.x * 0.5)
+            .min(max_left)
+            .max(bounds.left());
+        let top = (anchor.vec.y - GAP - size.vec.y).max(bounds.top());
+        LogicalRect {
+            pos: LogicalVector::new(left, top),
+            size,
+        }
+    }
+}
+
+/// A thumbnail and the name of an image shown above a point of the slider
+/// of the bottom bar. It covers the whole picture area, but only draws the
+/// preview.
+pub struct SliderPreview {
+    data: RefCell<SliderPreviewData>,
+}
+
+impl SliderPreview {
+    add_common_widget_functions!(data);
+
+    pub fn new() -> SliderPreview {
+        let placement = WidgetPlacement {
+            width: Length::Stretch {
+                min: 0.0,
+                max: f32::INFINITY,
+            },
+            height: Length::Stretch {
+                min: 0.0,
+                max: f32::INFINITY,
+            },
+            ignore_layout: true,
+            ..Default::default()
+        };
+        SliderPreview {
+            data: RefCell::new(SliderPreviewData {
+                placement,
+                drawn_bounds: Default::default(),
+                visible: true,
+                render_validity: Default::default(),
+                preview: None,
+            }),
+        }
+    }
+
+    /// Shows the preview above `anchor`
+    pub fn show(
+        &self,
+        anchor: LogicalVector,
+        name: String,
+        thumbnail: Thumbnail,
+    ) {
+        let mut borrowed = self.data.borrow_mut();
+        let changed = match &borrowed.preview {
+            Some(prev) => {
+                prev.anchor.vec != anchor.vec
+                    || prev.name != name
+                    || !same_thumbnail(&prev.thumbnail, &thumbnail)
+            }
+            None => true,
+        };
+        if changed {
+            let elided_name = match borrowed.preview.take() {
+                Some(prev) if prev.name == name => prev.elided_name,
+                _ => elide_to_width(&name, PREVIEW_SIZE),
+            };
+            borrowed.preview = Some(Preview {
+                anchor,
+                name,
+                elided_name,
+                thumbnail,
+            });
+            borrowed.render_validity.invalidate();
+        }
+    }
+
+    pub fn hide(&self) {
+        let mut borrowed = self.data.borrow_mut();
+        if borrowed.preview.take().is_some() {
+            borrowed.render_validity.invalidate();
+        }
+    }
+}
+
+impl Widget for SliderPreview {
+    fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+    ) -> Result<NextUpdate, WidgetError> {
+        let borrowed = self.data.borrow();
+        let preview = match &borrowed.preview {
+            Some(preview) if borrowed.visible => preview,
+            _ => return Ok(NextUpdate::Latest),
+        };
+        let rect = borrowed
+            .preview_rect(preview.anchor)
+            .align_to_pixels(context.dpi_scale_factor);
+        context.clear_color(target, BG_COLOR, Some(rect));
+        let thumbnail_area = LogicalRect {
+            pos: rect.pos,
+            size: LogicalVector::new(rect.size.vec.x, rect.size.vec.x),
+        };
+        draw_thumbnail_cell(
+            target,
+            context,
+            &preview.thumbnail,
+            thumbnail_area,
+            PADDING,
+            rect,
+            CellState {
+                selected: false,
+                hovered: false,
+            },
+        );
+        let text_area = LogicalRect {
+            pos: LogicalVector::new(
+                rect.left() + PADDING,
+                thumbnail_area.bottom(),
+            ),
+            size: LogicalVector::new(PREVIEW_SIZE, TEXT_HEIGHT),
+        };
+        let style = TextStyle {
+            font_size: FONT_SIZE,
+            color: TEXT_COLOR,
+            horizontal_align: Alignment::Center,
+            vertical_align: Alignment::Start,
+            ..Default::default()
+        };
+        draw_text(target, context, &preview.elided_name, &style, text_area)?;
+        Ok(NextUpdate::Latest)
+    }
+
+    fn layout(&self, available_space: LogicalRect) {
+        self.data.borrow_mut().default_layout(available_space);
+    }
+
+    fn handle_event(&self, _event: &Event) {}
+
+    fn children(&self, _children: &mut Vec<Rc<dyn Widget>>) {}
+
+    fn placement(&self) -> WidgetPlacement {
+        self.data.borrow().placement
+    }
+
+    fn visible(&self) -> bool {
+        self.data.borrow().visible
+    }
+
+    fn set_valid_ref(&self, render_validity: RenderValidity) {
+        self.data.borrow_mut().render_validity = render_validity;
+    }
+}
+
+/// Replaces the middle of `text` with an ellipsis if it's wider than
+/// `max_width`, because the text isn't clipped when drawn
+fn elide_to_width(text: &str, max_width: f32) -> String {
+    let font = match Font::default_font() {
+        Some(font) => font,
+        None => return text.to_owned(),
+    };
+    let fits = |text: &str| font.measure(text, FONT_SIZE).vec.x <= max_width;
+    if fits(text) {
+        return text.to_owned();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut keep = chars.len();
+    while keep > 0 {
+        keep -= 1;
+        let head = keep - keep / 2;
+        let tail = keep / 2;
+        let elided: String = chars[..head]
+            .iter()
+            .chain(std::iter::once(&'…'))
+            .chain(chars[chars.len() - tail..].iter())
+            .collect();
+        if fits(&elided) {
+            return elided;
+        }
+    }
+    String::from("…")
+}
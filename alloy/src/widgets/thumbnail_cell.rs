@@ -0,0 +1,154 @@
+//! Drawing of the thumbnails shared by the gallery and the filmstrip
+
+use std::rc::Rc;
+
+use gelatin::{
+    cgmath::{Matrix4, Vector3},
+    glium::{
+        uniform,
+        uniforms::{MagnifySamplerFilter, MinifySamplerFilter},
+        Blend, BlendingFunction, DrawParameters, Frame, LinearBlendingFactor,
+        Surface,
+    },
+    misc::{LogicalRect, LogicalVector},
+    DrawContext,
+};
+
+use crate::image_cache::Thumbnail;
+
+const SELECTED_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.4];
+const PLACEHOLDER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.12];
+const HOVER_BRIGHTEN: f32 = 0.15;
+
+/// How a cell is highlighted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellState {
+    pub selected: bool,
+    pub hovered: bool,
+}
+
+/// Draws the thumbnail centered within `cell`, keeping `padding` logical
+/// pixels of space around it. Nothing is drawn outside of `clip`.
+pub fn draw_thumbnail_cell(
+    target: &mut Frame,
+    context: &DrawContext,
+    thumbnail: &Thumbnail,
+    cell: LogicalRect,
+    padding: f32,
+    clip: LogicalRect,
+    state: CellState,
+) {
+    if state.selected {
+        let highlight = inset(&cell, (padding / 4.0).min(2.0));
+        if let Some(rect) = intersection(&highlight, &clip) {
+            context.clear_color(target, SELECTED_COLOR, Some(rect));
+        }
+    }
+    let area = inset(&cell, padding);
+    let texture = match thumbnail {
+        Thumbnail::Ready(texture) => texture,
+        Thumbnail::Loading | Thumbnail::Failed => {
+            if let Some(rect) = intersection(&area, &clip) {
+                context.clear_color(target, PLACEHOLDER_COLOR, Some(rect));
+            }
+            return;
+        }
+    };
+    // Fit the thumbnail into the cell, keeping its aspect ratio
+    let (tex_w, tex_h) = texture.dimensions();
+    let scale =
+        (area.size.vec.x / tex_w as f32).min(area.size.vec.y / tex_h as f32);
+    let size = LogicalVector::new(tex_w as f32 * scale, tex_h as f32 * scale);
+    let rect = LogicalRect {
+        pos: area.center() - size * 0.5,
+        size,
+    }
+    .align_to_pixels(context.dpi_scale_factor);
+    let transform =
+        Matrix4::from_nonuniform_scale(rect.size.vec.x, rect.size.vec.y, 1.0);
+    let transform =
+        Matrix4::from_translation(rect.pos.vec.extend(0.0)) * transform;
+    let transform = context.projection_transform * transform;
+    let draw_params = DrawParameters {
+        viewport: Some(*context.viewport),
+        scissor: Some(context.logical_rect_to_viewport(&clip)),
+        blend: Blend {
+            color: BlendingFunction::Addition {
+                source: LinearBlendingFactor::SourceAlpha,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let sampler = texture
+        .sampled()
+        .minify_filter(MinifySamplerFilter::LinearMipmapLinear)
+        .magnify_filter(MagnifySamplerFilter::Linear);
+    let brighten = if state.hovered {
+        HOVER_BRIGHTEN
+    } else {
+        0.0f32
+    };
+    let uniforms = uniform! {
+        matrix: Into::<[[f32; 4]; 4]>::into(transform),
+        tex: sampler,
+        bg_color: [0.0f32; 4],
+        texture_size: [rect.size.vec.x, rect.size.vec.y],
+        brighten: brighten,
+        shadow_color: Into::<[f32; 3]>::into(Vector3::<f32>::new(0.0, 0.0, 0.0)),
+        shadow_offset: 1.0f32
+    };
+    target
+        .draw(
+            context.unit_quad_vertices,
+            context.unit_quad_indices,
+            context.textured_program,
+            &uniforms,
+            &draw_params,
+        )
+        .unwrap();
+}
+
+/// True if redrawing with `b` instead of `a` wouldn't change anything
+pub fn same_thumbnail(a: &Thumbnail, b: &Thumbnail) -> bool {
+    match (a, b) {
+        (Thumbnail::Loading, Thumbnail::Loading) => true,
+        (Thumbnail::Failed, Thumbnail::Failed) => true,
+        (Thumbnail::Ready(a), Thumbnail::Ready(b)) => Rc::ptr_eq(a, b),
+        _ => false,
+    }
+}
+
+pub fn same_thumbnails(
+    a: &[(usize, Thumbnail)],
+    b: &[(usize, Thumbnail)],
+) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b.iter()).all(|((a_index, a), (b_index, b))| {
+            a_index == b_index && same_thumbnail(a, b)
+        })
+}
+
+fn inset(rect: &LogicalRect, amount: f32) -> LogicalRect {
+    let offset = LogicalVector::new(amount, amount);
+    LogicalRect {
+        pos: rect.pos + offset,
+        size: rect.size - offset * 2.0,
+    }
+}
+
+/// `clear_color` doesn't respect the scissor, so the rectangles are cut by hand
+fn intersection(a: &LogicalRect, b: &LogicalRect) -> Option<LogicalRect> {
+    let left = a.left().max(b.left());
+    let top = a.top().max(b.top());
+    let right = a.right().min(b.right());
+    let bottom = a.bottom().min(b.bottom());
+    if right <= left || bottom <= top {
+        return None;
+    }
+    Some(LogicalRect {
+        pos: LogicalVector::new(left, top),
+        size: LogicalVector::new(right - left, bottom - top),
+    })
+}
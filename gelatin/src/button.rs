@@ -10,6 +10,7 @@ use crate::{
     add_common_widget_functions,
     misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
     picture::Picture,
+    text::{Font, TextCache, TextStyle},
     window::RenderValidity,
     DrawContext, Event, EventKind, NextUpdate, Widget, WidgetData, WidgetError,
 };
@@ -24,6 +25,9 @@ struct ButtonData {
     icon: Option<Rc<Picture>>,
     bg_color: [f32; 4],
     on_click: Option<Rc<dyn Fn()>>,
+    text: String,
+    text_style: TextStyle,
+    text_cache: TextCache,
 
     render_validity: RenderValidity,
 }
@@ -59,6 +63,9 @@ impl Button {
                 on_click: None,
                 bg_color: [0.0; 4],
                 icon: None,
+                text: String::new(),
+                text_style: Default::default(),
+                text_cache: TextCache::new(),
                 render_validity: Default::default(),
             }),
         }
@@ -82,6 +89,34 @@ impl Button {
         borrowed.bg_color = bg_color;
         borrowed.render_validity.invalidate();
     }
+
+    /// The text is drawn centered over the icon or background.
+    pub fn set_text<S: Into<String>>(&self, text: S) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text = text.into();
+        borrowed.text_cache.invalidate();
+        borrowed.render_validity.invalidate();
+    }
+
+    /// When `None` is specified, the default font is used. See `Font::set_default`
+    pub fn set_font(&self, font: Option<Font>) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.font = font;
+        borrowed.render_validity.invalidate();
+    }
+
+    /// The font size is in logical pixels
+    pub fn set_font_size(&self, font_size: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.font_size = font_size;
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn set_text_color(&self, color: [f32; 4]) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.color = color;
+        borrowed.render_validity.invalidate();
+    }
 }
 
 impl Default for Button {
@@ -161,7 +196,7 @@ impl Widget for Button {
                 // building the uniforms
                 let uniforms = uniform! {
                     matrix: Into::<[[f32; 4]; 4]>::into(transform),
-                    bg_color: borrowed.bg_color,
+                    color: borrowed.bg_color,
                     size: texture_size,
                     //brighten: if self.hover { 0.15f32 } else { 0.0f32 },
                     brighten: 0.0f32,
@@ -180,6 +215,13 @@ impl Widget for Button {
                     )
                     .unwrap();
             }
+            borrowed.text_cache.draw(
+                target,
+                context,
+                &borrowed.text,
+                &borrowed.text_style,
+                borrowed.drawn_bounds,
+            )?;
         }
         Ok(NextUpdate::Latest)
     }
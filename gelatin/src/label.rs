@@ -7,6 +7,7 @@ use crate::{
     add_common_widget_functions,
     misc::{Alignment, Length, LogicalRect, LogicalVector, WidgetPlacement},
     picture::Picture,
+    text::{Font, TextCache, TextStyle},
     window::RenderValidity,
     DrawContext, Event, NextUpdate, Widget, WidgetData, WidgetError,
 };
@@ -18,6 +19,10 @@ struct LabelData {
 
     shadow_size: f32,
     icon: Option<Rc<Picture>>,
+    bg_color: [f32; 4],
+    text: String,
+    text_style: TextStyle,
+    text_cache: TextCache,
 
     render_validity: RenderValidity,
 }
@@ -50,6 +55,10 @@ impl Label {
                 visible: true,
                 shadow_size: 0.0,
                 icon: None,
+                bg_color: [1.0, 0.1, 0.5, 0.5],
+                text: String::new(),
+                text_style: Default::default(),
+                text_cache: TextCache::new(),
                 render_validity: Default::default(),
             }),
         }
@@ -66,6 +75,41 @@ impl Label {
         borrowed.shadow_size = shadow_size;
         borrowed.render_validity.invalidate();
     }
+
+    /// Sets the color of the background that's drawn when the label has no icon.
+    pub fn set_bg_color(&self, bg_color: [f32; 4]) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.bg_color = bg_color;
+        borrowed.render_validity.invalidate();
+    }
+
+    /// The text is drawn centered over the icon or background.
+    pub fn set_text<S: Into<String>>(&self, text: S) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text = text.into();
+        borrowed.text_cache.invalidate();
+        borrowed.render_validity.invalidate();
+    }
+
+    /// When `None` is specified, the default font is used. See `Font::set_default`
+    pub fn set_font(&self, font: Option<Font>) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.font = font;
+        borrowed.render_validity.invalidate();
+    }
+
+    /// The font size is in logical pixels
+    pub fn set_font_size(&self, font_size: f32) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.font_size = font_size;
+        borrowed.render_validity.invalidate();
+    }
+
+    pub fn set_text_color(&self, color: [f32; 4]) {
+        let mut borrowed = self.data.borrow_mut();
+        borrowed.text_style.color = color;
+        borrowed.render_validity.invalidate();
+    }
 }
 
 impl Default for Label {
@@ -145,7 +189,7 @@ impl Widget for Label {
             } else {
                 let uniforms = uniform! {
                     matrix: Into::<[[f32; 4]; 4]>::into(transform),
-                    color: [1.0f32, 0.1, 0.5, 0.5],
+                    color: borrowed.bg_color,
                     size: texture_size,
                     //brighten: if self.hover { 0.15f32 } else { 0.0f32 },
                     brighten: 0.0f32,
@@ -162,6 +206,13 @@ impl Widget for Label {
                     )
                     .unwrap();
             }
+            borrowed.text_cache.draw(
+                target,
+                context,
+                &borrowed.text,
+                &borrowed.text_style,
+                borrowed.drawn_bounds,
+            )?;
         }
         Ok(NextUpdate::Latest)
     }
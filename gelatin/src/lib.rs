@@ -24,6 +24,7 @@ pub mod misc;
 pub mod picture;
 pub mod shaders;
 pub mod slider;
+pub mod text;
 pub mod window;
 
 #[derive(Debug)]
@@ -378,6 +379,8 @@ pub struct DrawContext<'a> {
     pub textured_program: &'a Program,
     pub colored_shadowed_program: &'a Program,
     pub colored_program: &'a Program,
+    pub text_program: &'a Program,
+    pub glyph_atlas: &'a std::cell::RefCell<text::GlyphAtlas>,
     pub viewport: &'a Rect,
     pub projection_transform: &'a Matrix4<f32>,
 }
@@ -13,3 +13,6 @@ pub static COLOR_SHADOW_F_140: &str =
 
 pub static COLOR_F_110: &str = include_str!("shaders/color_f_110.glsl");
 pub static COLOR_F_140: &str = include_str!("shaders/color_f_140.glsl");
+
+pub static TEXT_F_110: &str = include_str!("shaders/text_f_110.glsl");
+pub static TEXT_F_140: &str = include_str!("shaders/text_f_140.glsl");
@@ -0,0 +1,9 @@
+#version 110
+uniform sampler2D tex;
+uniform vec4 color;
+varying vec2 v_tex_coords;
+
+void main() {
+    float coverage = texture2D(tex, v_tex_coords).a;
+    gl_FragColor = vec4(color.rgb, color.a * coverage);
+}
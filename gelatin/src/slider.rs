@@ -22,6 +22,8 @@ struct SliderData {
     value: u32,
     click: bool,
     hover: bool,
+    /// The horizontal position of the cursor in logical pixels
+    cursor_x: f32,
     on_value_change: Option<Rc<dyn Fn()>>,
     shadow_color: [f32; 3],
 
@@ -42,6 +44,18 @@ impl WidgetData for SliderData {
     }
 }
 
+impl SliderData {
+    /// The value that corresponds to the horizontal position `x`
+    fn value_at(&self, x: f32) -> u32 {
+        let relative_cursor_x = x - self.drawn_bounds.pos.vec.x;
+        let proportion = (relative_cursor_x / self.drawn_bounds.size.vec.x)
+            .max(0.0)
+            .min(1.0);
+        let stepsf = self.steps as f32;
+        (proportion * (1.0 + 1.0 / stepsf) * (stepsf - 1.0)).floor() as u32
+    }
+}
+
 pub struct Slider {
     data: RefCell<SliderData>,
 }
@@ -59,6 +73,7 @@ impl Slider {
                 value: 0,
                 click: false,
                 hover: false,
+                cursor_x: 0.0,
                 on_value_change: None,
                 shadow_color: [0.0, 0.0, 0.0],
                 render_validity: Default::default(),
@@ -86,6 +101,28 @@ impl Slider {
         }
     }
 
+    /// The value under the cursor while the cursor is over the slider or the
+    /// slider is being dragged
+    pub fn hovered_value(&self) -> Option<u32> {
+        let borrowed = self.data.borrow();
+        if borrowed.visible && (borrowed.hover || borrowed.click) {
+            Some(borrowed.value_at(borrowed.cursor_x))
+        } else {
+            None
+        }
+    }
+
+    /// The point at the top of the slider where the line of `value` is drawn
+    pub fn value_position(&self, value: u32) -> LogicalVector {
+        let borrowed = self.data.borrow();
+        let bounds = borrowed.drawn_bounds;
+        let value_ratio = (value as f32 + 0.5) / (borrowed.steps as f32);
+        LogicalVector::new(
+            bounds.pos.vec.x + value_ratio * bounds.size.vec.x,
+            bounds.pos.vec.y,
+        )
+    }
+
     pub fn set_value(&self, value: u32) {
         let mut borrowed = self.data.borrow_mut();
         borrowed.value = value;
@@ -216,18 +253,10 @@ impl Widget for Slider {
                 let mut borrowed = self.data.borrow_mut();
                 borrowed.hover =
                     borrowed.drawn_bounds.contains(event.cursor_pos);
+                borrowed.cursor_x = event.cursor_pos.vec.x;
                 if borrowed.click {
                     let prev_value = borrowed.value;
-                    let relative_cursor_x = event.cursor_pos.vec.x
-                        - borrowed.drawn_bounds.pos.vec.x;
-                    let proportion = (relative_cursor_x
-                        / borrowed.drawn_bounds.size.vec.x)
-                        .max(0.0)
-                        .min(1.0);
-                    let stepsf = borrowed.steps as f32;
-                    borrowed.value =
-                        (proportion * (1.0 + 1.0 / stepsf) * (stepsf - 1.0))
-                            .floor() as u32;
+                    borrowed.value = borrowed.value_at(borrowed.cursor_x);
                     if borrowed.value != prev_value {
                         borrowed.render_validity.invalidate();
                         on_value_change = borrowed.on_value_change.clone();
@@ -0,0 +1,755 @@
+//! Glyph rendering for widgets that display text.
+//!
+//! Fonts are rasterized on demand at the physical pixel size of the window (the
+//! logical font size multiplied by the dpi scale factor) and the resulting glyph
+//! bitmaps are packed into a single atlas texture that is owned by the window.
+//! A piece of text is then drawn with a single draw call, where each glyph is a
+//! quad sampling its own region of the atlas.
+//!
+//! Before rasterization, each line of text is split into runs of characters
+//! that are found in the same font face, and the runs are shaped with
+//! rustybuzz. This takes care of kerning, ligatures and combining marks.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fmt, fs, io,
+    path::Path,
+    rc::Rc,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use ab_glyph::{
+    Font as _, FontArc, Glyph, GlyphId, PxScale, PxScaleFont, ScaleFont,
+};
+use glium::{
+    index::PrimitiveType,
+    texture::{
+        ClientFormat, MipmapsOption, RawImage2d, Texture2d,
+        UncompressedFloatFormat,
+    },
+    uniform, Frame, IndexBuffer, Surface, VertexBuffer,
+};
+
+use crate::{
+    misc::{Alignment, LogicalRect, LogicalVector},
+    DrawContext, Vertex, WidgetError,
+};
+
+/// The width and the height of the glyph atlas texture in physical pixels
+const ATLAS_SIZE: u32 = 1024;
+
+/// The number of horizontal sub-pixel positions that a glyph may be rasterized at
+const SUBPIXEL_STEPS: f32 = 4.0;
+
+static NEXT_FONT_ID: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static DEFAULT_FONT: RefCell<Option<Font>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    InvalidFont(ab_glyph::InvalidFont),
+}
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(err) => write!(f, "FontError: Io ({})", err),
+            FontError::InvalidFont(err) => {
+                write!(f, "FontError: InvalidFont ({})", err)
+            }
+        }
+    }
+}
+impl Error for FontError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FontError::Io(err) => Some(err),
+            FontError::InvalidFont(err) => Some(err),
+        }
+    }
+}
+impl From<io::Error> for FontError {
+    fn from(err: io::Error) -> FontError {
+        FontError::Io(err)
+    }
+}
+impl From<ab_glyph::InvalidFont> for FontError {
+    fn from(err: ab_glyph::InvalidFont) -> FontError {
+        FontError::InvalidFont(err)
+    }
+}
+
+/// A single font face parsed both for rasterization and for shaping. The
+/// shaper is `None` if rustybuzz can't read the font.
+#[derive(Clone)]
+struct Face {
+    glyphs: FontArc,
+    shaper: Option<Rc<rustybuzz::Face<'static>>>,
+}
+
+impl Face {
+    fn new(data: &'static [u8]) -> Result<Face, FontError> {
+        Ok(Face {
+            glyphs: FontArc::try_from_slice(data)?,
+            shaper: rustybuzz::Face::from_slice(data, 0).map(Rc::new),
+        })
+    }
+}
+
+/// A list of font faces. When a character is not found in the first face, the
+/// following faces are searched in order.
+///
+/// Cloning a `Font` is cheap, the clones share the same glyph atlas entries.
+#[derive(Clone)]
+pub struct Font {
+    id: u32,
+    faces: Rc<Vec<Face>>,
+}
+
+impl Font {
+    pub fn from_static_bytes(data: &'static [u8]) -> Result<Font, FontError> {
+        Ok(Self::from_face(Face::new(data)?))
+    }
+
+    /// The contents of the file are kept in memory until the program exits,
+    /// so this is meant for fonts that are loaded once at startup.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Font, FontError> {
+        let data = Box::leak(fs::read(path)?.into_boxed_slice());
+        Ok(Self::from_face(Face::new(data)?))
+    }
+
+    /// Returns a font that uses the faces of `fallback` for characters that
+    /// are missing from `self`
+    pub fn with_fallback(&self, fallback: &Font) -> Font {
+        let mut faces = (*self.faces).clone();
+        faces.extend(fallback.faces.iter().cloned());
+        Font {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            faces: Rc::new(faces),
+        }
+    }
+
+    /// Sets the font used by widgets that don't have a font specified for them.
+    ///
+    /// The default font is stored per thread, so this has to be called from
+    /// the thread running the event loop.
+    pub fn set_default(font: Option<Font>) {
+        DEFAULT_FONT.with(|default| *default.borrow_mut() = font);
+    }
+
+    pub fn default_font() -> Option<Font> {
+        DEFAULT_FONT.with(|default| default.borrow().clone())
+    }
+
+    /// Returns the size of the area that `text` occupies when drawn with the
+    /// given font size. Both the `font_size` and the returned value are in
+    /// logical pixels.
+    pub fn measure(&self, text: &str, font_size: f32) -> LogicalVector {
+        let layout = self.layout(text, font_size);
+        LogicalVector::new(layout.width, layout.height)
+    }
+
+    fn from_face(face: Face) -> Font {
+        Font {
+            id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
+            faces: Rc::new(vec![face]),
+        }
+    }
+
+    /// Returns the index of the first face that has a glyph for `ch`, or the
+    /// primary face if none of them have it.
+    fn face_for_char(&self, ch: char) -> usize {
+        self.faces
+            .iter()
+            .position(|face| face.glyphs.glyph_id(ch).0 != 0)
+            .unwrap_or(0)
+    }
+
+    /// Positions the glyphs of `text` relative to the top left corner of the
+    /// text. The pixel size is in whatever unit the caller wants the result in.
+    fn layout(&self, text: &str, px_size: f32) -> TextLayout {
+        let scale = PxScale::from(px_size);
+        let primary = self.faces[0].glyphs.as_scaled(scale);
+        let line_height =
+            primary.ascent() - primary.descent() + primary.line_gap();
+
+        let mut glyphs = Vec::with_capacity(text.len());
+        let mut lines = Vec::new();
+        let mut width = 0.0f32;
+        let mut baseline = primary.ascent();
+        let mut line_start = 0;
+        for (line_index, line) in text.split('\n').enumerate() {
+            if line_index > 0 {
+                baseline += line_height;
+            }
+            // Consecutive characters that are found in the same face are
+            // shaped together
+            let mut runs: Vec<(usize, String)> = Vec::new();
+            for ch in line.chars().filter(|ch| !ch.is_control()) {
+                let face_index = self.face_for_char(ch);
+                match runs.last_mut() {
+                    Some((run_face, run)) if *run_face == face_index => {
+                        run.push(ch)
+                    }
+                    _ => runs.push((face_index, ch.to_string())),
+                }
+            }
+            let mut caret = 0.0f32;
+            for (face_index, run) in runs {
+                caret = self.shape_run(
+                    &run,
+                    face_index,
+                    scale,
+                    caret,
+                    baseline,
+                    &mut glyphs,
+                );
+            }
+            lines.push(LineRange {
+                glyphs: line_start..glyphs.len(),
+                width: caret,
+            });
+            line_start = glyphs.len();
+            width = width.max(caret);
+        }
+        TextLayout {
+            glyphs,
+            lines,
+            width,
+            height: baseline - primary.descent(),
+        }
+    }
+
+    /// Shapes a piece of text that only uses a single face and appends the
+    /// resulting glyphs starting from `caret`. Returns the position of the
+    /// caret after the last glyph.
+    fn shape_run(
+        &self,
+        run: &str,
+        face_index: usize,
+        scale: PxScale,
+        caret: f32,
+        baseline: f32,
+        glyphs: &mut Vec<LaidOutGlyph>,
+    ) -> f32 {
+        let face = &self.faces[face_index];
+        let scaled = face.glyphs.as_scaled(scale);
+        let shaper = match face.shaper.as_deref() {
+            Some(shaper) => shaper,
+            None => {
+                return place_unshaped(
+                    run, face_index, &scaled, caret, baseline, glyphs,
+                )
+            }
+        };
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(run);
+        buffer.guess_segment_properties();
+        let shaped = rustybuzz::shape(shaper, &[], buffer);
+
+        // The shaper works in font units, while ab_glyph's scale is relative
+        // to the height of the font and not to the units per em.
+        let (h_scale, v_scale) =
+            (scaled.h_scale_factor(), scaled.v_scale_factor());
+        let mut caret = caret;
+        for (info, pos) in
+            shaped.glyph_infos().iter().zip(shaped.glyph_positions())
+        {
+            glyphs.push(LaidOutGlyph {
+                face: face_index,
+                id: GlyphId(info.glyph_id as u16),
+                x: caret + pos.x_offset as f32 * h_scale,
+                y: baseline - pos.y_offset as f32 * v_scale,
+            });
+            caret += pos.x_advance as f32 * h_scale;
+        }
+        caret
+    }
+}
+
+/// Places the glyphs of `run` next to each other using only the advance of
+/// each glyph. This is only used if the shaper can't read the font.
+fn place_unshaped(
+    run: &str,
+    face_index: usize,
+    face: &PxScaleFont<&FontArc>,
+    mut caret: f32,
+    baseline: f32,
+    glyphs: &mut Vec<LaidOutGlyph>,
+) -> f32 {
+    for ch in run.chars() {
+        let id = face.glyph_id(ch);
+        glyphs.push(LaidOutGlyph {
+            face: face_index,
+            id,
+            x: caret,
+            y: baseline,
+        });
+        caret += face.h_advance(id);
+    }
+    caret
+}
+
+struct LaidOutGlyph {
+    face: usize,
+    id: GlyphId,
+    /// The position of the glyph's origin relative to the top left corner of
+    /// the text
+    x: f32,
+    y: f32,
+}
+
+struct LineRange {
+    glyphs: std::ops::Range<usize>,
+    width: f32,
+}
+
+struct TextLayout {
+    glyphs: Vec<LaidOutGlyph>,
+    lines: Vec<LineRange>,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font_id: u32,
+    face: usize,
+    glyph_id: u16,
+    /// The pixel size multiplied by 64 to allow for fractional sizes
+    px_size: u32,
+    subpixel_x: u8,
+}
+
+#[derive(Clone, Copy)]
+struct AtlasEntry {
+    /// `None` for glyphs that don't have any visible pixels, like the space.
+    region: Option<AtlasRegion>,
+}
+
+#[derive(Clone, Copy)]
+struct AtlasRegion {
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    /// The offset of the top left corner of the glyph bitmap from the glyph's
+    /// origin on the baseline, in physical pixels.
+    offset_x: f32,
+    offset_y: f32,
+}
+
+/// A texture holding the rasterized glyphs of every text drawn in a window.
+///
+/// Glyphs are packed in rows ("shelves"). When the atlas is full it's simply
+/// cleared and the glyphs needed for the current frame are rasterized again.
+pub struct GlyphAtlas {
+    texture: Option<Texture2d>,
+    entries: HashMap<GlyphKey, AtlasEntry>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+}
+
+impl GlyphAtlas {
+    pub fn new() -> GlyphAtlas {
+        GlyphAtlas {
+            texture: None,
+            entries: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.shelf_x = 0;
+        self.shelf_y = 0;
+        self.shelf_height = 0;
+    }
+
+    fn texture(
+        &mut self,
+        display: &glium::Display,
+    ) -> Result<&Texture2d, WidgetError> {
+        if self.texture.is_none() {
+            let texture = Texture2d::empty_with_format(
+                display,
+                UncompressedFloatFormat::U8U8U8U8,
+                MipmapsOption::NoMipmap,
+                ATLAS_SIZE,
+                ATLAS_SIZE,
+            )
+            .map_err(|e| WidgetError::Custom(Box::new(e)))?;
+            self.texture = Some(texture);
+        }
+        Ok(self.texture.as_ref().unwrap())
+    }
+
+    /// Returns `None` if the glyph doesn't fit into the remaining space of the atlas
+    fn insert(
+        &mut self,
+        display: &glium::Display,
+        font: &Font,
+        key: GlyphKey,
+    ) -> Result<Option<AtlasEntry>, WidgetError> {
+        if let Some(entry) = self.entries.get(&key) {
+            return Ok(Some(*entry));
+        }
+        let px_size = key.px_size as f32 / 64.0;
+        let subpixel_x = key.subpixel_x as f32 / SUBPIXEL_STEPS;
+        let glyph = Glyph {
+            id: GlyphId(key.glyph_id),
+            scale: PxScale::from(px_size),
+            position: ab_glyph::point(subpixel_x, 0.0),
+        };
+        let outlined = match font.faces[key.face].glyphs.outline_glyph(glyph) {
+            Some(outlined) => outlined,
+            None => {
+                let entry = AtlasEntry { region: None };
+                self.entries.insert(key, entry);
+                return Ok(Some(entry));
+            }
+        };
+        let bounds = outlined.px_bounds();
+        // Leave a transparent border around each glyph so that neighbouring
+        // glyphs never bleed into each other.
+        const PADDING: u32 = 1;
+        let glyph_w = bounds.width() as u32;
+        let glyph_h = bounds.height() as u32;
+        let cell_w = glyph_w + 2 * PADDING;
+        let cell_h = glyph_h + 2 * PADDING;
+        if cell_w > ATLAS_SIZE || cell_h > ATLAS_SIZE {
+            return Ok(None);
+        }
+        if self.shelf_x + cell_w > ATLAS_SIZE {
+            self.shelf_x = 0;
+            self.shelf_y += self.shelf_height;
+            self.shelf_height = 0;
+        }
+        if self.shelf_y + cell_h > ATLAS_SIZE {
+            return Ok(None);
+        }
+
+        let mut pixels = vec![0u8; (cell_w * cell_h * 4) as usize];
+        outlined.draw(|x, y, coverage| {
+            let px = (((y + PADDING) * cell_w + x + PADDING) * 4) as usize;
+            pixels[px] = 255;
+            pixels[px + 1] = 255;
+            pixels[px + 2] = 255;
+            pixels[px + 3] = (coverage.min(1.0) * 255.0).round() as u8;
+        });
+        let (x, y) = (self.shelf_x, self.shelf_y);
+        let raw_image = RawImage2d {
+            data: Cow::Owned(pixels),
+            width: cell_w,
+            height: cell_h,
+            format: ClientFormat::U8U8U8U8,
+        };
+        self.texture(display)?.write(
+            glium::Rect {
+                left: x,
+                bottom: y,
+                width: cell_w,
+                height: cell_h,
+            },
+            raw_image,
+        );
+        self.shelf_x += cell_w;
+        self.shelf_height = self.shelf_height.max(cell_h);
+
+        let entry = AtlasEntry {
+            region: Some(AtlasRegion {
+                x: x + PADDING,
+                y: y + PADDING,
+                w: glyph_w,
+                h: glyph_h,
+                offset_x: bounds.min.x - subpixel_x,
+                offset_y: bounds.min.y,
+            }),
+        };
+        self.entries.insert(key, entry);
+        Ok(Some(entry))
+    }
+}
+
+impl Default for GlyphAtlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Describes how a piece of text should be drawn.
+#[derive(Clone)]
+pub struct TextStyle {
+    /// When `None`, the default font is used. See `Font::set_default`
+    pub font: Option<Font>,
+    /// The height of the font in logical pixels
+    pub font_size: f32,
+    pub color: [f32; 4],
+    pub horizontal_align: Alignment,
+    pub vertical_align: Alignment,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            font: None,
+            font_size: 14.0,
+            color: [0.0, 0.0, 0.0, 1.0],
+            horizontal_align: Alignment::Center,
+            vertical_align: Alignment::Center,
+        }
+    }
+}
+
+/// Remembers the layout of the text that a widget drew last, so that the text
+/// doesn't have to be shaped again on every frame.
+///
+/// The owner has to call `invalidate` when the text changes. A different font
+/// or pixel size (for example after a dpi change) is detected automatically.
+#[derive(Default)]
+pub struct TextCache {
+    entry: RefCell<Option<CachedLayout>>,
+}
+
+struct CachedLayout {
+    font_id: u32,
+    /// The pixel size multiplied by 64, like in `GlyphKey`
+    px_size: u32,
+    layout: Rc<TextLayout>,
+}
+
+impl TextCache {
+    pub fn new() -> TextCache {
+        Default::default()
+    }
+
+    pub fn invalidate(&self) {
+        *self.entry.borrow_mut() = None;
+    }
+
+    /// Same as `draw_text` but reuses the layout from the previous call if
+    /// nothing changed since.
+    pub fn draw(
+        &self,
+        target: &mut Frame,
+        context: &DrawContext,
+        text: &str,
+        style: &TextStyle,
+        bounds: LogicalRect,
+    ) -> Result<(), WidgetError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let font = match style.font.clone().or_else(Font::default_font) {
+            Some(font) => font,
+            None => return Ok(()),
+        };
+        let px_size = style.font_size * context.dpi_scale_factor;
+        let key = (font.id, (px_size * 64.0).round() as u32);
+        let layout = {
+            let mut entry = self.entry.borrow_mut();
+            match entry.as_ref() {
+                Some(cached) if (cached.font_id, cached.px_size) == key => {
+                    cached.layout.clone()
+                }
+                _ => {
+                    let layout = Rc::new(font.layout(text, px_size));
+                    *entry = Some(CachedLayout {
+                        font_id: key.0,
+                        px_size: key.1,
+                        layout: layout.clone(),
+                    });
+                    layout
+                }
+            }
+        };
+        draw_layout(target, context, &font, px_size, &layout, style, bounds)
+    }
+}
+
+/// Draws `text` within `bounds` according to `style`.
+///
+/// The text is not clipped to `bounds` it's only used for aligning the text.
+pub fn draw_text(
+    target: &mut Frame,
+    context: &DrawContext,
+    text: &str,
+    style: &TextStyle,
+    bounds: LogicalRect,
+) -> Result<(), WidgetError> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    let font = match style.font.clone().or_else(Font::default_font) {
+        Some(font) => font,
+        None => return Ok(()),
+    };
+    let px_size = style.font_size * context.dpi_scale_factor;
+    let layout = font.layout(text, px_size);
+    draw_layout(target, context, &font, px_size, &layout, style, bounds)
+}
+
+fn draw_layout(
+    target: &mut Frame,
+    context: &DrawContext,
+    font: &Font,
+    px_size: f32,
+    layout: &TextLayout,
+    style: &TextStyle,
+    bounds: LogicalRect,
+) -> Result<(), WidgetError> {
+    use glium::{Blend, BlendingFunction, LinearBlendingFactor};
+
+    let dpi_scale = context.dpi_scale_factor;
+    let bounds = bounds.align_to_pixels(dpi_scale);
+    let phys_pos = bounds.pos * dpi_scale;
+    let phys_size = bounds.size * dpi_scale;
+    let text_top = match style.vertical_align {
+        Alignment::Start => phys_pos.vec.y,
+        Alignment::Center => {
+            phys_pos.vec.y + (phys_size.vec.y - layout.height) * 0.5
+        }
+        Alignment::End => phys_pos.vec.y + phys_size.vec.y - layout.height,
+    };
+
+    let mut atlas = context.glyph_atlas.borrow_mut();
+    let mut vertices = Vec::with_capacity(layout.glyphs.len() * 4);
+    let mut indices = Vec::with_capacity(layout.glyphs.len() * 6);
+    // If the atlas runs out of space while preparing this text, everything is
+    // evicted and the preparation is restarted once.
+    let mut attempts_left = 2;
+    'attempt: while attempts_left > 0 {
+        attempts_left -= 1;
+        vertices.clear();
+        indices.clear();
+        for line in layout.lines.iter() {
+            let line_left = match style.horizontal_align {
+                Alignment::Start => phys_pos.vec.x,
+                Alignment::Center => {
+                    phys_pos.vec.x + (phys_size.vec.x - line.width) * 0.5
+                }
+                Alignment::End => phys_pos.vec.x + phys_size.vec.x - line.width,
+            };
+            for glyph in &layout.glyphs[line.glyphs.clone()] {
+                let x = line_left + glyph.x;
+                let subpixel_x = ((x.fract() * SUBPIXEL_STEPS).floor() as u8)
+                    .min(SUBPIXEL_STEPS as u8 - 1);
+                let key = GlyphKey {
+                    font_id: font.id,
+                    face: glyph.face,
+                    glyph_id: glyph.id.0,
+                    px_size: (px_size * 64.0).round() as u32,
+                    subpixel_x,
+                };
+                let entry = match atlas.insert(context.display, font, key)? {
+                    Some(entry) => entry,
+                    None if attempts_left > 0 => {
+                        atlas.clear();
+                        continue 'attempt;
+                    }
+                    None => continue,
+                };
+                let region = match entry.region {
+                    Some(region) => region,
+                    None => continue,
+                };
+                let left = (x.floor()
+                    + subpixel_x as f32 / SUBPIXEL_STEPS
+                    + region.offset_x)
+                    .round();
+                let top = (text_top + glyph.y).round() + region.offset_y;
+                let (right, bottom) =
+                    (left + region.w as f32, top + region.h as f32);
+                let atlas_size = ATLAS_SIZE as f32;
+                let (u0, v0) = (
+                    region.x as f32 / atlas_size,
+                    region.y as f32 / atlas_size,
+                );
+                let (u1, v1) = (
+                    (region.x + region.w) as f32 / atlas_size,
+                    (region.y + region.h) as f32 / atlas_size,
+                );
+                let first = vertices.len() as u32;
+                let quad = [
+                    ([left, top], [u0, v0]),
+                    ([left, bottom], [u0, v1]),
+                    ([right, bottom], [u1, v1]),
+                    ([right, top], [u1, v0]),
+                ];
+                for (position, tex_coords) in quad {
+                    vertices.push(Vertex {
+                        position: [
+                            position[0] / dpi_scale,
+                            position[1] / dpi_scale,
+                        ],
+                        tex_coords,
+                    });
+                }
+                indices.extend_from_slice(&[
+                    first,
+                    first + 1,
+                    first + 2,
+                    first,
+                    first + 2,
+                    first + 3,
+                ]);
+            }
+        }
+        break;
+    }
+    if vertices.is_empty() {
+        return Ok(());
+    }
+
+    let vertex_buffer = VertexBuffer::new(context.display, &vertices)
+        .map_err(|e| WidgetError::Custom(Box::new(e)))?;
+    let index_buffer = IndexBuffer::new(
+        context.display,
+        PrimitiveType::TrianglesList,
+        &indices,
+    )
+    .map_err(|e| WidgetError::Custom(Box::new(e)))?;
+
+    // The vertices are already in logical window coordinates
+    let transform = *context.projection_transform;
+    let texture = atlas.texture(context.display)?;
+    let sampler = texture
+        .sampled()
+        .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp)
+        .minify_filter(glium::uniforms::MinifySamplerFilter::Nearest)
+        .magnify_filter(glium::uniforms::MagnifySamplerFilter::Nearest);
+    let uniforms = uniform! {
+        matrix: Into::<[[f32; 4]; 4]>::into(transform),
+        tex: sampler,
+        color: style.color,
+    };
+    let draw_params = glium::DrawParameters {
+        viewport: Some(*context.viewport),
+        blend: Blend {
+            color: BlendingFunction::Addition {
+                source: LinearBlendingFactor::SourceAlpha,
+                destination: LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    target
+        .draw(
+            &vertex_buffer,
+            &index_buffer,
+            context.text_program,
+            &uniforms,
+            &draw_params,
+        )
+        .unwrap();
+    Ok(())
+}
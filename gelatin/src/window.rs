@@ -23,7 +23,9 @@ use typed_builder::TypedBuilder;
 use crate::{
     application::Application,
     misc::{FromPhysical, LogicalRect, LogicalVector},
-    shaders, DrawContext, Event, EventKind, NextUpdate, Vertex, Widget,
+    shaders,
+    text::GlyphAtlas,
+    DrawContext, Event, EventKind, NextUpdate, Vertex, Widget,
 };
 
 const EVENT_UPDATE_DELTA: std::time::Duration =
@@ -109,6 +111,8 @@ struct WindowData {
     textured_program: Program,
     colored_shadowed_program: Program,
     colored_program: Program,
+    text_program: Program,
+    glyph_atlas: RefCell<GlyphAtlas>,
 }
 
 pub struct Window {
@@ -228,6 +232,17 @@ impl Window {
             },
         )
         .unwrap();
+        let text_program = program!(&display,
+            140 => {
+                vertex: shaders::VERTEX_140,
+                fragment: shaders::TEXT_F_140
+            },
+            110 => {
+                vertex: shaders::VERTEX_110,
+                fragment: shaders::TEXT_F_110
+            },
+        )
+        .unwrap();
 
         let resulting_window = Rc::new(Window {
             data: RefCell::new(WindowData {
@@ -257,6 +272,8 @@ impl Window {
                 textured_program,
                 colored_shadowed_program,
                 colored_program,
+                text_program,
+                glyph_atlas: RefCell::new(GlyphAtlas::new()),
             }),
         });
 
@@ -534,6 +551,8 @@ impl Window {
             textured_program: &borrowed.textured_program,
             colored_shadowed_program: &borrowed.colored_shadowed_program,
             colored_program: &borrowed.colored_program,
+            text_program: &borrowed.text_program,
+            glyph_atlas: &borrowed.glyph_atlas,
             viewport: &viewport,
             projection_transform: &projection_transform,
         };
@@ -591,6 +610,14 @@ impl Window {
             .set_maximized(maximized);
     }
 
+    /// Restores the window if it's minimized and brings it to the front.
+    pub fn focus(&self) {
+        let display = self.display_mut();
+        let gl_win = display.gl_window();
+        gl_win.window().set_minimized(false);
+        gl_win.window().focus_window();
+    }
+
     /// Sets the alpha values by drawing a quad covering the entire framebuffer
     /// with a blending mode set to max and a shader that draws (0,0,0,1) values
     fn set_alpha_to_1(&self, target: &mut Frame, context: &DrawContext) {